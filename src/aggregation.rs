@@ -0,0 +1,327 @@
+//! Recursive aggregation of state transition proofs
+//!
+//! This module folds a chain of `StateTransitionCircuit` proofs into a single
+//! constant-size proof attesting that a channel moved from a start state hash
+//! to an end state hash in a given number of valid steps.
+//!
+//! Proofs are combined pairwise in a binary tree. Every level has its own
+//! circuit, which verifies two proofs of the level below against that
+//! circuit's fixed verifier data, checks that both are bound to the same
+//! channel and that the first proof ends where the second starts, and adds up
+//! their step counts. An odd proof out at a level
+//! is paired with a copy of itself flagged as absent, so it is carried up
+//! without being counted twice.
+//!
+//! Only the state hashes of leaf proofs are carried up; their transition data
+//! digests are not aggregated.
+//!
+//! Public inputs of every aggregation proof are
+//! `[start state hash (4), end state hash (4), steps, channel id limbs (8)]`.
+
+use anyhow::{anyhow, Context, Result};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, CircuitData, CommonCircuitData, VerifierOnlyCircuitData,
+};
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+
+use crate::channel::ChannelState;
+use crate::codec::{elements_to_bytes, field_to_u64, limbs_to_bytes, BYTES32_LIMBS};
+use crate::state::hash_state;
+use crate::state_transition::StateTransitionCircuit;
+use crate::types::Bytes32;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Index of the step count in an aggregation proof's public inputs.
+const STEPS_INDEX: usize = 8;
+
+/// Index of the first channel id limb in an aggregation proof's public inputs.
+const CHANNEL_ID_INDEX: usize = 9;
+
+/// Index of the first channel id limb in a leaf proof's public inputs.
+const LEAF_CHANNEL_ID_INDEX: usize = 12;
+
+/// Proof that a channel reached `end_hash` from `start_hash` in `steps`
+/// valid transitions.
+#[derive(Debug, Clone)]
+pub struct AggregatedProof {
+    /// Level of the aggregation tree the proof was produced at, starting at 1
+    pub level: usize,
+    /// The recursive proof
+    pub proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl AggregatedProof {
+    /// Hash of the state the first aggregated transition started from.
+    pub fn start_hash(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[0..4]) }
+
+    /// Hash of the state the last aggregated transition ended in.
+    pub fn end_hash(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[4..8]) }
+
+    /// Number of transitions covered by the proof.
+    pub fn steps(&self) -> u64 { field_to_u64(self.proof.public_inputs[STEPS_INDEX]) }
+
+    /// Id of the channel every aggregated transition is bound to.
+    pub fn channel_id(&self) -> Result<Bytes32> {
+        let limbs = self
+            .proof
+            .public_inputs
+            .get(CHANNEL_ID_INDEX..CHANNEL_ID_INDEX + BYTES32_LIMBS)
+            .and_then(|limbs| <&[F; BYTES32_LIMBS]>::try_from(limbs).ok())
+            .ok_or_else(|| anyhow!("Unexpected number of public inputs"))?;
+        limbs_to_bytes(limbs).context("Invalid channel id limbs")
+    }
+}
+
+/// Circuit verifying two proofs of the level below.
+struct AggregationCircuit {
+    circuit_data: CircuitData<F, C, D>,
+    left_target: ProofWithPublicInputsTarget<D>,
+    right_target: ProofWithPublicInputsTarget<D>,
+    right_present_target: BoolTarget,
+}
+
+impl AggregationCircuit {
+    /// Builds the circuit for proofs of the inner circuit described by
+    /// `inner_common` and `inner_verifier`. Leaf proofs count as one step each.
+    fn new(
+        inner_common: &CommonCircuitData<F, D>,
+        inner_verifier: &VerifierOnlyCircuitData<C, D>,
+        inner_is_leaf: bool,
+    ) -> Self {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+
+        // Both children must be valid proofs of the inner circuit.
+        let inner_verifier_target = builder.constant_verifier_data(inner_verifier);
+        let left_target = builder.add_virtual_proof_with_pis(inner_common);
+        let right_target = builder.add_virtual_proof_with_pis(inner_common);
+        builder.verify_proof::<C>(&left_target, &inner_verifier_target, inner_common);
+        builder.verify_proof::<C>(&right_target, &inner_verifier_target, inner_common);
+        let right_present_target = builder.add_virtual_bool_target_safe();
+
+        let left = &left_target.public_inputs;
+        let right = &right_target.public_inputs;
+
+        // A present right child must start where the left child ends.
+        let mut end = Vec::with_capacity(4);
+        for i in 0..4 {
+            let gap = builder.sub(left[4 + i], right[i]);
+            let checked_gap = builder.mul(right_present_target.target, gap);
+            builder.assert_zero(checked_gap);
+            end.push(builder.select(right_present_target, right[4 + i], left[4 + i]));
+        }
+
+        // Add up the steps, ignoring an absent right child.
+        let (left_steps, right_steps, channel_id_index) = if inner_is_leaf {
+            let one = builder.one();
+            (one, one, LEAF_CHANNEL_ID_INDEX)
+        } else {
+            (left[STEPS_INDEX], right[STEPS_INDEX], CHANNEL_ID_INDEX)
+        };
+        let steps = builder.mul_add(right_present_target.target, right_steps, left_steps);
+
+        // Both children are bound to the same channel. An absent right child
+        // is a copy of the left one, so this holds for it too.
+        let channel_id = &left[channel_id_index..channel_id_index + BYTES32_LIMBS];
+        for (i, &limb) in channel_id.iter().enumerate() {
+            builder.connect(limb, right[channel_id_index + i]);
+        }
+
+        builder.register_public_inputs(&left[0..4]);
+        builder.register_public_inputs(&end);
+        builder.register_public_input(steps);
+        builder.register_public_inputs(channel_id);
+
+        let circuit_data = builder.build::<C>();
+
+        Self { circuit_data, left_target, right_target, right_present_target }
+    }
+
+    /// Proves the combination of `left` and, if given, `right`.
+    fn prove(
+        &self,
+        left: &ProofWithPublicInputs<F, C, D>,
+        right: Option<&ProofWithPublicInputs<F, C, D>>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        pw.set_proof_with_pis_target(&self.left_target, left)
+            .context("Failed to set left proof")?;
+        // An absent right child is filled with a copy of the left one.
+        pw.set_proof_with_pis_target(&self.right_target, right.unwrap_or(left))
+            .context("Failed to set right proof")?;
+        pw.set_bool_target(self.right_present_target, right.is_some())
+            .context("Failed to set right proof flag")?;
+
+        self.circuit_data.prove(pw).context("Aggregation proof generation failed")
+    }
+}
+
+/// Folds chains of `StateTransitionCircuit` proofs into a single proof.
+///
+/// Aggregation circuits are built on first use for each tree level and
+/// reused afterwards, both for proving and for verifying. Circuits depend only
+/// on the leaf circuit, so any aggregator over the same leaf circuit verifies
+/// the proofs of another.
+pub struct TransitionAggregator<'a> {
+    leaf_circuit: &'a StateTransitionCircuit,
+    levels: Vec<AggregationCircuit>,
+}
+
+impl<'a> TransitionAggregator<'a> {
+    /// Creates an aggregator for proofs of `leaf_circuit`.
+    pub fn new(leaf_circuit: &'a StateTransitionCircuit) -> Self {
+        Self { leaf_circuit, levels: Vec::new() }
+    }
+
+    /// Aggregates consecutive transition proofs of one channel, ordered from
+    /// the first transition to the last, into one proof.
+    pub fn aggregate(
+        &mut self,
+        proofs: Vec<ProofWithPublicInputs<F, C, D>>,
+    ) -> Result<AggregatedProof> {
+        if proofs.is_empty() {
+            return Err(anyhow!("No transition proofs to aggregate"));
+        }
+
+        // Reject broken chains early rather than failing inside the prover.
+        for (i, pair) in proofs.windows(2).enumerate() {
+            let (_, left_end) = StateTransitionCircuit::public_state_hashes(&pair[0])?;
+            let (right_start, _) = StateTransitionCircuit::public_state_hashes(&pair[1])?;
+            if left_end != right_start {
+                return Err(anyhow!("Transition {} does not follow transition {i}", i + 1));
+            }
+            if StateTransitionCircuit::public_channel_id(&pair[0])?
+                != StateTransitionCircuit::public_channel_id(&pair[1])?
+            {
+                return Err(anyhow!("Transition {} belongs to another channel", i + 1));
+            }
+        }
+
+        let mut level = 0;
+        let mut current = proofs;
+        while level == 0 || current.len() > 1 {
+            let circuit = self.level_circuit(level + 1);
+            current = current
+                .chunks(2)
+                .map(|pair| circuit.prove(&pair[0], pair.get(1)))
+                .collect::<Result<Vec<_>>>()?;
+            level += 1;
+        }
+
+        let proof = current.pop().ok_or_else(|| anyhow!("Aggregation produced no proof"))?;
+        Ok(AggregatedProof { level, proof })
+    }
+
+    /// Verifies an aggregated proof, building the circuit of its level if
+    /// needed.
+    pub fn verify(&mut self, aggregated: &AggregatedProof) -> Result<()> {
+        // A tree over at most `usize::MAX` proofs has no more levels than this
+        if aggregated.level == 0 || aggregated.level > usize::BITS as usize {
+            return Err(anyhow!("Unknown aggregation level {}", aggregated.level));
+        }
+        self.level_circuit(aggregated.level)
+            .circuit_data
+            .verify(aggregated.proof.clone())
+            .context("Aggregated proof verification failed")
+    }
+
+    /// Verifies an aggregated proof and checks that it proves `start` reaching
+    /// `end` in `steps` transitions of channel `channel_id`.
+    pub fn verify_chain(
+        &mut self,
+        aggregated: &AggregatedProof,
+        channel_id: &Bytes32,
+        start: &ChannelState,
+        end: &ChannelState,
+        steps: u64,
+    ) -> Result<bool> {
+        if aggregated.start_hash() != hash_state(start)?
+            || aggregated.end_hash() != hash_state(end)?
+            || aggregated.steps() != steps
+            || aggregated.channel_id()? != *channel_id
+        {
+            return Ok(false);
+        }
+        self.verify(aggregated)?;
+        Ok(true)
+    }
+
+    /// Gets the aggregation circuit for `level`, building it and any missing
+    /// levels below it.
+    fn level_circuit(&mut self, level: usize) -> &AggregationCircuit {
+        while self.levels.len() < level {
+            let circuit = match self.levels.last() {
+                Some(inner) => AggregationCircuit::new(
+                    &inner.circuit_data.common,
+                    &inner.circuit_data.verifier_only,
+                    false,
+                ),
+                None => {
+                    let leaf_data = self.leaf_circuit.circuit_data();
+                    AggregationCircuit::new(&leaf_data.common, &leaf_data.verifier_only, true)
+                }
+            };
+            self.levels.push(circuit);
+        }
+        &self.levels[level - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::ChannelParticipants;
+    use crate::state_transition::{apply_transition, TransitionData};
+
+    #[test]
+    fn test_aggregate_transition_chain() -> Result<()> {
+        let circuit = StateTransitionCircuit::new();
+        let participants = ChannelParticipants { sender: [2u8; 32], receiver: [3u8; 32] };
+        let start = ChannelState::new(100, Vec::new(), participants)?;
+        let channel_id = [4u8; 32];
+
+        // Three transfers, so one level carries an unpaired proof
+        let mut states = vec![start.clone()];
+        let mut proofs = Vec::new();
+        for amount in [10, 20, 30] {
+            let state = &states[states.len() - 1];
+            proofs.push(circuit.prove_transfer(&channel_id, state, amount)?);
+            states.push(apply_transition(state, &TransitionData::transfer(amount))?);
+        }
+        let state = states[3].clone();
+
+        let mut aggregator = TransitionAggregator::new(&circuit);
+        let aggregated = aggregator.aggregate(proofs.clone())?;
+        assert_eq!(aggregated.level, 2);
+        assert_eq!(aggregated.steps(), 3);
+        assert_eq!(aggregated.channel_id()?, channel_id);
+        assert!(aggregator.verify_chain(&aggregated, &channel_id, &start, &state, 3)?);
+        assert!(!aggregator.verify_chain(&aggregated, &channel_id, &start, &state, 2)?);
+        assert!(!aggregator.verify_chain(&aggregated, &[5u8; 32], &start, &state, 3)?);
+
+        // A fresh aggregator verifies without having proven anything
+        let mut verifier = TransitionAggregator::new(&circuit);
+        assert!(verifier.verify_chain(&aggregated, &channel_id, &start, &state, 3)?);
+        let unknown = AggregatedProof { level: 0, ..aggregated.clone() };
+        assert!(verifier.verify(&unknown).is_err());
+        let mislabelled = AggregatedProof { level: 1, ..aggregated };
+        assert!(verifier.verify(&mislabelled).is_err());
+
+        // Proofs that do not form a chain of one channel are rejected
+        let mut foreign = proofs.clone();
+        foreign[2] = circuit.prove_transfer(&[5u8; 32], &states[2], 30)?;
+        assert!(aggregator.aggregate(foreign).is_err());
+        proofs.swap(0, 1);
+        assert!(aggregator.aggregate(proofs).is_err());
+        assert!(aggregator.aggregate(Vec::new()).is_err());
+        Ok(())
+    }
+}
@@ -5,20 +5,203 @@
 //! state channels.
 
 use anyhow::Result;
+use secp256k1::Keypair;
 use serde::{Deserialize, Serialize};
 
-use crate::commitments::generate_random_blinding;
-use crate::commitments::pedersen_commit;
+use crate::clock::Clock;
+use crate::commitments::ChannelCommitment;
+use crate::commitments::CommitmentOpening;
 use crate::error::ChannelError;
 use crate::merkle::compute_channel_root;
 use crate::pedersen_parameters::PedersenParameters;
-use crate::state::generate_state_proof;
-use crate::state::hash_state;
-use crate::state_proof;
+use crate::proof_system::{ProofSystem, ProofSystemId};
+use crate::range_proof::{ChannelRangeProof, RangeProofBinding};
+use crate::signing::{sign_digest, tagged_hash, verify_digest, x_only_public_key};
+use crate::codec::hash_out_to_bytes;
+use crate::state::{channel_aux_digest, hash_state};
+use crate::state_proof::StateProof;
 use crate::tree::MerkleTree;
 use crate::tree::MerkleTreeError;
 use crate::types::Bytes32;
 
+/// BIP340 tag for the digest signed for each channel state.
+const STATE_DIGEST_TAG: &[u8] = b"Overpass/ChannelState";
+
+/// Lifecycle stage of a channel.
+///
+/// Channels move forward through the stages; see `can_transition_to`
+/// for the allowed moves.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelLifecycle {
+    /// Funding transaction is not yet confirmed
+    Funding,
+    /// Channel accepts state updates
+    Open,
+    /// Cooperative or unilateral close is in progress
+    Closing,
+    /// A published state is being contested
+    Disputed,
+    /// Channel is settled and accepts no further updates
+    Closed,
+}
+
+impl ChannelLifecycle {
+    /// Returns whether a channel in this stage may move to `next`.
+    pub fn can_transition_to(self, next: ChannelLifecycle) -> bool {
+        use ChannelLifecycle::*;
+        matches!(
+            (self, next),
+            (Funding, Open)
+                | (Funding, Closed)
+                | (Open, Closing)
+                | (Open, Disputed)
+                | (Closing, Disputed)
+                | (Closing, Closed)
+                | (Disputed, Closed)
+        )
+    }
+
+    /// Numeric tag identifying the stage in state hashes.
+    pub fn tag(self) -> u8 {
+        match self {
+            ChannelLifecycle::Funding => 0,
+            ChannelLifecycle::Open => 1,
+            ChannelLifecycle::Closing => 2,
+            ChannelLifecycle::Disputed => 3,
+            ChannelLifecycle::Closed => 4,
+        }
+    }
+}
+
+/// Kind of update that produced a channel state.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ChannelTransition {
+    /// Initial state of the channel
+    Genesis,
+    /// Payment from the sender to the receiver
+    Transfer,
+    /// Sender top-up raising the channel capacity by `amount`, backed by
+    /// `funding`: the funding outpoint or a commitment to it
+    Deposit { amount: u64, funding: Bytes32 },
+    /// Final state returning the remaining sender balance after expiry
+    Refund,
+    /// Receiver withdrawal moving part of the receiver balance to `output`
+    Claim { output: SettlementOutput },
+    /// `count` payments from the sender to the receiver totalling `total`,
+    /// advancing the nonce by `count`
+    TransferBatch { count: u64, total: u64 },
+    /// Move to the state's `lifecycle` stage without moving funds
+    Lifecycle,
+    /// Cooperative close before expiry, settling the current balances
+    Close,
+}
+
+impl ChannelTransition {
+    /// Numeric tag identifying the transition kind in state hashes.
+    pub fn tag(&self) -> u8 {
+        match self {
+            ChannelTransition::Genesis => 0,
+            ChannelTransition::Transfer => 1,
+            ChannelTransition::Deposit { .. } => 2,
+            ChannelTransition::Refund => 3,
+            ChannelTransition::Claim { .. } => 4,
+            ChannelTransition::TransferBatch { .. } => 5,
+            ChannelTransition::Lifecycle => 6,
+            ChannelTransition::Close => 7,
+        }
+    }
+
+    /// Number of nonces consumed by this transition.
+    pub fn nonce_step(&self) -> u64 {
+        match self {
+            ChannelTransition::TransferBatch { count, .. } => *count,
+            _ => 1,
+        }
+    }
+
+    /// Public amount moved into or out of the channel by this transition.
+    pub fn amount(&self) -> u64 {
+        match self {
+            ChannelTransition::Deposit { amount, .. } => *amount,
+            ChannelTransition::Claim { output } => output.amount,
+            ChannelTransition::TransferBatch { total, .. } => *total,
+            _ => 0,
+        }
+    }
+}
+
+/// Funds withdrawn from a channel by a claim, to be paid out on settlement.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SettlementOutput {
+    /// Public key of the party receiving the funds
+    pub recipient: Bytes32,
+    /// Amount withdrawn from the channel
+    pub amount: u64,
+}
+
+/// Proof that a batch of transfers moved a channel from `start_nonce` to
+/// `end_nonce`, paying `total_amount` from the sender to the receiver.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BatchProof {
+    /// Channel the batch was applied to
+    pub channel_id: Bytes32,
+    /// Nonce of the state the batch was applied to
+    pub start_nonce: u64,
+    /// Nonce of the resulting state
+    pub end_nonce: u64,
+    /// Sum of all transfers in the batch
+    pub total_amount: u64,
+    /// Hash of the balance commitment before the batch
+    pub old_commitment: Bytes32,
+    /// Hash of the balance commitment after the batch
+    pub new_commitment: Bytes32,
+    /// Transition proof covering the whole batch
+    pub proof: Vec<u8>,
+}
+
+impl BatchProof {
+    /// Verifies that `next` is the result of this batch applied to `prior` in
+    /// channel `channel_id`, including the aggregated balance delta and the
+    /// `system` proof of the transition.
+    pub fn verify<P: ProofSystem>(
+        &self,
+        system: &P,
+        channel_id: &Bytes32,
+        prior: &ChannelState,
+        next: &ChannelState,
+    ) -> Result<(), ChannelError> {
+        let count = self
+            .end_nonce
+            .checked_sub(self.start_nonce)
+            .ok_or(ChannelError::BatchProofMismatch)?;
+        if next.transition != (ChannelTransition::TransferBatch { count, total: self.total_amount })
+            || self.channel_id != *channel_id
+            || prior.nonce != self.start_nonce
+            || next.nonce != self.end_nonce
+            || prior.generate_commitment().hash() != self.old_commitment
+            || next.generate_commitment().hash() != self.new_commitment
+            || next.proof.as_ref() != Some(&self.proof)
+        {
+            return Err(ChannelError::BatchProofMismatch);
+        }
+
+        next.verify_transition(prior, system.clock())?;
+        system
+            .deserialize(&self.proof)
+            .and_then(|proof| system.verify_transition(&proof, channel_id, Some(prior), next))
+            .map_err(|_| ChannelError::InvalidTransitionProof)
+    }
+}
+
+/// BIP340 x-only public keys of the channel participants.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelParticipants {
+    /// Public key of the sender, who signs transfers, deposits and refunds
+    pub sender: Bytes32,
+    /// Public key of the receiver, who signs claims
+    pub receiver: Bytes32,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ChannelState {
     /// Balance of the sender
@@ -31,6 +214,24 @@ pub struct ChannelState {
     pub nonce: u64,
     /// ZKP proof for state verification
     pub proof: Option<Vec<u8>>,
+    /// Pedersen commitment to the balances, including its blinding factor
+    pub commitment: Option<ChannelCommitment>,
+    /// Range proofs for the committed balances and transfer amount
+    pub range_proof: Option<ChannelRangeProof>,
+    /// Participant public keys bound into the channel
+    pub participants: ChannelParticipants,
+    /// BIP340 signature over `state_digest` by the key returned by `signer`
+    pub signature: Option<Vec<u8>>,
+    /// Lifecycle stage of the channel
+    pub lifecycle: ChannelLifecycle,
+    /// Unix timestamp (seconds) after which no further updates are accepted
+    /// and the sender may reclaim the remaining `sender_balance`
+    pub expiry: Option<u64>,
+    /// Kind of update that produced this state
+    pub transition: ChannelTransition,
+    /// Hash of an application-defined memo attached to the update
+    #[serde(default)]
+    pub memo: Option<Bytes32>,
 }
 
 impl ChannelState {
@@ -38,9 +239,16 @@ impl ChannelState {
     /// with the given initial `sender_balance` and `metadata`.
     /// The `receiver_balance` starts at 0 as a constructor invariant.
     /// `metadata` is the metadata for the channel.
+    /// `participants` are the public keys bound into every state of the channel.
+    /// The channel is considered funded and starts `Open`; use `new_funding`
+    /// when the funding transaction is still unconfirmed.
     ///
     /// Returns an error if the initial sender balance is zero.
-    pub fn new(sender_balance: u64, metadata: Vec<u8>) -> Result<Self, ChannelError> {
+    pub fn new(
+        sender_balance: u64,
+        metadata: Vec<u8>,
+        participants: ChannelParticipants,
+    ) -> Result<Self, ChannelError> {
         if sender_balance == 0 {
             return Err(ChannelError::InvalidZeroBalance);
         }
@@ -49,37 +257,229 @@ impl ChannelState {
         let params = PedersenParameters::default();
 
         // Compute Pedersen commitment for channel state
-        let blinding = generate_random_blinding();
         // receiver balance is 0 for initial state
-        let commitment = pedersen_commit(sender_balance, 0, blinding, &params);
-
-        // generate helper proof for the initial state
-        let helper_proof = generate_state_proof(
-            commitment, // Old commitment = initial state
-            commitment, // New commitment = same for initial state
-            commitment, // Merkle root = commitment for single channel
-            &params,
-        );
+        let commitment = ChannelCommitment::random(sender_balance, 0, &params);
 
-        let state_proof = state_proof::StateProof {
-            pi: helper_proof.pi,
-            public_inputs: helper_proof.public_inputs,
-            timestamp: helper_proof.timestamp,
-        };
-
-        let proof = Some(state_proof.pi.to_vec());
-
-        Ok(Self {
+        let mut state = Self {
             sender_balance,
             receiver_balance: 0,
             nonce: 0,
             metadata,
-            proof,
-        })
+            proof: None,
+            commitment: Some(commitment),
+            range_proof: None,
+            participants,
+            signature: None,
+            lifecycle: ChannelLifecycle::Open,
+            expiry: None,
+            transition: ChannelTransition::Genesis,
+            memo: None,
+        };
+        state.range_proof = Some(state.prove_ranges(None, 0)?);
+        state.proof = Some(state.genesis_proof()?);
+        Ok(state)
+    }
+
+    /// Sets the expiry of a newly created channel.
+    /// The expiry is committed into every state and cannot change afterwards.
+    ///
+    /// Returns an error if the range proofs cannot be rebound to the new
+    /// `channel_digest`.
+    pub fn with_expiry(mut self, expiry: u64) -> Result<Self, ChannelError> {
+        self.expiry = Some(expiry);
+        self.range_proof = Some(self.prove_ranges(None, 0)?);
+        self.proof = Some(self.genesis_proof()?);
+        Ok(self)
+    }
+
+    /// Serialized `ProofSystemId::Genesis` envelope over this state's hash.
+    /// Initial states follow no transition, so this stands in for the
+    /// transition proof every later state carries. Nothing checks its age,
+    /// so it is left unstamped and initial states do not depend on the time
+    /// they were created at.
+    fn genesis_proof(&self) -> Result<Vec<u8>, ChannelError> {
+        let state_hash = hash_state(self).map_err(|_| ChannelError::StateHashFailed)?;
+        StateProof::new(ProofSystemId::Genesis, Vec::new(), vec![state_hash], 0)
+            .to_bytes()
+            .map_err(|_| ChannelError::InvalidTransitionProof)
+    }
+
+    /// Verifies that this is a well-formed initial state, as created by `new`
+    /// or `new_funding`, holding its genesis envelope.
+    pub fn verify_genesis(&self) -> Result<(), ChannelError> {
+        if self.transition != ChannelTransition::Genesis
+            || self.nonce != 0
+            || self.receiver_balance != 0
+        {
+            return Err(ChannelError::InvalidTransitionKind);
+        }
+
+        let state_hash = hash_state(self).map_err(|_| ChannelError::StateHashFailed)?;
+        let proof = self
+            .proof
+            .as_deref()
+            .map(StateProof::from_bytes)
+            .and_then(Result::ok)
+            .ok_or(ChannelError::InvalidTransitionProof)?;
+        if proof.system != ProofSystemId::Genesis || proof.public_inputs != [state_hash] {
+            return Err(ChannelError::InvalidTransitionProof);
+        }
+
+        let commitment = self.commitment.as_ref().ok_or(ChannelError::CommitmentMismatch)?;
+        self.range_proof
+            .as_ref()
+            .ok_or(ChannelError::InvalidRangeProof)?
+            .verify(
+                &self.range_proof_binding()?,
+                None,
+                commitment,
+                &PedersenParameters::default(),
+            )
+            .map_err(|_| ChannelError::InvalidRangeProof)
+    }
+
+    /// Digest identifying the channel this state belongs to: the
+    /// `channel_aux_digest` over the participants, expiry and metadata,
+    /// which every state of the channel commits to.
+    pub fn channel_digest(&self) -> Result<Bytes32, ChannelError> {
+        let digest = channel_aux_digest(self).map_err(|_| ChannelError::StateHashFailed)?;
+        Ok(hash_out_to_bytes(&digest))
+    }
+
+    /// Channel and nonce this state's range proofs are bound to.
+    fn range_proof_binding(&self) -> Result<RangeProofBinding, ChannelError> {
+        Ok(RangeProofBinding { channel: self.channel_digest()?, nonce: self.nonce })
     }
 
-    /// Create a new state by transferring amount from sender to receiver
-    pub fn transfer(&self, amount: u64) -> Result<Self, ChannelError> {
+    /// Proves that the balances committed in this state are in range, and,
+    /// when `prior` is given, that `transfer_amount` left the sender balance.
+    fn prove_ranges(
+        &self,
+        prior: Option<&ChannelCommitment>,
+        transfer_amount: u64,
+    ) -> Result<ChannelRangeProof, ChannelError> {
+        let commitment = self.commitment.as_ref().ok_or(ChannelError::CommitmentMismatch)?;
+        ChannelRangeProof::prove(
+            &self.range_proof_binding()?,
+            prior,
+            commitment,
+            self.sender_balance,
+            self.receiver_balance,
+            transfer_amount,
+            &PedersenParameters::default(),
+        )
+        .map_err(|_| ChannelError::InvalidRangeProof)
+    }
+
+    /// Returns whether the channel has expired at the given Unix timestamp.
+    pub fn is_expired_at(&self, timestamp: u64) -> bool {
+        self.expiry.is_some_and(|expiry| timestamp >= expiry)
+    }
+
+    /// Returns whether the channel has expired by `clock`'s time.
+    pub fn is_expired(&self, clock: &dyn Clock) -> bool { self.is_expired_at(clock.now()) }
+
+    /// Creates a new channel like `new`, but in the `Funding` stage.
+    /// It must be moved to `Open` before it accepts transfers.
+    pub fn new_funding(
+        sender_balance: u64,
+        metadata: Vec<u8>,
+        participants: ChannelParticipants,
+    ) -> Result<Self, ChannelError> {
+        let mut channel = Self::new(sender_balance, metadata, participants)?;
+        channel.lifecycle = ChannelLifecycle::Funding;
+        channel.proof = Some(channel.genesis_proof()?);
+        Ok(channel)
+    }
+
+    /// Moves the channel to the `next` lifecycle stage. The stage is part of
+    /// the signed state, so the move advances the nonce and is signed by
+    /// `signer`, who must be the sender.
+    ///
+    /// Returns an error if the move is not allowed from the current stage.
+    pub fn advance_lifecycle(
+        &mut self,
+        next: ChannelLifecycle,
+        signer: &Keypair,
+    ) -> Result<(), ChannelError> {
+        if !self.lifecycle.can_transition_to(next) {
+            return Err(ChannelError::InvalidLifecycleTransition { from: self.lifecycle, to: next });
+        }
+
+        let mut next_state = self.clone();
+        next_state.nonce = self.nonce.checked_add(1).ok_or(ChannelError::ChannelNonceOverflow)?;
+        next_state.lifecycle = next;
+        next_state.signature = None;
+        next_state.proof = None;
+        next_state.memo = None;
+        next_state.transition = ChannelTransition::Lifecycle;
+        next_state.sign(signer)?;
+
+        *self = next_state;
+        Ok(())
+    }
+
+    /// Verifies that self moves `prior` to another lifecycle stage, as
+    /// produced by `advance_lifecycle`.
+    pub fn verify_lifecycle(&self, prior: &ChannelState) -> Result<(), ChannelError> {
+        if self.transition != ChannelTransition::Lifecycle {
+            return Err(ChannelError::InvalidTransitionKind);
+        }
+        if !prior.lifecycle.can_transition_to(self.lifecycle) {
+            return Err(ChannelError::InvalidLifecycleTransition {
+                from: prior.lifecycle,
+                to: self.lifecycle,
+            });
+        }
+        if self.expiry != prior.expiry {
+            return Err(ChannelError::ExpiryMismatch);
+        }
+
+        let expected_nonce =
+            prior.nonce.checked_add(1).ok_or(ChannelError::ChannelNonceOverflow)?;
+        if self.nonce != expected_nonce {
+            return Err(ChannelError::InvalidNonceIncrement);
+        }
+        if self.sender_balance != prior.sender_balance
+            || self.receiver_balance != prior.receiver_balance
+            || self.commitment != prior.commitment
+        {
+            return Err(ChannelError::InvalidBalanceChange);
+        }
+
+        if self.participants != prior.participants {
+            return Err(ChannelError::ParticipantMismatch);
+        }
+        self.verify_signature()
+    }
+
+    /// Returns an error unless the channel accepts state updates.
+    pub fn ensure_open(&self) -> Result<(), ChannelError> {
+        if self.lifecycle != ChannelLifecycle::Open {
+            return Err(ChannelError::ChannelNotOpen(self.lifecycle));
+        }
+        Ok(())
+    }
+
+    /// Create a new state by transferring amount from sender to receiver,
+    /// as long as the channel has not expired by `clock`'s time.
+    /// The returned state is unsigned; see `sign`.
+    pub fn transfer(&self, amount: u64, clock: &dyn Clock) -> Result<Self, ChannelError> {
+        self.transfer_by(amount, 1, clock)
+    }
+
+    /// Like `transfer`, but advances the nonce by `nonce_step`, so the
+    /// range proofs are bound to the final nonce and generated once.
+    fn transfer_by(
+        &self,
+        amount: u64,
+        nonce_step: u64,
+        clock: &dyn Clock,
+    ) -> Result<Self, ChannelError> {
+        self.ensure_open()?;
+        if self.is_expired(clock) {
+            return Err(ChannelError::ChannelExpired);
+        }
         if amount == 0 {
             return Err(ChannelError::InvalidZeroTransfer);
         }
@@ -97,35 +497,191 @@ impl ChannelState {
 
         next_state.sender_balance = next_sender_balance;
         next_state.receiver_balance = next_receiver_balance;
+        next_state.nonce = self
+            .nonce
+            .checked_add(nonce_step)
+            .ok_or(ChannelError::ChannelNonceOverflow)?;
+        let params = PedersenParameters::default();
+        let commitment = match &self.commitment {
+            Some(commitment) => {
+                commitment.next(next_sender_balance, next_receiver_balance, &params)
+            }
+            None => ChannelCommitment::random(next_sender_balance, next_receiver_balance, &params),
+        };
+        next_state.commitment = Some(commitment);
+        next_state.range_proof = Some(next_state.prove_ranges(self.commitment.as_ref(), amount)?);
+        next_state.signature = None;
+        next_state.proof = None;
+        next_state.memo = None;
+        next_state.transition = ChannelTransition::Transfer;
+
+        Ok(next_state)
+    }
+
+    /// Create a new state by adding `amount` to the sender balance,
+    /// raising the channel capacity. `funding` identifies the funding outpoint,
+    /// or a commitment to it, that backs the deposit and must not be zero.
+    /// The returned state is unsigned; see `sign`.
+    pub fn deposit(
+        &self,
+        amount: u64,
+        funding: Bytes32,
+        clock: &dyn Clock,
+    ) -> Result<Self, ChannelError> {
+        self.ensure_open()?;
+        if self.is_expired(clock) {
+            return Err(ChannelError::ChannelExpired);
+        }
+        if amount == 0 {
+            return Err(ChannelError::InvalidZeroDeposit);
+        }
+        if funding == [0u8; 32] {
+            return Err(ChannelError::MissingFunding);
+        }
+
+        let mut next_state = self.clone();
+
+        let next_sender_balance = self
+            .sender_balance
+            .checked_add(amount)
+            .ok_or(ChannelError::BalanceOverflow)?;
+        // Total capacity must stay representable
+        next_sender_balance
+            .checked_add(self.receiver_balance)
+            .ok_or(ChannelError::BalanceOverflow)?;
+
+        next_state.sender_balance = next_sender_balance;
         next_state.nonce = self
             .nonce
             .checked_add(1)
             .ok_or(ChannelError::ChannelNonceOverflow)?;
+        let params = PedersenParameters::default();
+        let commitment = match &self.commitment {
+            Some(commitment) => {
+                commitment.next(next_sender_balance, self.receiver_balance, &params)
+            }
+            None => ChannelCommitment::random(next_sender_balance, self.receiver_balance, &params),
+        };
+        next_state.commitment = Some(commitment);
+        // The deposit amount is public, so only the balances need range proofs
+        next_state.range_proof = Some(next_state.prove_ranges(None, 0)?);
+        next_state.signature = None;
+        next_state.proof = None;
+        next_state.memo = None;
+        next_state.transition = ChannelTransition::Deposit { amount, funding };
 
         Ok(next_state)
     }
 
-    // Apply the transfer to the channel state
-    pub fn apply_transfer(&mut self, channel_id: Bytes32, amount: u64) -> Result<(), ChannelError> {
-        let old_state = self.clone();
-        *self = self.transfer(amount)?;
+    /// Create a new state withdrawing `amount` from the receiver balance into a
+    /// settlement output for the receiver, leaving the channel open.
+    /// The returned state is unsigned; it must be signed by the receiver.
+    pub fn claim(&self, amount: u64, clock: &dyn Clock) -> Result<Self, ChannelError> {
+        self.ensure_open()?;
+        if self.is_expired(clock) {
+            return Err(ChannelError::ChannelExpired);
+        }
+        if amount == 0 {
+            return Err(ChannelError::InvalidZeroClaim);
+        }
 
-        // Generate proof for the state transition
-        let proof = self
-            .generate_transition_proof(channel_id, &old_state)
-            .map_err(|_| ChannelError::InvalidBalanceChange)?;
+        let mut next_state = self.clone();
+
+        let next_receiver_balance = self
+            .receiver_balance
+            .checked_sub(amount)
+            .ok_or(ChannelError::InsufficientBalance)?;
 
+        next_state.receiver_balance = next_receiver_balance;
+        next_state.nonce = self
+            .nonce
+            .checked_add(1)
+            .ok_or(ChannelError::ChannelNonceOverflow)?;
+        let params = PedersenParameters::default();
+        let commitment = match &self.commitment {
+            Some(commitment) => {
+                commitment.next(self.sender_balance, next_receiver_balance, &params)
+            }
+            None => ChannelCommitment::random(self.sender_balance, next_receiver_balance, &params),
+        };
+        next_state.commitment = Some(commitment);
+        // The claimed amount is public, so only the balances need range proofs
+        next_state.range_proof = Some(next_state.prove_ranges(None, 0)?);
+        next_state.signature = None;
+        next_state.proof = None;
+        next_state.memo = None;
+        next_state.transition = ChannelTransition::Claim {
+            output: SettlementOutput { recipient: self.participants.receiver, amount },
+        };
+
+        Ok(next_state)
+    }
+
+    /// Applies a claim signed by the receiver to the channel state and proves
+    /// the transition, returning the settlement output.
+    pub fn claim_with_proof<P: ProofSystem>(
+        &mut self,
+        system: &P,
+        channel_id: Bytes32,
+        amount: u64,
+        signer: &Keypair,
+    ) -> Result<SettlementOutput, anyhow::Error> {
+        let mut new_state = self.claim(amount, system.clock())?;
+        new_state.sign(signer)?;
+        let proof = new_state.generate_transition_proof(system, channel_id, self)?;
+
+        *self = new_state;
         self.proof = Some(proof);
+
+        Ok(SettlementOutput { recipient: self.participants.receiver, amount })
+    }
+
+    /// Applies a signed deposit backed by `funding` to the channel state and
+    /// proves the transition.
+    pub fn deposit_with_proof<P: ProofSystem>(
+        &mut self,
+        system: &P,
+        channel_id: Bytes32,
+        amount: u64,
+        funding: Bytes32,
+        signer: &Keypair,
+    ) -> Result<(), anyhow::Error> {
+        let mut new_state = self.deposit(amount, funding, system.clock())?;
+        new_state.sign(signer)?;
+        let proof = new_state.generate_transition_proof(system, channel_id, self)?;
+
+        *self = new_state;
+        self.proof = Some(proof);
+
         Ok(())
     }
 
-    pub fn transfer_with_proof(
+    // Apply the transfer to the channel state, signed by the sender
+    pub fn apply_transfer<P: ProofSystem>(
+        &mut self,
+        system: &P,
+        channel_id: Bytes32,
+        amount: u64,
+        signer: &Keypair,
+    ) -> Result<(), ChannelError> {
+        self.transfer_with_proof(system, channel_id, amount, signer).map_err(|err| {
+            err.downcast::<ChannelError>().unwrap_or(ChannelError::ProofGenerationFailed)
+        })
+    }
+
+    /// Applies a signed transfer to the channel state and stores the
+    /// serialized `system` proof of the transition in `proof`.
+    pub fn transfer_with_proof<P: ProofSystem>(
         &mut self,
+        system: &P,
         channel_id: Bytes32,
         amount: u64,
+        signer: &Keypair,
     ) -> Result<(), anyhow::Error> {
-        let new_state = self.transfer(amount)?;
-        let proof = new_state.generate_transition_proof(channel_id, self)?;
+        let mut new_state = self.transfer(amount, system.clock())?;
+        new_state.sign(signer)?;
+        new_state.verify_transition(self, system.clock())?;
+        let proof = new_state.generate_transition_proof(system, channel_id, self)?;
 
         // Update self
         *self = new_state;
@@ -134,58 +690,495 @@ impl ChannelState {
         Ok(())
     }
 
-    /// Verifies that the transition from prior to self is valid.
-    /// Used for external state validation (network messages, etc.)
-    pub fn verify_transition(&self, prior: &ChannelState) -> Result<(), ChannelError> {
+    /// Create a new state applying every transfer in `amounts` at once,
+    /// advancing the nonce by `amounts.len()`. Only the aggregated amount is
+    /// committed and range-proven. The returned state is unsigned; see `sign`.
+    pub fn transfer_batch(
+        &self,
+        amounts: &[u64],
+        clock: &dyn Clock,
+    ) -> Result<Self, ChannelError> {
+        if amounts.is_empty() {
+            return Err(ChannelError::EmptyBatch);
+        }
+        if amounts.contains(&0) {
+            return Err(ChannelError::InvalidZeroTransfer);
+        }
+        let total = amounts
+            .iter()
+            .try_fold(0u64, |total, &amount| total.checked_add(amount))
+            .ok_or(ChannelError::BalanceOverflow)?;
+
+        // A batch is a single transfer of the total with a wider nonce step
+        let count = amounts.len() as u64;
+        let mut next_state = self.transfer_by(total, count, clock)?;
+        next_state.transition = ChannelTransition::TransferBatch { count, total };
+
+        Ok(next_state)
+    }
+
+    /// Applies a signed batch of transfers to the channel state and returns
+    /// a single proof covering the whole nonce range.
+    pub fn apply_batch<P: ProofSystem>(
+        &mut self,
+        system: &P,
+        channel_id: Bytes32,
+        amounts: &[u64],
+        signer: &Keypair,
+    ) -> Result<BatchProof, ChannelError> {
+        let mut next_state = self.transfer_batch(amounts, system.clock())?;
+        next_state.sign(signer)?;
+        let proof = next_state
+            .generate_transition_proof(system, channel_id, self)
+            .map_err(|_| ChannelError::ProofGenerationFailed)?;
+
+        let batch_proof = BatchProof {
+            channel_id,
+            start_nonce: self.nonce,
+            end_nonce: next_state.nonce,
+            total_amount: next_state.transition.amount(),
+            old_commitment: self.generate_commitment().hash(),
+            new_commitment: next_state.generate_commitment().hash(),
+            proof: proof.clone(),
+        };
+
+        *self = next_state;
+        self.proof = Some(proof);
+        Ok(batch_proof)
+    }
+
+    /// Verifies that the transition from prior to self is valid by `clock`'s
+    /// time. Used for external state validation (network messages, etc.)
+    pub fn verify_transition(
+        &self,
+        prior: &ChannelState,
+        clock: &dyn Clock,
+    ) -> Result<(), ChannelError> {
+        self.verify_transition_at(prior, clock.now())
+    }
+
+    /// Verifies that the transition from prior to self was valid at `timestamp`,
+    /// e.g. when replaying previously accepted updates.
+    pub fn verify_transition_at(
+        &self,
+        prior: &ChannelState,
+        timestamp: u64,
+    ) -> Result<(), ChannelError> {
+        // Updates are only valid while the channel is open and unexpired
+        prior.ensure_open()?;
+        self.ensure_open()?;
+        if self.expiry != prior.expiry {
+            return Err(ChannelError::ExpiryMismatch);
+        }
+        if self.is_expired_at(timestamp) {
+            return Err(ChannelError::ChannelExpired);
+        }
+
         // Verify nonce increment
+        if self.transition.nonce_step() == 0 {
+            return Err(ChannelError::EmptyBatch);
+        }
         let expected_nonce = prior
             .nonce
-            .checked_add(1)
+            .checked_add(self.transition.nonce_step())
             .ok_or(ChannelError::ChannelNonceOverflow)?;
 
         if self.nonce != expected_nonce {
             return Err(ChannelError::InvalidNonceIncrement);
         }
 
-        // Verify balance conservation (total balance should remain the same)
-        let old_total = prior.sender_balance + prior.receiver_balance;
-        let new_total = self.sender_balance + self.receiver_balance;
+        // The range proofs are bound to the participants, so check them first
+        if self.participants != prior.participants {
+            return Err(ChannelError::ParticipantMismatch);
+        }
 
-        if old_total != new_total {
-            return Err(ChannelError::InvalidBalanceChange);
+        match &self.transition {
+            ChannelTransition::Transfer => self.verify_transfer(prior)?,
+            ChannelTransition::Deposit { amount, funding } => {
+                self.verify_deposit(prior, *amount, funding)?
+            }
+            ChannelTransition::Claim { output } => self.verify_claim(prior, output)?,
+            ChannelTransition::TransferBatch { count, total } => {
+                self.verify_batch(prior, *count, *total)?
+            }
+            ChannelTransition::Genesis
+            | ChannelTransition::Refund
+            | ChannelTransition::Lifecycle
+            | ChannelTransition::Close => return Err(ChannelError::InvalidTransitionKind),
         }
 
+        // Verify the update was authorised by the expected participant
+        self.verify_signature()?;
+
         Ok(())
     }
 
-    // Generate commitment for the channel
-    pub fn generate_commitment(&self) -> (Bytes32, Bytes32) {
-        let blinding = generate_random_blinding();
+    /// Verifies the balance rules of a transfer from prior to self.
+    fn verify_transfer(&self, prior: &ChannelState) -> Result<(), ChannelError> {
+        self.verify_balances(
+            prior,
+            prior.commitment.as_ref(),
+            // The committed total must stay the same
+            |commitment, prior_commitment| commitment.conserves_total(prior_commitment),
+            || {
+                let old_total = prior.sender_balance + prior.receiver_balance;
+                let new_total = self.sender_balance + self.receiver_balance;
+                if old_total != new_total {
+                    return Err(ChannelError::InvalidBalanceChange);
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Verifies the balance rules of a batch of `count` transfers totalling
+    /// `total` from prior to self.
+    fn verify_batch(
+        &self,
+        prior: &ChannelState,
+        count: u64,
+        total: u64,
+    ) -> Result<(), ChannelError> {
+        // Every transfer in the batch moves at least one unit
+        if total < count {
+            return Err(ChannelError::InvalidBalanceChange);
+        }
+
         let params = PedersenParameters::default();
-        let commitment = pedersen_commit(
-            self.sender_balance,
-            self.receiver_balance,
-            blinding,
-            &params,
-        );
-        (commitment, blinding)
+        self.verify_balances(
+            prior,
+            prior.commitment.as_ref(),
+            // The committed balances must move by exactly the public total
+            |commitment, prior_commitment| {
+                commitment.moves_to_receiver(prior_commitment, total, &params)
+            },
+            || {
+                // The aggregated delta must match the balance change on both sides
+                if prior.sender_balance.checked_sub(total) != Some(self.sender_balance)
+                    || prior.receiver_balance.checked_add(total) != Some(self.receiver_balance)
+                {
+                    return Err(ChannelError::InvalidBalanceChange);
+                }
+                Ok(())
+            },
+        )
     }
 
-    // Generate state proof for the channel
-    pub fn generate_state_proof(
+    /// Verifies the balance rules of a deposit of `amount` backed by
+    /// `funding` from prior to self.
+    fn verify_deposit(
         &self,
-        channel_id: Bytes32,
-        old_commitment: Bytes32,
-        new_commitment: Bytes32,
-    ) -> Result<crate::state::StateProof, anyhow::Error> {
+        prior: &ChannelState,
+        amount: u64,
+        funding: &Bytes32,
+    ) -> Result<(), ChannelError> {
+        if amount == 0 {
+            return Err(ChannelError::InvalidZeroDeposit);
+        }
+        if *funding == [0u8; 32] {
+            return Err(ChannelError::MissingFunding);
+        }
+
         let params = PedersenParameters::default();
-        let proof = generate_state_proof(
-            old_commitment,
-            new_commitment,
-            self.compute_merkle_root(channel_id)?,
-            &params,
-        );
-        Ok(proof) // Return the state::StateProof directly, don't convert
+        self.verify_balances(
+            prior,
+            None,
+            // The committed total must grow by the public deposit amount
+            |commitment, prior_commitment| {
+                commitment.increases_total_by(prior_commitment, amount, &params)
+            },
+            || {
+                // Only the sender balance grows, by exactly the deposited amount
+                let expected_sender_balance = prior
+                    .sender_balance
+                    .checked_add(amount)
+                    .ok_or(ChannelError::BalanceOverflow)?;
+                if self.sender_balance != expected_sender_balance
+                    || self.receiver_balance != prior.receiver_balance
+                {
+                    return Err(ChannelError::InvalidBalanceChange);
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Verifies the balance rules of a claim from prior to self, which must
+    /// be matched by a settlement output paying the receiver.
+    fn verify_claim(
+        &self,
+        prior: &ChannelState,
+        output: &SettlementOutput,
+    ) -> Result<(), ChannelError> {
+        if output.amount == 0 {
+            return Err(ChannelError::InvalidZeroClaim);
+        }
+        if output.recipient != prior.participants.receiver {
+            return Err(ChannelError::SettlementMismatch);
+        }
+
+        let params = PedersenParameters::default();
+        self.verify_balances(
+            prior,
+            None,
+            // The committed total must shrink by the public settled amount
+            |commitment, prior_commitment| {
+                commitment.decreases_total_by(prior_commitment, output.amount, &params)
+            },
+            || {
+                // Only the receiver balance shrinks, by exactly the settled amount
+                let expected_receiver_balance = prior
+                    .receiver_balance
+                    .checked_sub(output.amount)
+                    .ok_or(ChannelError::InsufficientBalance)?;
+                if self.receiver_balance != expected_receiver_balance
+                    || self.sender_balance != prior.sender_balance
+                {
+                    return Err(ChannelError::InvalidBalanceChange);
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Verifies the balances of self against prior.
+    ///
+    /// Once prior is committed, self must be committed too and the
+    /// commitments are checked: `commitments_match` relates the two, and the
+    /// range proofs, proving the amount moved out of the sender balance when
+    /// `transfer_from` is given, keep the committed balances in range.
+    /// Transitions from an uncommitted state are checked with `plaintext`.
+    /// A committed self must also open to its plaintext balances, so the
+    /// balances signed and hashed with the state match the commitment.
+    fn verify_balances(
+        &self,
+        prior: &ChannelState,
+        transfer_from: Option<&ChannelCommitment>,
+        commitments_match: impl FnOnce(&ChannelCommitment, &ChannelCommitment) -> bool,
+        plaintext: impl FnOnce() -> Result<(), ChannelError>,
+    ) -> Result<(), ChannelError> {
+        match (&prior.commitment, &self.commitment) {
+            (Some(prior_commitment), Some(commitment)) => {
+                if !commitments_match(commitment, prior_commitment) {
+                    return Err(ChannelError::CommitmentMismatch);
+                }
+            }
+            (Some(_), None) => return Err(ChannelError::CommitmentMismatch),
+            (None, _) => plaintext()?,
+        }
+
+        // Verify committed balances open to the plaintext ones, and that
+        // they, and any transfer amount, are in range
+        if let Some(commitment) = &self.commitment {
+            let params = PedersenParameters::default();
+            let opening = commitment.open(self.sender_balance, self.receiver_balance);
+            if !commitment.verify_opening(&opening, &params) {
+                return Err(ChannelError::CommitmentMismatch);
+            }
+            self.range_proof
+                .as_ref()
+                .ok_or(ChannelError::InvalidRangeProof)?
+                .verify(
+                    &self.range_proof_binding()?,
+                    transfer_from,
+                    commitment,
+                    &params,
+                )
+                .map_err(|_| ChannelError::InvalidRangeProof)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates the final state of an expired channel, returning the remaining
+    /// `sender_balance` to the sender. The receiver keeps `receiver_balance`.
+    /// The returned state is `Closed` and unsigned; see `sign`.
+    ///
+    /// Returns an error if the channel has no expiry or has not expired by
+    /// `clock`'s time.
+    pub fn refund(&self, clock: &dyn Clock) -> Result<Self, ChannelError> {
+        if self.expiry.is_none() || !self.is_expired(clock) {
+            return Err(ChannelError::ChannelNotExpired);
+        }
+        self.final_state(ChannelTransition::Refund)
+    }
+
+    /// Verifies that self is the refund of `prior` produced by `refund`,
+    /// by `clock`'s time.
+    pub fn verify_refund(
+        &self,
+        prior: &ChannelState,
+        clock: &dyn Clock,
+    ) -> Result<(), ChannelError> {
+        self.verify_refund_at(prior, clock.now())
+    }
+
+    /// Verifies that self was a valid refund of `prior` at `timestamp`.
+    pub fn verify_refund_at(&self, prior: &ChannelState, timestamp: u64) -> Result<(), ChannelError> {
+        if prior.expiry.is_none() || !prior.is_expired_at(timestamp) {
+            return Err(ChannelError::ChannelNotExpired);
+        }
+        self.verify_final_state(prior, ChannelTransition::Refund)
+    }
+
+    /// Creates the final state of a cooperative close, settling the current
+    /// balances. The returned state is `Closed` and unsigned; see `sign`.
+    ///
+    /// Returns an error once the channel has expired; the sender then
+    /// reclaims the funds with `refund` instead.
+    pub fn close(&self, clock: &dyn Clock) -> Result<Self, ChannelError> {
+        if self.is_expired(clock) {
+            return Err(ChannelError::ChannelExpired);
+        }
+        self.final_state(ChannelTransition::Close)
+    }
+
+    /// Verifies that self is the cooperative close of `prior` produced by
+    /// `close`, by `clock`'s time.
+    pub fn verify_close(
+        &self,
+        prior: &ChannelState,
+        clock: &dyn Clock,
+    ) -> Result<(), ChannelError> {
+        self.verify_close_at(prior, clock.now())
+    }
+
+    /// Verifies that self was a valid cooperative close of `prior` at
+    /// `timestamp`.
+    pub fn verify_close_at(
+        &self,
+        prior: &ChannelState,
+        timestamp: u64,
+    ) -> Result<(), ChannelError> {
+        if prior.is_expired_at(timestamp) {
+            return Err(ChannelError::ChannelExpired);
+        }
+        self.verify_final_state(prior, ChannelTransition::Close)
+    }
+
+    /// Creates the `Closed` state ending the channel with `transition`,
+    /// keeping the balances. Funding and closed channels cannot be ended.
+    fn final_state(&self, transition: ChannelTransition) -> Result<Self, ChannelError> {
+        if matches!(self.lifecycle, ChannelLifecycle::Funding | ChannelLifecycle::Closed) {
+            return Err(ChannelError::ChannelNotOpen(self.lifecycle));
+        }
+
+        let mut final_state = self.clone();
+        final_state.nonce =
+            self.nonce.checked_add(1).ok_or(ChannelError::ChannelNonceOverflow)?;
+        final_state.lifecycle = ChannelLifecycle::Closed;
+        final_state.signature = None;
+        final_state.proof = None;
+        final_state.memo = None;
+        final_state.transition = transition;
+        Ok(final_state)
+    }
+
+    /// Verifies that self is the state `final_state` creates from `prior`
+    /// for `transition`, signed by the sender. Expiry rules are left to the
+    /// caller.
+    fn verify_final_state(
+        &self,
+        prior: &ChannelState,
+        transition: ChannelTransition,
+    ) -> Result<(), ChannelError> {
+        if self.expiry != prior.expiry {
+            return Err(ChannelError::ExpiryMismatch);
+        }
+        if matches!(prior.lifecycle, ChannelLifecycle::Funding | ChannelLifecycle::Closed) {
+            return Err(ChannelError::ChannelNotOpen(prior.lifecycle));
+        }
+        if self.transition != transition {
+            return Err(ChannelError::InvalidTransitionKind);
+        }
+        if self.lifecycle != ChannelLifecycle::Closed {
+            return Err(ChannelError::InvalidLifecycleTransition {
+                from: prior.lifecycle,
+                to: self.lifecycle,
+            });
+        }
+
+        let expected_nonce =
+            prior.nonce.checked_add(1).ok_or(ChannelError::ChannelNonceOverflow)?;
+        if self.nonce != expected_nonce {
+            return Err(ChannelError::InvalidNonceIncrement);
+        }
+        if self.sender_balance != prior.sender_balance
+            || self.receiver_balance != prior.receiver_balance
+            || self.commitment != prior.commitment
+        {
+            return Err(ChannelError::InvalidBalanceChange);
+        }
+
+        if self.participants != prior.participants {
+            return Err(ChannelError::ParticipantMismatch);
+        }
+        self.verify_signature()
+    }
+
+    /// Canonical digest of this state signed by `signer`.
+    /// Covers the state hash and the balance commitment.
+    pub fn state_digest(&self) -> Result<Bytes32, ChannelError> {
+        let state_hash = hash_state(self).map_err(|_| ChannelError::StateHashFailed)?;
+        let commitment_hash =
+            self.commitment.as_ref().map(ChannelCommitment::hash).unwrap_or_default();
+
+        let mut message = Vec::with_capacity(64);
+        message.extend_from_slice(&state_hash);
+        message.extend_from_slice(&commitment_hash);
+        Ok(tagged_hash(STATE_DIGEST_TAG, &message))
+    }
+
+    /// Public key authorised to sign this state: the receiver for claims,
+    /// the sender for every other transition.
+    pub fn signer(&self) -> Bytes32 {
+        match self.transition {
+            ChannelTransition::Claim { .. } => self.participants.receiver,
+            _ => self.participants.sender,
+        }
+    }
+
+    /// Signs this state with the authorised participant's keypair.
+    ///
+    /// Returns an error if the keypair does not belong to `signer`.
+    pub fn sign(&mut self, signer: &Keypair) -> Result<(), ChannelError> {
+        if x_only_public_key(signer) != self.signer() {
+            return Err(ChannelError::ParticipantMismatch);
+        }
+        let digest = self.state_digest()?;
+        self.signature = Some(sign_digest(&digest, signer));
+        Ok(())
+    }
+
+    /// Verifies the authorised participant's signature over this state.
+    pub fn verify_signature(&self) -> Result<(), ChannelError> {
+        let signature = self.signature.as_ref().ok_or(ChannelError::MissingSignature)?;
+        let digest = self.state_digest()?;
+        if !verify_digest(&digest, signature, &self.signer()) {
+            return Err(ChannelError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    /// Returns the commitment stored with this state, or commits to the
+    /// balances with a fresh blinding if none is stored.
+    pub fn generate_commitment(&self) -> ChannelCommitment {
+        match &self.commitment {
+            Some(commitment) => commitment.clone(),
+            None => ChannelCommitment::random(
+                self.sender_balance,
+                self.receiver_balance,
+                &PedersenParameters::default(),
+            ),
+        }
+    }
+
+    /// Opens the stored commitment, revealing the balances and blinding
+    /// so a receiver can check them with `ChannelCommitment::verify_opening`.
+    pub fn open_commitment(&self) -> Option<CommitmentOpening> {
+        self.commitment
+            .as_ref()
+            .map(|commitment| commitment.open(self.sender_balance, self.receiver_balance))
     }
 
     // Verify a ZK proof
@@ -194,29 +1187,26 @@ impl ChannelState {
         crate::state::verify_zk_proof(proof, public_inputs, &params)
     }
 
-    /// Generate proof for this state transition
-    pub fn generate_transition_proof(
+    /// Generate a serialized `system` proof of the transition from `prior`
+    pub fn generate_transition_proof<P: ProofSystem>(
         &self,
+        system: &P,
         channel_id: Bytes32,
         prior: &ChannelState,
     ) -> Result<Vec<u8>, anyhow::Error> {
-        // Verify transition first
-        self.verify_transition(prior)?;
-
-        let (old_commitment, _) = prior.generate_commitment();
-        let (new_commitment, _) = self.generate_commitment();
-
-        let state_proof = self.generate_state_proof(channel_id, old_commitment, new_commitment)?;
-        Ok(state_proof.pi.to_vec())
+        let proof = system.prove_transition(&channel_id, prior, self)?;
+        Ok(system.serialize(&proof)?)
     }
 
-    /// Updates the Sparse Merkle Tree with the new state.
+    /// Updates the Sparse Merkle Tree with the new state, if it is a valid
+    /// successor of `old_state` by `clock`'s time.
     pub fn update_in_tree(
         &self,
         smt: &mut MerkleTree,
         old_state: &ChannelState,
+        clock: &dyn Clock,
     ) -> Result<(Bytes32, Bytes32), MerkleTreeError> {
-        if !self.verify_transition(old_state).is_ok() {
+        if !self.verify_transition(old_state, clock).is_ok() {
             return Err(MerkleTreeError::InvalidInput(
                 "Invalid state transition".to_string(),
             ));
@@ -234,9 +1224,19 @@ impl ChannelState {
         Ok((new_leaf, new_root))
     }
 
-    /// Check if the channel has a valid proof
-    pub fn has_valid_proof(&self) -> bool {
-        self.proof.is_some()
+    /// Checks that `proof` holds a valid `system` proof of a transition
+    /// ending in this state in channel `channel_id`. Initial states hold a
+    /// genesis envelope instead, which is checked by `verify_genesis`.
+    pub fn has_valid_proof<P: ProofSystem>(&self, system: &P, channel_id: &Bytes32) -> bool {
+        if self.transition == ChannelTransition::Genesis {
+            return self.verify_genesis().is_ok();
+        }
+        self.proof.as_ref().is_some_and(|bytes| {
+            system
+                .deserialize(bytes)
+                .and_then(|proof| system.verify_transition(&proof, channel_id, None, self))
+                .is_ok()
+        })
     }
 
     /// Computes the merkle root for a channel given its ID, current state hash, and nonce.
@@ -248,23 +1248,59 @@ impl ChannelState {
 
 #[cfg(test)]
 mod tests {
+    use secp256k1::SECP256K1;
+
     use super::*;
+    use crate::clock::ManualClock;
+    use crate::proof_system::{MockProofSystem, Plonky2ProofSystem};
+
+    /// Funding outpoint backing deposits in tests.
+    const FUNDING: Bytes32 = [5u8; 32];
+
+    /// Unix time the test clock starts at.
+    const NOW: u64 = 1_700_000_000;
+
+    /// Channel id the test states are proven under.
+    const CHANNEL_ID: Bytes32 = [1u8; 32];
+
+    fn sender_keypair() -> Keypair { Keypair::from_seckey_slice(SECP256K1, &[1u8; 32]).unwrap() }
+
+    fn receiver_keypair() -> Keypair { Keypair::from_seckey_slice(SECP256K1, &[2u8; 32]).unwrap() }
+
+    fn participants() -> ChannelParticipants {
+        ChannelParticipants {
+            sender: x_only_public_key(&sender_keypair()),
+            receiver: x_only_public_key(&receiver_keypair()),
+        }
+    }
+
+    fn clock() -> ManualClock { ManualClock::new(NOW, 800_000) }
 
     fn create_state(sender_balance: u64, receiver_balance: u64, nonce: u64) -> ChannelState {
-        ChannelState {
+        let mut state = ChannelState {
             sender_balance,
             receiver_balance,
             metadata: vec![],
             nonce,
             proof: None,
-        }
+            commitment: None,
+            range_proof: None,
+            participants: participants(),
+            signature: None,
+            lifecycle: ChannelLifecycle::Open,
+            expiry: None,
+            transition: ChannelTransition::Transfer,
+            memo: None,
+        };
+        state.sign(&sender_keypair()).unwrap();
+        state
     }
 
     #[test]
     fn test_new() {
         let sender_balance = 100;
         let metadata = vec![1, 2, 3];
-        let channel = ChannelState::new(sender_balance, metadata.clone()).unwrap();
+        let channel = ChannelState::new(sender_balance, metadata.clone(), participants()).unwrap();
 
         // Test constructor
         assert_eq!(channel.sender_balance, sender_balance);
@@ -272,20 +1308,35 @@ mod tests {
         assert_eq!(channel.metadata, metadata);
         assert_eq!(channel.nonce, 0);
         assert!(channel.proof.is_some());
-        assert!(channel.has_valid_proof());
+        assert!(channel.has_valid_proof(&Plonky2ProofSystem::default(), &CHANNEL_ID));
+        assert!(channel.has_valid_proof(&MockProofSystem::default(), &CHANNEL_ID));
+
+        // The genesis envelope only vouches for the state it was created with
+        let mut tampered = channel.clone();
+        tampered.sender_balance += 1;
+        assert_eq!(tampered.verify_genesis(), Err(ChannelError::InvalidTransitionProof));
+        let mut stripped = channel.clone();
+        stripped.proof = None;
+        assert!(!stripped.has_valid_proof(&Plonky2ProofSystem::default(), &CHANNEL_ID));
+
+        // Funding and expiring genesis states also get valid envelopes
+        let funding = ChannelState::new_funding(100, Vec::new(), participants()).unwrap();
+        assert!(funding.verify_genesis().is_ok());
+        let expiring = channel.clone().with_expiry(u64::MAX).unwrap();
+        assert!(expiring.verify_genesis().is_ok());
     }
 
     #[test]
     fn test_new_simple() {
         let sender_balance = 100;
-        let channel = ChannelState::new(sender_balance, Vec::new()).unwrap();
+        let channel = ChannelState::new(sender_balance, Vec::new(), participants()).unwrap();
 
         // Test constructor
         assert_eq!(channel.sender_balance, sender_balance);
         assert_eq!(channel.receiver_balance, 0);
         assert_eq!(channel.metadata, Vec::<u8>::new());
         assert_eq!(channel.nonce, 0);
-        assert!(channel.has_valid_proof());
+        assert!(channel.has_valid_proof(&Plonky2ProofSystem::default(), &CHANNEL_ID));
     }
 
     #[test]
@@ -293,50 +1344,648 @@ mod tests {
         let old = create_state(100, 0, 0);
         let new = create_state(90, 10, 1);
 
-        let transition_result = new.verify_transition(&old);
+        let transition_result = new.verify_transition(&old, &clock());
         assert!(transition_result.is_ok());
 
         // Test invalid nonce increment
         let invalid_nonce = create_state(90, 10, 2);
-        let invalid_nonce_result = invalid_nonce.verify_transition(&old);
+        let invalid_nonce_result = invalid_nonce.verify_transition(&old, &clock());
         assert!(invalid_nonce_result.is_err());
 
         // Test invalid balance total
         let invalid_balance = create_state(90, 15, 1);
-        let invalid_balance_result = invalid_balance.verify_transition(&old);
+        let invalid_balance_result = invalid_balance.verify_transition(&old, &clock());
         assert!(invalid_balance_result.is_err());
 
         // Test nonce overflow
         let max_nonce = create_state(100, 0, u64::MAX);
         let overflow = create_state(90, 10, 0);
-        let overflow_result = overflow.verify_transition(&max_nonce);
+        let overflow_result = overflow.verify_transition(&max_nonce, &clock());
         assert!(overflow_result.is_err());
     }
 
     #[test]
     fn test_transfer() {
-        let channel = ChannelState::new(100, Vec::new()).unwrap();
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
 
         // Test successful transfer
-        let transfer_result = channel.transfer(30);
+        let transfer_result = channel.transfer(30, &clock());
         let new_channel = transfer_result.unwrap();
         assert_eq!(new_channel.sender_balance, 70);
         assert_eq!(new_channel.receiver_balance, 30);
         assert_eq!(new_channel.nonce, 1);
-        assert!(new_channel.has_valid_proof());
+        // The prior state's proof is not carried over
+        assert!(new_channel.proof.is_none());
+        assert!(!new_channel.has_valid_proof(&MockProofSystem::default(), &CHANNEL_ID));
+
+        // Proven transfers carry a proof of the same envelope format
+        let mock = MockProofSystem::default();
+        let mut proven = channel.clone();
+        proven.apply_transfer(&mock, CHANNEL_ID, 30, &sender_keypair()).unwrap();
+        assert!(proven.proof.is_some());
+        assert!(proven.has_valid_proof(&mock, &CHANNEL_ID));
+        assert!(!proven.has_valid_proof(&mock, &[2u8; 32]));
 
         // Test insufficient balance
-        let insufficient_result = new_channel.transfer(80);
+        let insufficient_result = new_channel.transfer(80, &clock());
         assert!(insufficient_result.is_err());
 
         // Test zero transfer
-        let zero_result = new_channel.transfer(0);
+        let zero_result = new_channel.transfer(0, &clock());
         assert!(zero_result.is_err());
     }
 
+    #[test]
+    fn test_transfer_with_proof() -> Result<()> {
+        let signer = sender_keypair();
+        let system = Plonky2ProofSystem::default();
+        let mut channel = ChannelState::new(100, b"channel".to_vec(), participants())?;
+        channel.transfer_with_proof(&system, [1u8; 32], 30, &signer)?;
+        assert_eq!((channel.sender_balance, channel.receiver_balance), (70, 30));
+        assert!(channel.has_valid_proof(&system, &[1u8; 32]));
+        assert!(!channel.has_valid_proof(&system, &[2u8; 32]));
+
+        // The proof only vouches for the state it was generated for
+        let mut tampered = channel.clone();
+        tampered.receiver_balance += 1;
+        assert!(!tampered.has_valid_proof(&system, &[1u8; 32]));
+
+        let mut corrupted = channel.clone();
+        corrupted.proof = Some(vec![0u8; 32]);
+        assert!(!corrupted.has_valid_proof(&system, &[1u8; 32]));
+
+        // A failed transfer leaves the channel untouched
+        let prior = channel.clone();
+        let overdrawn = channel.transfer_with_proof(&system, [1u8; 32], 71, &signer);
+        assert!(overdrawn.is_err());
+        assert_eq!(channel, prior);
+        Ok(())
+    }
+
+    #[test]
+    fn test_commitment_is_stored_and_openable() {
+        let params = PedersenParameters::default();
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+
+        // The stored commitment is stable across calls
+        let commitment = channel.generate_commitment();
+        assert_eq!(commitment, channel.generate_commitment());
+
+        let opening = channel.open_commitment().unwrap();
+        assert_eq!(opening.sender_balance, 100);
+        assert_eq!(opening.receiver_balance, 0);
+        assert!(commitment.verify_opening(&opening, &params));
+
+        // Each transfer commits to the new balances
+        let next = channel.transfer(30, &clock()).unwrap();
+        let next_commitment = next.generate_commitment();
+        assert!(next_commitment.verify_opening(&next.open_commitment().unwrap(), &params));
+        assert!(!next_commitment.verify_opening(&opening, &params));
+    }
+
+    #[test]
+    fn test_verify_transition_with_commitments() {
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let mut next = channel.transfer(30, &clock()).unwrap();
+        next.sign(&sender_keypair()).unwrap();
+
+        // Conservation holds over the commitment points
+        assert!(next
+            .commitment
+            .as_ref()
+            .unwrap()
+            .conserves_total(channel.commitment.as_ref().unwrap()));
+        assert!(next.verify_transition(&channel, &clock()).is_ok());
+
+        // A commitment with unrelated blindings is rejected
+        let mut tampered = next.clone();
+        tampered.commitment =
+            Some(ChannelCommitment::random(70, 30, &PedersenParameters::default()));
+        assert_eq!(
+            tampered.verify_transition(&channel, &clock()),
+            Err(ChannelError::CommitmentMismatch)
+        );
+    }
+
+    #[test]
+    fn test_verify_transition_with_range_proofs() {
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let mut next = channel.transfer(30, &clock()).unwrap();
+        next.sign(&sender_keypair()).unwrap();
+        assert!(next.range_proof.as_ref().unwrap().transfer.is_some());
+        assert!(next.verify_transition(&channel, &clock()).is_ok());
+
+        // A committed state without range proofs is rejected
+        let mut missing = next.clone();
+        missing.range_proof = None;
+        assert_eq!(
+            missing.verify_transition(&channel, &clock()),
+            Err(ChannelError::InvalidRangeProof)
+        );
+
+        // Range proofs from another transition are rejected
+        let mut swapped = next.clone();
+        swapped.range_proof = channel.transfer(30, &clock()).unwrap().range_proof;
+        assert_eq!(
+            swapped.verify_transition(&channel, &clock()),
+            Err(ChannelError::InvalidRangeProof)
+        );
+    }
+
+    #[test]
+    fn test_verify_transition_signatures() {
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let mut next = channel.transfer(30, &clock()).unwrap();
+
+        // Unsigned updates are rejected
+        assert_eq!(next.verify_transition(&channel, &clock()), Err(ChannelError::MissingSignature));
+
+        // Only the sender may sign
+        let receiver = Keypair::from_seckey_slice(SECP256K1, &[2u8; 32]).unwrap();
+        assert_eq!(next.sign(&receiver), Err(ChannelError::ParticipantMismatch));
+
+        next.sign(&sender_keypair()).unwrap();
+        assert!(next.verify_transition(&channel, &clock()).is_ok());
+
+        // A signature over different balances is rejected
+        let mut forged = next.clone();
+        forged.signature = create_state(60, 40, 1).signature;
+        assert_eq!(
+            forged.verify_transition(&channel, &clock()),
+            Err(ChannelError::InvalidSignature)
+        );
+
+        // Participants cannot change within a channel
+        let mut rebound = next.clone();
+        rebound.participants.receiver = rebound.participants.sender;
+        rebound.sign(&sender_keypair()).unwrap();
+        assert_eq!(
+            rebound.verify_transition(&channel, &clock()),
+            Err(ChannelError::ParticipantMismatch)
+        );
+    }
+
+    #[test]
+    fn test_lifecycle_transitions() {
+        let mut channel = ChannelState::new_funding(100, Vec::new(), participants()).unwrap();
+        assert_eq!(channel.lifecycle, ChannelLifecycle::Funding);
+
+        // No transfers before the channel is open
+        assert_eq!(
+            channel.transfer(10, &clock()),
+            Err(ChannelError::ChannelNotOpen(ChannelLifecycle::Funding))
+        );
+
+        let funding = channel.clone();
+        channel.advance_lifecycle(ChannelLifecycle::Open, &sender_keypair()).unwrap();
+        assert_eq!(channel.nonce, funding.nonce + 1);
+        assert!(channel.verify_lifecycle(&funding).is_ok());
+        let mut next = channel.transfer(10, &clock()).unwrap();
+        next.sign(&sender_keypair()).unwrap();
+        assert!(next.verify_transition(&channel, &clock()).is_ok());
+
+        // The stage is signed, so it cannot be changed without a new signature
+        assert_ne!(hash_state(&channel).unwrap(), hash_state(&funding).unwrap());
+        let mut forged = channel.clone();
+        forged.lifecycle = ChannelLifecycle::Closed;
+        assert_eq!(forged.verify_signature(), Err(ChannelError::InvalidSignature));
+
+        // Only the sender may move the channel
+        let mut moved = channel.clone();
+        assert_eq!(
+            moved.advance_lifecycle(ChannelLifecycle::Closing, &receiver_keypair()),
+            Err(ChannelError::ParticipantMismatch)
+        );
+        assert_eq!(moved, channel);
+
+        // Guarded moves
+        assert_eq!(
+            channel.advance_lifecycle(ChannelLifecycle::Funding, &sender_keypair()),
+            Err(ChannelError::InvalidLifecycleTransition {
+                from: ChannelLifecycle::Open,
+                to: ChannelLifecycle::Funding,
+            })
+        );
+        let open = channel.clone();
+        channel.advance_lifecycle(ChannelLifecycle::Closing, &sender_keypair()).unwrap();
+        channel.advance_lifecycle(ChannelLifecycle::Disputed, &sender_keypair()).unwrap();
+        channel.advance_lifecycle(ChannelLifecycle::Closed, &sender_keypair()).unwrap();
+        assert!(channel.advance_lifecycle(ChannelLifecycle::Open, &sender_keypair()).is_err());
+        assert_eq!(channel.nonce, open.nonce + 3);
+
+        // Lifecycle moves must follow the allowed moves and cannot move funds
+        let mut skipped = open.clone();
+        skipped.advance_lifecycle(ChannelLifecycle::Closing, &sender_keypair()).unwrap();
+        assert_eq!(
+            skipped.verify_lifecycle(&funding),
+            Err(ChannelError::InvalidLifecycleTransition {
+                from: ChannelLifecycle::Funding,
+                to: ChannelLifecycle::Closing,
+            })
+        );
+        let mut skimmed = open.clone();
+        skimmed.advance_lifecycle(ChannelLifecycle::Closing, &sender_keypair()).unwrap();
+        skimmed.sender_balance = 0;
+        skimmed.sign(&sender_keypair()).unwrap();
+        assert_eq!(skimmed.verify_lifecycle(&open), Err(ChannelError::InvalidBalanceChange));
+
+        // Transferring on a closed channel is rejected
+        assert_eq!(
+            channel.transfer(10, &clock()),
+            Err(ChannelError::ChannelNotOpen(ChannelLifecycle::Closed))
+        );
+        assert_eq!(
+            next.verify_transition(&channel, &clock()),
+            Err(ChannelError::ChannelNotOpen(ChannelLifecycle::Closed))
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        let clock = clock();
+        let future = NOW + 3600;
+        let channel = ChannelState::new(100, Vec::new(), participants())
+            .unwrap()
+            .with_expiry(future)
+            .unwrap();
+        assert!(!channel.is_expired(&clock));
+        assert!(channel.is_expired_at(future));
+
+        // Transfers are accepted before expiry
+        let mut next = channel.transfer(10, &clock).unwrap();
+        next.sign(&sender_keypair()).unwrap();
+        assert!(next.verify_transition(&channel, &clock).is_ok());
+        assert_eq!(channel.refund(&clock), Err(ChannelError::ChannelNotExpired));
+
+        // The expiry is committed and cannot change
+        let mut extended = next.clone();
+        extended.expiry = Some(future + 1);
+        extended.sign(&sender_keypair()).unwrap();
+        assert_eq!(extended.verify_transition(&channel, &clock), Err(ChannelError::ExpiryMismatch));
+        assert_ne!(hash_state(&extended).unwrap(), hash_state(&next).unwrap());
+
+        // Transfers are rejected once the clock reaches the expiry
+        clock.set(future);
+        assert!(channel.is_expired(&clock));
+        assert_eq!(channel.transfer(10, &clock), Err(ChannelError::ChannelExpired));
+        assert_eq!(next.verify_transition(&channel, &clock), Err(ChannelError::ChannelExpired));
+        assert!(next.verify_transition_at(&channel, future - 1).is_ok());
+        assert!(channel.refund(&clock).is_ok());
+    }
+
+    #[test]
+    fn test_refund_after_expiry() {
+        let channel =
+            ChannelState::new(100, Vec::new(), participants()).unwrap().with_expiry(1).unwrap();
+        let paid = ChannelState { sender_balance: 70, receiver_balance: 30, ..channel };
+
+        let mut refund = paid.refund(&clock()).unwrap();
+        assert_eq!(refund.lifecycle, ChannelLifecycle::Closed);
+        assert_eq!(refund.sender_balance, 70);
+        assert_eq!(refund.receiver_balance, 30);
+        assert_eq!(refund.nonce, paid.nonce + 1);
+
+        assert_eq!(refund.verify_refund(&paid, &clock()), Err(ChannelError::MissingSignature));
+        refund.sign(&sender_keypair()).unwrap();
+        assert!(refund.verify_refund(&paid, &clock()).is_ok());
+
+        // The refund cannot move balances
+        let mut skimmed = refund.clone();
+        skimmed.receiver_balance = 0;
+        skimmed.sign(&sender_keypair()).unwrap();
+        assert_eq!(skimmed.verify_refund(&paid, &clock()), Err(ChannelError::InvalidBalanceChange));
+
+        // A closed channel cannot be refunded again
+        assert_eq!(
+            refund.refund(&clock()),
+            Err(ChannelError::ChannelNotOpen(ChannelLifecycle::Closed))
+        );
+    }
+
+    #[test]
+    fn test_close() {
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let mut paid = channel.transfer(30, &clock()).unwrap();
+        paid.memo = Some([7u8; 32]);
+        paid.sign(&sender_keypair()).unwrap();
+
+        let mut closed = paid.close(&clock()).unwrap();
+        assert_eq!(closed.transition, ChannelTransition::Close);
+        assert_eq!(closed.lifecycle, ChannelLifecycle::Closed);
+        assert_eq!((closed.sender_balance, closed.receiver_balance), (70, 30));
+        assert_eq!(closed.memo, None);
+        assert_eq!(closed.verify_close(&paid, &clock()), Err(ChannelError::MissingSignature));
+        closed.sign(&sender_keypair()).unwrap();
+        assert!(closed.verify_close(&paid, &clock()).is_ok());
+
+        // A close is not a refund, and the two hash differently
+        assert_eq!(closed.verify_refund(&paid, &clock()), Err(ChannelError::ChannelNotExpired));
+        let mut relabeled = closed.clone();
+        relabeled.transition = ChannelTransition::Refund;
+        assert_ne!(hash_state(&relabeled).unwrap(), hash_state(&closed).unwrap());
+        relabeled.sign(&sender_keypair()).unwrap();
+        assert_eq!(
+            relabeled.verify_close(&paid, &clock()),
+            Err(ChannelError::InvalidTransitionKind)
+        );
+
+        // Closing is guarded like refunds: not twice, not while funding
+        assert_eq!(
+            closed.close(&clock()),
+            Err(ChannelError::ChannelNotOpen(ChannelLifecycle::Closed))
+        );
+        let funding = ChannelState::new_funding(100, Vec::new(), participants()).unwrap();
+        assert_eq!(
+            funding.close(&clock()),
+            Err(ChannelError::ChannelNotOpen(ChannelLifecycle::Funding))
+        );
+
+        // Once expired, only a refund can end the channel
+        let expired = ChannelState { expiry: Some(1), ..paid.clone() };
+        assert_eq!(expired.close(&clock()), Err(ChannelError::ChannelExpired));
+        assert_eq!(closed.verify_close_at(&paid, 0), Ok(()));
+        let mut late = ChannelState { expiry: Some(1), ..closed.clone() };
+        late.sign(&sender_keypair()).unwrap();
+        assert_eq!(late.verify_close(&expired, &clock()), Err(ChannelError::ChannelExpired));
+    }
+
+    #[test]
+    fn test_deposit() {
+        let params = PedersenParameters::default();
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let mut paid = channel.transfer(40, &clock()).unwrap();
+        paid.sign(&sender_keypair()).unwrap();
+
+        let mut topped_up = paid.deposit(50, FUNDING, &clock()).unwrap();
+        assert_eq!(topped_up.sender_balance, 110);
+        assert_eq!(topped_up.receiver_balance, 40);
+        assert_eq!(topped_up.nonce, 2);
+        assert_eq!(
+            topped_up.transition,
+            ChannelTransition::Deposit { amount: 50, funding: FUNDING }
+        );
+        assert!(topped_up
+            .commitment
+            .as_ref()
+            .unwrap()
+            .verify_opening(&topped_up.open_commitment().unwrap(), &params));
+
+        topped_up.sign(&sender_keypair()).unwrap();
+        assert!(topped_up.verify_transition(&paid, &clock()).is_ok());
+
+        // The deposit amount must match the balance change
+        let mut overstated = topped_up.clone();
+        overstated.transition = ChannelTransition::Deposit { amount: 60, funding: FUNDING };
+        overstated.sign(&sender_keypair()).unwrap();
+        assert_eq!(
+            overstated.verify_transition(&paid, &clock()),
+            Err(ChannelError::CommitmentMismatch)
+        );
+
+        // A deposit cannot be passed off as a transfer
+        let mut relabeled = topped_up.clone();
+        relabeled.transition = ChannelTransition::Transfer;
+        relabeled.sign(&sender_keypair()).unwrap();
+        assert_eq!(
+            relabeled.verify_transition(&paid, &clock()),
+            Err(ChannelError::CommitmentMismatch)
+        );
+
+        // The plaintext balances must open the commitment
+        let mut stale = topped_up.clone();
+        stale.sender_balance = 0;
+        stale.sign(&sender_keypair()).unwrap();
+        assert_eq!(stale.verify_transition(&paid, &clock()), Err(ChannelError::CommitmentMismatch));
+
+        // and a committed channel cannot drop its commitment
+        let mut uncommitted = topped_up.clone();
+        uncommitted.commitment = None;
+        uncommitted.sign(&sender_keypair()).unwrap();
+        assert_eq!(
+            uncommitted.verify_transition(&paid, &clock()),
+            Err(ChannelError::CommitmentMismatch)
+        );
+
+        // Deposits must name the funds backing them
+        let mut unfunded = topped_up.clone();
+        unfunded.transition = ChannelTransition::Deposit { amount: 50, funding: [0u8; 32] };
+        unfunded.sign(&sender_keypair()).unwrap();
+        assert_eq!(unfunded.verify_transition(&paid, &clock()), Err(ChannelError::MissingFunding));
+        assert_eq!(paid.deposit(50, [0u8; 32], &clock()), Err(ChannelError::MissingFunding));
+
+        // The funding reference is signed
+        let mut refunded = topped_up.clone();
+        refunded.transition = ChannelTransition::Deposit { amount: 50, funding: [6u8; 32] };
+        assert_eq!(
+            refunded.verify_transition(&paid, &clock()),
+            Err(ChannelError::InvalidSignature)
+        );
+
+        assert_eq!(paid.deposit(0, FUNDING, &clock()), Err(ChannelError::InvalidZeroDeposit));
+        assert_eq!(paid.deposit(u64::MAX, FUNDING, &clock()), Err(ChannelError::BalanceOverflow));
+    }
+
+    #[test]
+    fn test_deposit_with_proof() -> Result<()> {
+        let mock = MockProofSystem::default();
+        let mut channel = ChannelState::new(100, Vec::new(), participants())?;
+        let prior = channel.clone();
+        channel.deposit_with_proof(&mock, [1u8; 32], 25, FUNDING, &sender_keypair())?;
+
+        assert_eq!(channel.sender_balance, 125);
+        assert!(channel.verify_transition(&prior, &clock()).is_ok());
+        assert!(channel.has_valid_proof(&mock, &[1u8; 32]));
+        assert_ne!(channel.proof, prior.proof);
+
+        // Proofs of one system are rejected by another
+        assert!(!channel.has_valid_proof(&Plonky2ProofSystem::default(), &[1u8; 32]));
+        assert_ne!(hash_state(&channel)?, hash_state(&prior)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim() {
+        let params = PedersenParameters::default();
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let mut paid = channel.transfer(40, &clock()).unwrap();
+        paid.sign(&sender_keypair()).unwrap();
+
+        let mut claimed = paid.claim(15, &clock()).unwrap();
+        assert_eq!(claimed.sender_balance, 60);
+        assert_eq!(claimed.receiver_balance, 25);
+        assert_eq!(claimed.nonce, 2);
+        assert_eq!(claimed.lifecycle, ChannelLifecycle::Open);
+        assert_eq!(
+            claimed.transition,
+            ChannelTransition::Claim {
+                output: SettlementOutput { recipient: participants().receiver, amount: 15 }
+            }
+        );
+        assert!(claimed
+            .commitment
+            .as_ref()
+            .unwrap()
+            .verify_opening(&claimed.open_commitment().unwrap(), &params));
+
+        // Claims are authorised by the receiver, not the sender
+        assert_eq!(claimed.sign(&sender_keypair()), Err(ChannelError::ParticipantMismatch));
+        claimed.sign(&receiver_keypair()).unwrap();
+        assert!(claimed.verify_transition(&paid, &clock()).is_ok());
+
+        // The settlement output must match the balance change and pay the receiver
+        let mut overstated = claimed.clone();
+        overstated.transition = ChannelTransition::Claim {
+            output: SettlementOutput { recipient: participants().receiver, amount: 20 },
+        };
+        overstated.sign(&receiver_keypair()).unwrap();
+        assert_eq!(
+            overstated.verify_transition(&paid, &clock()),
+            Err(ChannelError::CommitmentMismatch)
+        );
+
+        let mut redirected = claimed.clone();
+        redirected.transition = ChannelTransition::Claim {
+            output: SettlementOutput { recipient: participants().sender, amount: 15 },
+        };
+        redirected.sign(&receiver_keypair()).unwrap();
+        assert_eq!(
+            redirected.verify_transition(&paid, &clock()),
+            Err(ChannelError::SettlementMismatch)
+        );
+
+        // Without a settlement output the state is a non-conserving transfer
+        let mut unmatched = claimed.clone();
+        unmatched.transition = ChannelTransition::Transfer;
+        unmatched.sign(&sender_keypair()).unwrap();
+        assert_eq!(
+            unmatched.verify_transition(&paid, &clock()),
+            Err(ChannelError::CommitmentMismatch)
+        );
+
+        assert_eq!(paid.claim(0, &clock()), Err(ChannelError::InvalidZeroClaim));
+        assert_eq!(paid.claim(41, &clock()), Err(ChannelError::InsufficientBalance));
+    }
+
+    #[test]
+    fn test_claim_with_proof() -> Result<()> {
+        let mock = MockProofSystem::default();
+        let mut channel = ChannelState::new(100, Vec::new(), participants())?;
+        channel.apply_transfer(&mock, [1u8; 32], 30, &sender_keypair())?;
+        let prior = channel.clone();
+
+        let output = channel.claim_with_proof(&mock, [1u8; 32], 30, &receiver_keypair())?;
+        assert_eq!(output, SettlementOutput { recipient: participants().receiver, amount: 30 });
+        assert_eq!(channel.receiver_balance, 0);
+        assert!(channel.verify_transition(&prior, &clock()).is_ok());
+        assert_ne!(channel.proof, prior.proof);
+
+        // The sender can keep paying after the claim
+        let mut next = channel.transfer(10, &clock())?;
+        next.sign(&sender_keypair())?;
+        assert!(next.verify_transition(&channel, &clock()).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_batch() -> Result<()> {
+        let mock = MockProofSystem::default();
+        let mut channel = ChannelState::new(100, Vec::new(), participants())?;
+        channel.apply_transfer(&mock, [1u8; 32], 10, &sender_keypair())?;
+        let prior = channel.clone();
+
+        let batch = channel.apply_batch(&mock, [1u8; 32], &[1, 2, 3, 4], &sender_keypair())?;
+        assert_eq!((batch.start_nonce, batch.end_nonce), (1, 5));
+        assert_eq!(batch.total_amount, 10);
+        assert_eq!(channel.nonce, 5);
+        assert_eq!((channel.sender_balance, channel.receiver_balance), (80, 20));
+        assert_eq!(channel.transition, ChannelTransition::TransferBatch { count: 4, total: 10 });
+        assert!(channel.verify_transition(&prior, &clock()).is_ok());
+        assert!(batch.verify(&mock, &[1u8; 32], &prior, &channel).is_ok());
+
+        // Payments continue from the end of the batch
+        let mut next = channel.transfer(5, &clock())?;
+        next.sign(&sender_keypair())?;
+        assert!(next.verify_transition(&channel, &clock()).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_rejects_invalid() {
+        let mock = MockProofSystem::default();
+        let mut channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let prior = channel.clone();
+        assert_eq!(
+            channel.apply_batch(&mock, [1u8; 32], &[], &sender_keypair()),
+            Err(ChannelError::EmptyBatch)
+        );
+        assert_eq!(
+            channel.transfer_batch(&[5, 0], &clock()),
+            Err(ChannelError::InvalidZeroTransfer)
+        );
+        assert_eq!(
+            channel.transfer_batch(&[60, 50], &clock()),
+            Err(ChannelError::InsufficientBalance)
+        );
+        assert_eq!(
+            channel.transfer_batch(&[u64::MAX, 1], &clock()),
+            Err(ChannelError::BalanceOverflow)
+        );
+        assert_eq!(channel, prior);
+
+        let batch = channel.apply_batch(&mock, [1u8; 32], &[5, 5], &sender_keypair()).unwrap();
+
+        // The claimed aggregate must match the committed balance change
+        let mut understated = channel.clone();
+        understated.transition = ChannelTransition::TransferBatch { count: 2, total: 9 };
+        understated.sign(&sender_keypair()).unwrap();
+        assert_eq!(
+            understated.verify_transition(&prior, &clock()),
+            Err(ChannelError::CommitmentMismatch)
+        );
+
+        // The nonce must advance by exactly the batch size
+        let mut skipped = channel.clone();
+        skipped.nonce += 1;
+        skipped.sign(&sender_keypair()).unwrap();
+        assert_eq!(
+            skipped.verify_transition(&prior, &clock()),
+            Err(ChannelError::InvalidNonceIncrement)
+        );
+
+        let mut empty = channel.clone();
+        empty.transition = ChannelTransition::TransferBatch { count: 0, total: 10 };
+        assert_eq!(empty.verify_transition(&prior, &clock()), Err(ChannelError::EmptyBatch));
+
+        // The batch proof only verifies against the states it covers
+        let tampered = BatchProof { total_amount: 11, ..batch.clone() };
+        assert_eq!(
+            tampered.verify(&mock, &[1u8; 32], &prior, &channel),
+            Err(ChannelError::BatchProofMismatch)
+        );
+        assert_eq!(
+            batch.verify(&mock, &[1u8; 32], &channel, &channel),
+            Err(ChannelError::BatchProofMismatch)
+        );
+        assert_eq!(
+            batch.verify(&mock, &[2u8; 32], &prior, &channel),
+            Err(ChannelError::BatchProofMismatch)
+        );
+
+        // The proof itself must verify, not just match the state's copy
+        let mut envelope = mock.deserialize(&batch.proof).unwrap();
+        envelope.pi[0] ^= 1;
+        let forged = BatchProof { proof: mock.serialize(&envelope).unwrap(), ..batch.clone() };
+        let mut forged_state = channel.clone();
+        forged_state.proof = Some(forged.proof.clone());
+        assert_eq!(
+            forged.verify(&mock, &[1u8; 32], &prior, &forged_state),
+            Err(ChannelError::InvalidTransitionProof)
+        );
+    }
+
     #[test]
     fn test_update_in_tree() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
 
         let old = create_state(100, 0, 0);
         let new = create_state(90, 10, 1);
@@ -347,24 +1996,19 @@ mod tests {
         tree.insert(old_leaf)?;
 
         // Test successful update
-        let (new_leaf, new_root) = new.update_in_tree(&mut tree, &old)?;
+        let (new_leaf, new_root) = new.update_in_tree(&mut tree, &old, &clock())?;
         assert_ne!(new_leaf, [0u8; 32]);
         assert_ne!(new_root, [0u8; 32]);
 
         // Test invalid transition
         let invalid_state = create_state(90, 15, 2);
-        assert!(invalid_state.update_in_tree(&mut tree, &old).is_err());
-
-        // Test hash_state error
-        let mut invalid_hash_state = create_state(90, 10, 1);
-        invalid_hash_state.metadata = vec![0u8; 1000000];
-        assert!(invalid_hash_state.update_in_tree(&mut tree, &old).is_err());
+        assert!(invalid_state.update_in_tree(&mut tree, &old, &clock()).is_err());
 
         // Test MerkleTree update error
         let non_existent_old = create_state(200, 0, 0);
         let new_state = create_state(180, 20, 1);
         assert!(new_state
-            .update_in_tree(&mut tree, &non_existent_old)
+            .update_in_tree(&mut tree, &non_existent_old, &clock())
             .is_err());
 
         Ok(())
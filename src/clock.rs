@@ -0,0 +1,141 @@
+//! Time sources for proof freshness
+//!
+//! Proofs record the time, and optionally the block height, at which they
+//! were generated, and verifiers only accept them for a while afterwards.
+//! `Clock` abstracts over where the current reading comes from so that
+//! verification does not depend on the wall clock, and `ProofFreshness`
+//! decides how long a proof stays valid, in seconds or in blocks.
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::state::current_timestamp;
+use crate::state_proof::StateProof;
+
+/// Default number of seconds a proof stays fresh.
+pub const DEFAULT_FRESHNESS_WINDOW: u64 = 3600;
+
+/// Source of the current time and, if it follows a chain, block height.
+pub trait Clock: Debug + Send + Sync {
+    /// Current Unix time in seconds.
+    fn now(&self) -> u64;
+
+    /// Height of the current chain tip, if the clock follows a chain.
+    fn block_height(&self) -> Option<u64> { None }
+}
+
+/// The system wall clock. It does not follow a chain.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 { current_timestamp() }
+}
+
+/// Clock that only moves when told to, e.g. in tests and simulations.
+/// Clones share the same reading.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+    block_height: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Creates a clock reading `now` at `block_height`.
+    pub fn new(now: u64, block_height: u64) -> Self {
+        Self {
+            now: Arc::new(AtomicU64::new(now)),
+            block_height: Arc::new(AtomicU64::new(block_height)),
+        }
+    }
+
+    /// Sets the current time.
+    pub fn set(&self, now: u64) { self.now.store(now, Ordering::SeqCst) }
+
+    /// Moves the current time forward by `seconds`.
+    pub fn advance(&self, seconds: u64) { self.now.fetch_add(seconds, Ordering::SeqCst); }
+
+    /// Sets the current block height.
+    pub fn set_block_height(&self, block_height: u64) {
+        self.block_height.store(block_height, Ordering::SeqCst)
+    }
+
+    /// Moves the chain tip forward by `blocks`.
+    pub fn mine(&self, blocks: u64) { self.block_height.fetch_add(blocks, Ordering::SeqCst); }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 { self.now.load(Ordering::SeqCst) }
+
+    fn block_height(&self) -> Option<u64> { Some(self.block_height.load(Ordering::SeqCst)) }
+}
+
+/// How long a proof is accepted after it was generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofFreshness {
+    /// For the given number of seconds after the proof's timestamp
+    Seconds(u64),
+    /// For the given number of blocks after the height the proof was
+    /// generated at. Proofs without a block height are never fresh.
+    Blocks(u64),
+}
+
+impl Default for ProofFreshness {
+    fn default() -> Self { ProofFreshness::Seconds(DEFAULT_FRESHNESS_WINDOW) }
+}
+
+impl ProofFreshness {
+    /// Checks whether `proof` is still accepted according to `clock`.
+    pub fn is_fresh(&self, proof: &StateProof, clock: &dyn Clock) -> bool {
+        match *self {
+            ProofFreshness::Seconds(window) => {
+                clock.now().saturating_sub(proof.timestamp) <= window
+            }
+            ProofFreshness::Blocks(window) => match (proof.block_height, clock.block_height()) {
+                (Some(height), Some(tip)) => tip.saturating_sub(height) <= window,
+                _ => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof_system::ProofSystemId;
+
+    fn proof_at(clock: &dyn Clock) -> StateProof {
+        let mut proof =
+            StateProof::new(ProofSystemId::MockHash, Vec::new(), Vec::new(), clock.now());
+        proof.block_height = clock.block_height();
+        proof
+    }
+
+    #[test]
+    fn test_proof_freshness() {
+        let clock = ManualClock::new(1_000, 800_000);
+        let proof = proof_at(&clock);
+
+        // Clones share the reading
+        let handle = clock.clone();
+        handle.advance(60);
+        handle.mine(2);
+        assert_eq!((clock.now(), clock.block_height()), (1_060, Some(800_002)));
+
+        assert!(ProofFreshness::Seconds(60).is_fresh(&proof, &clock));
+        assert!(!ProofFreshness::Seconds(59).is_fresh(&proof, &clock));
+        assert!(ProofFreshness::Blocks(2).is_fresh(&proof, &clock));
+        assert!(!ProofFreshness::Blocks(1).is_fresh(&proof, &clock));
+
+        // Block-based freshness needs heights on both sides
+        let unanchored = proof_at(&SystemClock);
+        assert!(!ProofFreshness::Blocks(u64::MAX).is_fresh(&unanchored, &clock));
+        assert!(!ProofFreshness::Blocks(u64::MAX).is_fresh(&proof, &SystemClock));
+
+        clock.set(1_000 + DEFAULT_FRESHNESS_WINDOW);
+        assert!(ProofFreshness::default().is_fresh(&proof, &clock));
+        clock.advance(1);
+        assert!(!ProofFreshness::default().is_fresh(&proof, &clock));
+    }
+}
@@ -0,0 +1,184 @@
+//! Canonical conversions between bytes, integers and Goldilocks field elements
+//!
+//! Goldilocks elements hold values below `ORDER = 2^64 - 2^32 + 1`, so not
+//! every u64 is a field element. The conversions here either reject values
+//! outside the field or split them into 32-bit limbs, which always fit, so
+//! that every encoding is injective and decodes back to its input.
+
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::hash::hash_types::HashOut;
+use plonky2_field::types::{Field, Field64, PrimeField64};
+use thiserror::Error;
+
+use crate::types::Bytes32;
+
+type F = GoldilocksField;
+
+/// Number of 32-bit limbs in the encoding of a `Bytes32`.
+pub const BYTES32_LIMBS: usize = 8;
+
+/// Bytes per limb in `pack_bytes`. Seven bytes stay below the field order.
+pub const PACKED_LIMB_BYTES: usize = 7;
+
+/// Represents errors in field conversions.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    #[error("Value {0:#x} is not a canonical Goldilocks field element")]
+    NonCanonical(u64),
+}
+
+/// Converts a u64 to a field element, rejecting values at or above the
+/// field order.
+pub fn u64_to_field(value: u64) -> Result<F, CodecError> {
+    if value >= F::ORDER {
+        return Err(CodecError::NonCanonical(value));
+    }
+    Ok(F::from_canonical_u64(value))
+}
+
+/// Converts a field element back to the u64 it encodes.
+pub fn field_to_u64(element: F) -> u64 { element.to_canonical_u64() }
+
+/// Splits a u64 into `[low 32 bits, high 32 bits]`, which accepts every u64.
+pub fn u64_to_limbs(value: u64) -> [F; 2] {
+    [F::from_canonical_u32(value as u32), F::from_canonical_u32((value >> 32) as u32)]
+}
+
+/// Reassembles a u64 split by `u64_to_limbs`, rejecting limbs above 32 bits.
+pub fn limbs_to_u64(limbs: [F; 2]) -> Result<u64, CodecError> {
+    let [low, high] = limbs.map(field_to_u64);
+    if low > u32::MAX as u64 {
+        return Err(CodecError::NonCanonical(low));
+    }
+    if high > u32::MAX as u64 {
+        return Err(CodecError::NonCanonical(high));
+    }
+    Ok(high << 32 | low)
+}
+
+/// Splits 32 bytes into eight little-endian u32 limbs.
+pub fn bytes_to_limbs(bytes: &Bytes32) -> [F; BYTES32_LIMBS] {
+    let mut limbs = [F::ZERO; BYTES32_LIMBS];
+    for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(4)) {
+        let word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        *limb = F::from_canonical_u32(word);
+    }
+    limbs
+}
+
+/// Reassembles bytes split by `bytes_to_limbs`, rejecting limbs above 32 bits.
+pub fn limbs_to_bytes(limbs: &[F; BYTES32_LIMBS]) -> Result<Bytes32, CodecError> {
+    let mut bytes = [0u8; 32];
+    for (chunk, &limb) in bytes.chunks_exact_mut(4).zip(limbs) {
+        let word = field_to_u64(limb);
+        let word = u32::try_from(word).map_err(|_| CodecError::NonCanonical(word))?;
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Packs arbitrary bytes as `[length, 7-byte little-endian limbs...]`, with
+/// the last limb zero-padded. The length prefix keeps inputs that differ
+/// only in trailing zero bytes apart.
+pub fn pack_bytes(bytes: &[u8]) -> Vec<F> {
+    let mut elements = Vec::with_capacity(1 + bytes.len().div_ceil(PACKED_LIMB_BYTES));
+    elements.push(F::from_canonical_usize(bytes.len()));
+    for chunk in bytes.chunks(PACKED_LIMB_BYTES) {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        elements.push(F::from_canonical_u64(u64::from_le_bytes(word)));
+    }
+    elements
+}
+
+/// Encodes a hash as the little-endian bytes of its canonical elements.
+pub fn hash_out_to_bytes(hash: &HashOut<F>) -> Bytes32 {
+    let mut bytes = [0u8; 32];
+    for (chunk, element) in bytes.chunks_exact_mut(8).zip(hash.elements) {
+        chunk.copy_from_slice(&field_to_u64(element).to_le_bytes());
+    }
+    bytes
+}
+
+/// Decodes bytes produced by `hash_out_to_bytes`, rejecting chunks that are
+/// not canonical field elements.
+pub fn bytes_to_hash_out(bytes: &Bytes32) -> Result<HashOut<F>, CodecError> {
+    let mut elements = [F::ZERO; 4];
+    for (element, chunk) in elements.iter_mut().zip(bytes.chunks_exact(8)) {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(chunk);
+        *element = u64_to_field(u64::from_le_bytes(word))?;
+    }
+    Ok(HashOut { elements })
+}
+
+/// Encodes four public input elements holding a hash as bytes.
+pub fn elements_to_bytes(elements: &[F]) -> Bytes32 {
+    hash_out_to_bytes(&HashOut::from_partial(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_u64_field_conversions() {
+        assert_eq!(u64_to_field(F::ORDER - 1).map(field_to_u64), Ok(F::ORDER - 1));
+        assert_eq!(u64_to_field(F::ORDER), Err(CodecError::NonCanonical(F::ORDER)));
+        assert_eq!(u64_to_field(u64::MAX), Err(CodecError::NonCanonical(u64::MAX)));
+
+        // Limbs carry every u64, including values outside the field
+        for value in [0, 1, u32::MAX as u64 + 1, F::ORDER, u64::MAX] {
+            assert_eq!(limbs_to_u64(u64_to_limbs(value)), Ok(value));
+        }
+        let too_wide = [F::from_canonical_u64(1 << 32), F::ZERO];
+        assert_eq!(limbs_to_u64(too_wide), Err(CodecError::NonCanonical(1 << 32)));
+    }
+
+    #[test]
+    fn test_hash_out_round_trip() {
+        let largest = F::from_canonical_u64(F::ORDER - 1);
+        let hash = HashOut { elements: [F::ZERO, F::ONE, largest, F::from_canonical_u32(7)] };
+        let bytes = hash_out_to_bytes(&hash);
+        assert_eq!(bytes_to_hash_out(&bytes), Ok(hash));
+        assert_eq!(elements_to_bytes(&hash.elements), bytes);
+
+        // Chunks at or above the field order have no element to decode to
+        let mut invalid = bytes;
+        invalid[..8].copy_from_slice(&F::ORDER.to_le_bytes());
+        assert_eq!(bytes_to_hash_out(&invalid), Err(CodecError::NonCanonical(F::ORDER)));
+        assert!(bytes_to_hash_out(&[0xff; 32]).is_err());
+    }
+
+    #[test]
+    fn test_bytes_to_limbs() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 1;
+        bytes[31] = 0x80;
+        let limbs = bytes_to_limbs(&bytes);
+        assert_eq!(limbs[0], F::ONE);
+        assert_eq!(limbs[7], F::from_canonical_u32(0x8000_0000));
+        assert_ne!(bytes_to_limbs(&[1u8; 32]), bytes_to_limbs(&[2u8; 32]));
+        assert_eq!(limbs_to_bytes(&limbs), Ok(bytes));
+
+        let mut too_wide = limbs;
+        too_wide[3] = F::from_canonical_u64(1 << 32);
+        assert_eq!(limbs_to_bytes(&too_wide), Err(CodecError::NonCanonical(1 << 32)));
+    }
+
+    #[test]
+    fn test_pack_bytes() {
+        assert_eq!(pack_bytes(&[]), vec![F::ZERO]);
+
+        let packed = pack_bytes(&[0xff; 15]);
+        assert_eq!(packed.len(), 4);
+        assert_eq!(packed[0], F::from_canonical_u64(15));
+        assert_eq!(packed[1], F::from_canonical_u64((1 << 56) - 1));
+        assert_eq!(packed[3], F::from_canonical_u64(0xff));
+
+        // Trailing zero bytes change the length prefix
+        assert_ne!(pack_bytes(b"ab"), pack_bytes(b"ab\0"));
+        let len = 1usize << 20;
+        assert_eq!(pack_bytes(&vec![7u8; len]).len(), 1 + len.div_ceil(PACKED_LIMB_BYTES));
+    }
+}
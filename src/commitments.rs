@@ -1,9 +1,10 @@
 // src/zkp/commitments.rs
 
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
 use rand::rngs::OsRng;
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 use crate::pedersen_parameters::PedersenParameters;
@@ -33,6 +34,216 @@ pub fn pedersen_commit(
     hash_point(commitment)
 }
 
+/// Pedersen commitment to a single balance, kept as a curve point
+/// together with its blinding factor.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BalanceCommitment {
+    /// Compressed Ristretto commitment point
+    pub point: Bytes32,
+    /// Blinding factor used for the commitment
+    pub blinding: Bytes32,
+}
+
+impl BalanceCommitment {
+    /// Commits to `value` with an explicit blinding scalar.
+    pub fn new(value: u64, blinding: Scalar, hparams: &PedersenParameters) -> Self {
+        let point = hparams.g * Scalar::from(value) + hparams.h * blinding;
+        Self { point: point.compress().to_bytes(), blinding: blinding.to_bytes() }
+    }
+
+    /// Decompresses the commitment point, if it is a valid Ristretto encoding.
+    pub fn decompress(&self) -> Option<Point> { CompressedRistretto(self.point).decompress() }
+
+    /// Returns the blinding factor as a scalar.
+    pub fn blinding_scalar(&self) -> Scalar { Scalar::from_bytes_mod_order(self.blinding) }
+
+    /// Checks that `value` and `blinding` reproduce this commitment point.
+    pub fn verify(&self, value: u64, blinding: Bytes32, hparams: &PedersenParameters) -> bool {
+        let Some(point) = self.decompress() else {
+            return false;
+        };
+        point == hparams.g * Scalar::from(value) + hparams.h * Scalar::from_bytes_mod_order(blinding)
+    }
+}
+
+/// Values revealed when opening a `ChannelCommitment`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommitmentOpening {
+    /// Committed balance of the sender
+    pub sender_balance: u64,
+    /// Committed balance of the receiver
+    pub receiver_balance: u64,
+    /// Blinding factor of the sender commitment
+    pub sender_blinding: Bytes32,
+    /// Blinding factor of the receiver commitment
+    pub receiver_blinding: Bytes32,
+}
+
+/// Separate Pedersen commitments to a channel's sender and receiver balances.
+///
+/// The points stay additively homomorphic, so balance conservation between two
+/// states can be checked from the points alone (see `conserves_total`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChannelCommitment {
+    /// Commitment to the sender balance
+    pub sender: BalanceCommitment,
+    /// Commitment to the receiver balance
+    pub receiver: BalanceCommitment,
+}
+
+impl ChannelCommitment {
+    /// Commits to the given balances with explicit blinding factors.
+    pub fn new(
+        sender_balance: u64,
+        receiver_balance: u64,
+        sender_blinding: Bytes32,
+        receiver_blinding: Bytes32,
+        hparams: &PedersenParameters,
+    ) -> Self {
+        Self {
+            sender: BalanceCommitment::new(
+                sender_balance,
+                Scalar::from_bytes_mod_order(sender_blinding),
+                hparams,
+            ),
+            receiver: BalanceCommitment::new(
+                receiver_balance,
+                Scalar::from_bytes_mod_order(receiver_blinding),
+                hparams,
+            ),
+        }
+    }
+
+    /// Commits to the given balances with fresh random blinding factors.
+    pub fn random(sender_balance: u64, receiver_balance: u64, hparams: &PedersenParameters) -> Self {
+        Self::new(
+            sender_balance,
+            receiver_balance,
+            generate_random_blinding(),
+            generate_random_blinding(),
+            hparams,
+        )
+    }
+
+    /// Commits to the balances of the next state.
+    ///
+    /// The sender blinding is fresh, while the receiver blinding is chosen so the
+    /// sum of both blindings is unchanged. This keeps `conserves_total` checkable
+    /// from the points alone.
+    pub fn next(
+        &self,
+        sender_balance: u64,
+        receiver_balance: u64,
+        hparams: &PedersenParameters,
+    ) -> Self {
+        let total_blinding = self.sender.blinding_scalar() + self.receiver.blinding_scalar();
+        let sender_blinding = Scalar::from_bytes_mod_order(generate_random_blinding());
+        Self {
+            sender: BalanceCommitment::new(sender_balance, sender_blinding, hparams),
+            receiver: BalanceCommitment::new(
+                receiver_balance,
+                total_blinding - sender_blinding,
+                hparams,
+            ),
+        }
+    }
+
+    /// Returns the sum of the sender and receiver commitment points,
+    /// which commits to the channel's total balance.
+    pub fn total_point(&self) -> Option<Point> {
+        Some(self.sender.decompress()? + self.receiver.decompress()?)
+    }
+
+    /// Checks that this commitment holds the same total balance as `prior`,
+    /// using only the commitment points.
+    pub fn conserves_total(&self, prior: &ChannelCommitment) -> bool {
+        match (self.total_point(), prior.total_point()) {
+            (Some(next_total), Some(prior_total)) => next_total == prior_total,
+            _ => false,
+        }
+    }
+
+    /// Checks that this commitment holds the total balance of `prior` minus a
+    /// public `amount`, using only the commitment points.
+    pub fn decreases_total_by(
+        &self,
+        prior: &ChannelCommitment,
+        amount: u64,
+        hparams: &PedersenParameters,
+    ) -> bool {
+        match (self.total_point(), prior.total_point()) {
+            (Some(next_total), Some(prior_total)) => {
+                next_total == prior_total - hparams.g * Scalar::from(amount)
+            }
+            _ => false,
+        }
+    }
+
+    /// Checks that this commitment holds the total balance of `prior` plus a
+    /// public `amount`, using only the commitment points.
+    pub fn increases_total_by(
+        &self,
+        prior: &ChannelCommitment,
+        amount: u64,
+        hparams: &PedersenParameters,
+    ) -> bool {
+        match (self.total_point(), prior.total_point()) {
+            (Some(next_total), Some(prior_total)) => {
+                next_total == prior_total + hparams.g * Scalar::from(amount)
+            }
+            _ => false,
+        }
+    }
+
+    /// Checks that this commitment holds the balances of `prior` with a public
+    /// `amount` moved from the sender to the receiver. Besides the points, this
+    /// uses the two sender blindings, which reveal the amount but not the balances.
+    pub fn moves_to_receiver(
+        &self,
+        prior: &ChannelCommitment,
+        amount: u64,
+        hparams: &PedersenParameters,
+    ) -> bool {
+        let (Some(prior_sender), Some(sender)) =
+            (prior.sender.decompress(), self.sender.decompress())
+        else {
+            return false;
+        };
+        let blinding_delta = prior.sender.blinding_scalar() - self.sender.blinding_scalar();
+        self.conserves_total(prior)
+            && prior_sender - sender
+                == hparams.g * Scalar::from(amount) + hparams.h * blinding_delta
+    }
+
+    /// Opens the commitment for the given balances, revealing its blinding factors.
+    pub fn open(&self, sender_balance: u64, receiver_balance: u64) -> CommitmentOpening {
+        CommitmentOpening {
+            sender_balance,
+            receiver_balance,
+            sender_blinding: self.sender.blinding,
+            receiver_blinding: self.receiver.blinding,
+        }
+    }
+
+    /// Checks that `opening` reproduces both commitment points.
+    /// Only the points are trusted; the blindings are taken from the opening.
+    pub fn verify_opening(&self, opening: &CommitmentOpening, hparams: &PedersenParameters) -> bool {
+        self.sender.verify(opening.sender_balance, opening.sender_blinding, hparams)
+            && self.receiver.verify(opening.receiver_balance, opening.receiver_blinding, hparams)
+    }
+
+    /// Hashes both commitment points to bytes32, as used in state proofs.
+    pub fn hash(&self) -> Bytes32 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.sender.point);
+        hasher.update(self.receiver.point);
+        let result = hasher.finalize();
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&result);
+        hash
+    }
+}
+
 /// Hashes a RistrettoPoint to bytes32 using SHA256.
 pub fn hash_point(point: Point) -> Bytes32 {
     let mut hasher = Sha256::new();
@@ -67,6 +278,74 @@ mod tests {
         assert_eq!(c1, c2);
     }
 
+    #[test]
+    fn test_channel_commitment_open_and_verify() {
+        let params = PedersenParameters::default();
+        let commitment = ChannelCommitment::random(70, 30, &params);
+
+        let opening = commitment.open(70, 30);
+        assert!(commitment.verify_opening(&opening, &params));
+
+        // Opening to different balances must fail
+        assert!(!commitment.verify_opening(&commitment.open(30, 70), &params));
+        assert!(!commitment.verify_opening(&commitment.open(71, 29), &params));
+
+        // Opening with a different blinding must fail
+        let mut wrong_blinding = opening.clone();
+        wrong_blinding.sender_blinding = generate_random_blinding();
+        assert!(!commitment.verify_opening(&wrong_blinding, &params));
+    }
+
+    #[test]
+    fn test_channel_commitment_conserves_total() {
+        let params = PedersenParameters::default();
+        let prior = ChannelCommitment::random(100, 0, &params);
+
+        // A transfer keeps the total and the blinding sum
+        let next = prior.next(70, 30, &params);
+        assert!(next.conserves_total(&prior));
+        assert!(next.verify_opening(&next.open(70, 30), &params));
+
+        // Changing the total is detected from the points alone
+        let inflated = prior.next(70, 31, &params);
+        assert!(!inflated.conserves_total(&prior));
+
+        // Independent blindings do not satisfy the check
+        let unrelated = ChannelCommitment::random(70, 30, &params);
+        assert!(!unrelated.conserves_total(&prior));
+    }
+
+    #[test]
+    fn test_channel_commitment_increases_total_by() {
+        let params = PedersenParameters::default();
+        let prior = ChannelCommitment::random(70, 30, &params);
+
+        let topped_up = prior.next(120, 30, &params);
+        assert!(topped_up.increases_total_by(&prior, 50, &params));
+        assert!(!topped_up.increases_total_by(&prior, 49, &params));
+        assert!(!topped_up.conserves_total(&prior));
+        assert!(prior.next(70, 30, &params).increases_total_by(&prior, 0, &params));
+
+        let claimed = prior.next(70, 10, &params);
+        assert!(claimed.decreases_total_by(&prior, 20, &params));
+        assert!(!claimed.decreases_total_by(&prior, 21, &params));
+        assert!(!claimed.increases_total_by(&prior, 20, &params));
+    }
+
+    #[test]
+    fn test_channel_commitment_moves_to_receiver() {
+        let params = PedersenParameters::default();
+        let prior = ChannelCommitment::random(100, 0, &params);
+
+        let paid = prior.next(70, 30, &params);
+        assert!(paid.moves_to_receiver(&prior, 30, &params));
+        assert!(!paid.moves_to_receiver(&prior, 29, &params));
+
+        // The total must be conserved as well
+        let inflated = prior.next(70, 31, &params);
+        assert!(!inflated.moves_to_receiver(&prior, 30, &params));
+    }
+
     #[test]
     fn test_hash_point_output_length() {
         let point = RistrettoPoint::default();
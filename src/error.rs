@@ -6,6 +6,7 @@
 
 use thiserror::Error;
 
+use crate::channel::ChannelLifecycle;
 use crate::types::ChannelId;
 use crate::types::WalletId;
 
@@ -86,6 +87,86 @@ pub enum ChannelError {
     /// Invalid balance change
     #[error("Invalid balance change")]
     InvalidBalanceChange,
+
+    /// Balance commitments do not conserve the channel total
+    #[error("Balance commitments do not conserve the channel total")]
+    CommitmentMismatch,
+
+    /// Range proof for committed balances is missing or invalid
+    #[error("Range proof for committed balances is missing or invalid")]
+    InvalidRangeProof,
+
+    /// State update is not signed
+    #[error("State update is not signed")]
+    MissingSignature,
+
+    /// State update signature does not verify against the sender key
+    #[error("Invalid state update signature")]
+    InvalidSignature,
+
+    /// Participant keys do not match the channel
+    #[error("Participant keys do not match the channel")]
+    ParticipantMismatch,
+
+    /// Channel state could not be hashed
+    #[error("Failed to hash channel state")]
+    StateHashFailed,
+
+    /// Channel does not accept updates in its current lifecycle stage
+    #[error("Channel is not open: {0:?}")]
+    ChannelNotOpen(ChannelLifecycle),
+
+    /// Lifecycle move is not allowed
+    #[error("Invalid lifecycle transition from {from:?} to {to:?}")]
+    InvalidLifecycleTransition { from: ChannelLifecycle, to: ChannelLifecycle },
+
+    /// Channel has expired and accepts no further transfers
+    #[error("Channel has expired")]
+    ChannelExpired,
+
+    /// Channel has no expiry or has not expired yet
+    #[error("Channel has not expired")]
+    ChannelNotExpired,
+
+    /// Channel expiry differs between states
+    #[error("Channel expiry does not match")]
+    ExpiryMismatch,
+
+    /// Deposit amount cannot be zero
+    #[error("Deposit amount cannot be zero")]
+    InvalidZeroDeposit,
+
+    /// Deposit names no funding outpoint or commitment
+    #[error("Deposit is not backed by a funding outpoint or commitment")]
+    MissingFunding,
+
+    /// Claim amount cannot be zero
+    #[error("Claim amount cannot be zero")]
+    InvalidZeroClaim,
+
+    /// Claim is not matched by a settlement output paying the receiver
+    #[error("Settlement output does not match the claim")]
+    SettlementMismatch,
+
+    /// Batch must contain at least one transfer
+    #[error("Transfer batch cannot be empty")]
+    EmptyBatch,
+
+    /// Batch proof does not cover the given states
+    #[error("Batch proof does not match the channel states")]
+    BatchProofMismatch,
+
+    /// Transition proof could not be generated
+    #[error("Failed to generate transition proof")]
+    ProofGenerationFailed,
+
+    /// Transition proof does not verify against the channel states
+    #[error("Invalid transition proof")]
+    InvalidTransitionProof,
+
+    /// State was not produced by a transition valid in this context
+    #[error("Invalid transition kind")]
+    InvalidTransitionKind,
 }
 
 /// Errors that can occur during wallet operations
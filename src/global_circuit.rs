@@ -0,0 +1,293 @@
+//! Epoch proofs of the global root
+//!
+//! The global root contract commits to the state roots of its wallets with a
+//! Poseidon state tree over the wallet roots, sorted by wallet ID, built as in
+//! `merkle_circuit`. `GlobalRootCircuit` proves that the global state root
+//! moved from the root anchored at the start of an epoch to a new value by
+//! applying proven wallet transitions: each update slot recursively verifies a
+//! `WalletTransitionCircuit` proof and replaces the wallet's old root by its
+//! new one in the tree, starting from the root left by the previous slot.
+//!
+//! The circuit has a fixed number of update slots and supports trees up to
+//! `GLOBAL_TREE_MAX_DEPTH`. Unused slots are flagged inactive; they verify a
+//! padding wallet proof but leave the root unchanged.
+//!
+//! Public inputs are
+//! `[anchored global root (4), new global root (4), epoch, wallet updates]`.
+
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use anyhow::{anyhow, Context, Result};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::hash::hash_types::HashOutTarget;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+
+use crate::codec::{bytes_to_hash_out, elements_to_bytes, field_to_u64, u64_to_field};
+use crate::merkle_circuit::{MerkleUpdateTargets, StateTreePath};
+use crate::types::Bytes32;
+use crate::wallet_circuit::{WalletTransitionCircuit, WalletTransitionProof};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Deepest global state tree supported by `GlobalRootCircuit`.
+pub const GLOBAL_TREE_MAX_DEPTH: usize = 16;
+
+/// Maximum number of wallet updates proven by one epoch proof.
+pub const GLOBAL_UPDATE_SLOTS: usize = 4;
+
+/// Index of the epoch number in an epoch proof's public inputs.
+const EPOCH_INDEX: usize = 8;
+
+/// Index of the wallet update count in an epoch proof's public inputs.
+const UPDATES_INDEX: usize = 9;
+
+/// A proven wallet transition and the path of the wallet's leaf in the global
+/// state tree it is applied to.
+#[derive(Debug, Clone)]
+pub struct GlobalUpdateStep {
+    /// `WalletTransitionCircuit` proof of the wallet root transition
+    pub wallet_proof: WalletTransitionProof,
+    /// Path of the wallet's leaf in the tree before the update
+    pub path: StateTreePath,
+}
+
+/// Proof that the global state root moved from `old_root` to `new_root` during
+/// `epoch` by applying `wallet_updates` proven wallet transitions.
+#[derive(Debug, Clone)]
+pub struct GlobalRootProof {
+    /// The epoch proof
+    pub proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl GlobalRootProof {
+    /// Global state root anchored at the start of the epoch.
+    pub fn old_root(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[0..4]) }
+
+    /// Global state root at the end of the epoch.
+    pub fn new_root(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[4..8]) }
+
+    /// Epoch the proof was generated for.
+    pub fn epoch(&self) -> u64 { field_to_u64(self.proof.public_inputs[EPOCH_INDEX]) }
+
+    /// Number of wallet updates covered by the proof.
+    pub fn wallet_updates(&self) -> u64 {
+        field_to_u64(self.proof.public_inputs[UPDATES_INDEX])
+    }
+}
+
+/// Targets of one update slot.
+struct UpdateSlotTargets {
+    active: BoolTarget,
+    wallet_proof: ProofWithPublicInputsTarget<D>,
+    update: MerkleUpdateTargets,
+}
+
+/// Circuit proving a batch of wallet updates to the global state tree.
+pub struct GlobalRootCircuit {
+    circuit_data: CircuitData<F, C, D>,
+    old_root_target: HashOutTarget,
+    epoch_target: Target,
+    slots: Vec<UpdateSlotTargets>,
+    /// Valid wallet proof verified by inactive slots
+    padding_proof: WalletTransitionProof,
+}
+
+impl GlobalRootCircuit {
+    /// Builds the circuit for proofs of `wallet_circuit`.
+    pub fn new(wallet_circuit: &WalletTransitionCircuit) -> Result<Self> {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+
+        let inner = wallet_circuit.circuit_data();
+        let inner_verifier_target = builder.constant_verifier_data(&inner.verifier_only);
+
+        let old_root_target = builder.add_virtual_hash();
+        let epoch_target = builder.add_virtual_target();
+        let mut root = old_root_target;
+        let mut updates = builder.zero();
+        let mut slots = Vec::with_capacity(GLOBAL_UPDATE_SLOTS);
+        for _ in 0..GLOBAL_UPDATE_SLOTS {
+            let active = builder.add_virtual_bool_target_safe();
+            let wallet_proof = builder.add_virtual_proof_with_pis(&inner.common);
+            builder.verify_proof::<C>(&wallet_proof, &inner_verifier_target, &inner.common);
+
+            // The replaced leaves are the wallet roots the wallet proof moves.
+            let update = MerkleUpdateTargets::add_up_to(&mut builder, GLOBAL_TREE_MAX_DEPTH);
+            for i in 0..4 {
+                builder.connect(update.old_leaf.elements[i], wallet_proof.public_inputs[i]);
+                builder.connect(update.new_leaf.elements[i], wallet_proof.public_inputs[4 + i]);
+            }
+
+            // An active slot must start from the current root and moves it on.
+            let mut next_root = root;
+            for i in 0..4 {
+                let gap = builder.sub(update.old_root.elements[i], root.elements[i]);
+                let checked_gap = builder.mul(active.target, gap);
+                builder.assert_zero(checked_gap);
+                next_root.elements[i] =
+                    builder.select(active, update.new_root.elements[i], root.elements[i]);
+            }
+            root = next_root;
+            updates = builder.add(updates, active.target);
+
+            slots.push(UpdateSlotTargets { active, wallet_proof, update });
+        }
+
+        builder.register_public_inputs(&old_root_target.elements);
+        builder.register_public_inputs(&root.elements);
+        builder.register_public_input(epoch_target);
+        builder.register_public_input(updates);
+
+        let circuit_data = builder.build::<C>();
+
+        // A wallet proof without updates keeps the empty root and pads
+        // inactive slots.
+        let padding_proof = wallet_circuit
+            .prove(&[0u8; 32], &[], 0, None)
+            .context("Failed to generate padding proof")?;
+
+        Ok(Self { circuit_data, old_root_target, epoch_target, slots, padding_proof })
+    }
+
+    /// Gets a circuit for `WalletTransitionCircuit::shared` proofs, built on
+    /// first use.
+    pub fn shared() -> Result<&'static Self> {
+        static CIRCUIT: OnceLock<GlobalRootCircuit> = OnceLock::new();
+        static BUILD: Mutex<()> = Mutex::new(());
+        if let Some(circuit) = CIRCUIT.get() {
+            return Ok(circuit);
+        }
+        // Serialized as in `WalletTransitionCircuit::shared`.
+        let _build = BUILD.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(circuit) = CIRCUIT.get() {
+            return Ok(circuit);
+        }
+        let circuit = Self::new(WalletTransitionCircuit::shared()?)?;
+        Ok(CIRCUIT.get_or_init(|| circuit))
+    }
+
+    /// Gets the underlying circuit data, e.g. to hand its verifier data to an
+    /// external verifier.
+    pub fn circuit_data(&self) -> &CircuitData<F, C, D> { &self.circuit_data }
+
+    /// Proves applying `steps`, in order, to the global state tree with root
+    /// `anchored_root` during `epoch`.
+    pub fn prove(
+        &self,
+        anchored_root: &Bytes32,
+        epoch: u64,
+        steps: &[GlobalUpdateStep],
+    ) -> Result<GlobalRootProof> {
+        if steps.len() > GLOBAL_UPDATE_SLOTS {
+            return Err(anyhow!(
+                "{} updates exceed the {GLOBAL_UPDATE_SLOTS} slots of an epoch proof",
+                steps.len()
+            ));
+        }
+
+        // Check the chain natively rather than failing inside the prover.
+        let mut root = *anchored_root;
+        for (i, step) in steps.iter().enumerate() {
+            if step.path.compute_root(&step.wallet_proof.old_root())? != root {
+                return Err(anyhow!("Update {i} does not apply to the current global root"));
+            }
+            root = step.path.compute_root(&step.wallet_proof.new_root())?;
+        }
+
+        let mut pw = PartialWitness::new();
+        pw.set_hash_target(self.old_root_target, bytes_to_hash_out(anchored_root)?)
+            .context("Failed to set anchored root")?;
+        pw.set_target(self.epoch_target, u64_to_field(epoch)?).context("Failed to set epoch")?;
+        let padding_path = StateTreePath { index: 0, siblings: Vec::new() };
+        for (i, slot) in self.slots.iter().enumerate() {
+            let (proof, path) = match steps.get(i) {
+                Some(step) => (&step.wallet_proof, &step.path),
+                None => (&self.padding_proof, &padding_path),
+            };
+            pw.set_bool_target(slot.active, i < steps.len())
+                .context("Failed to set slot flag")?;
+            pw.set_proof_with_pis_target(&slot.wallet_proof, &proof.proof)
+                .context("Failed to set wallet proof")?;
+            slot.update.set_witness(&mut pw, &proof.old_root(), &proof.new_root(), path)?;
+        }
+
+        let proof = self.circuit_data.prove(pw).context("Epoch proof generation failed")?;
+        Ok(GlobalRootProof { proof })
+    }
+
+    /// Verifies an epoch proof.
+    pub fn verify(&self, proof: &GlobalRootProof) -> Result<()> {
+        self.circuit_data.verify(proof.proof.clone()).context("Epoch proof verification failed")
+    }
+
+    /// Verifies that `proof` is the proof of `epoch` and starts from the
+    /// global root anchored for it.
+    pub fn verify_epoch(
+        &self,
+        anchored_root: &Bytes32,
+        epoch: u64,
+        proof: &GlobalRootProof,
+    ) -> Result<()> {
+        if proof.epoch() != epoch {
+            return Err(anyhow!("Proof is for epoch {}, expected {epoch}", proof.epoch()));
+        }
+        if proof.old_root() != *anchored_root {
+            return Err(anyhow!("Proof does not start from the anchored root"));
+        }
+        self.verify(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{ChannelParticipants, ChannelState};
+    use crate::merkle_circuit::state_tree_root;
+    use crate::state::hash_state;
+    use crate::state_transition::StateTransitionCircuit;
+    use crate::wallet_circuit::WalletUpdateStep;
+
+    #[test]
+    fn test_global_root_proof() -> Result<()> {
+        let circuit = GlobalRootCircuit::shared()?;
+        let wallet_circuit = WalletTransitionCircuit::shared()?;
+
+        // A wallet with a single channel, whose state root is the channel hash
+        let participants = ChannelParticipants { sender: [2u8; 32], receiver: [3u8; 32] };
+        let channel = ChannelState::new(100, Vec::new(), participants)?;
+        let wallet_root = hash_state(&channel)?;
+        let transition_proof =
+            StateTransitionCircuit::shared().prove_transfer(&[1u8; 32], &channel, 10)?;
+        let step = WalletUpdateStep {
+            transition_proof,
+            path: StateTreePath::new(&[wallet_root], 0)?,
+        };
+        let wallet_proof = wallet_circuit.prove(&wallet_root, &[step], 1_000, None)?;
+
+        let mut leaves = vec![[1u8; 32], wallet_root, [2u8; 32]];
+        let anchored_root = state_tree_root(&leaves)?;
+        let path = StateTreePath::new(&leaves, 1)?;
+        leaves[1] = wallet_proof.new_root();
+        let steps = [GlobalUpdateStep { wallet_proof, path }];
+
+        let proof = circuit.prove(&anchored_root, 7, &steps)?;
+        circuit.verify_epoch(&anchored_root, 7, &proof)?;
+        assert_eq!(proof.new_root(), state_tree_root(&leaves)?);
+        assert_eq!(proof.wallet_updates(), 1);
+
+        // The proof is bound to its epoch and anchored root
+        assert!(circuit.verify_epoch(&anchored_root, 8, &proof).is_err());
+        assert!(circuit.verify_epoch(&proof.new_root(), 7, &proof).is_err());
+
+        // Updates must apply to the root they are proven against
+        assert!(circuit.prove(&proof.new_root(), 8, &steps).is_err());
+        Ok(())
+    }
+}
@@ -1,16 +1,22 @@
 // src/zkp/global_root_contract.rs
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use anyhow::Result;
 use thiserror::Error;
 
-use super::state::StateProof as HelperStateProof;
-use super::state_proof::{self, StateProof};
 use super::tree::{MerkleTree, MerkleTreeError};
+use crate::clock::{Clock, ProofFreshness, SystemClock};
+use crate::codec::bytes_to_hash_out;
+use crate::global_circuit::{
+    GlobalRootCircuit, GlobalRootProof, GlobalUpdateStep, GLOBAL_UPDATE_SLOTS,
+};
 use crate::merkle::compute_global_root;
+use crate::merkle_circuit::{state_tree_root, StateTreePath};
 use crate::pedersen_parameters::{PedersenParameters, SerdePedersenParameters};
-use crate::state::verify_wallet_proof;
+use crate::proof_system::{Plonky2ProofSystem, ProofSystem, ProofSystemError};
+use crate::state_proof::StateProof;
 use crate::types::Bytes32;
 
 /// Represents errors in GlobalRootContract operations.
@@ -25,6 +31,15 @@ pub enum GlobalRootContractError {
     #[error("Proof verification failed")]
     ProofVerificationFailed,
 
+    #[error("Proof is no longer fresh")]
+    StaleProof,
+
+    #[error("Epoch already holds the maximum number of wallet updates")]
+    EpochFull,
+
+    #[error("Epoch has updates waiting for their epoch proof")]
+    EpochInProgress,
+
     #[error("Invalid input: {0}")]
     InvalidInput(String),
 
@@ -47,28 +62,76 @@ impl From<anyhow::Error> for GlobalRootContractError {
     }
 }
 
+/// Move of the anchored root by a wallet registration.
+///
+/// Registrations insert a leaf into the global state tree, which epoch proofs
+/// cannot prove, so the contract publishes them instead. An external verifier
+/// chains the epoch proofs through them: the proof of `epoch` starts from the
+/// `new_root` of the last re-anchor of that epoch, or from the end of the
+/// previous epoch proof if there is none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reanchor {
+    /// Epoch whose proof starts from `new_root`
+    pub epoch: u64,
+    /// Registered wallet
+    pub wallet_id: Bytes32,
+    /// Initial root of the registered wallet
+    pub wallet_root: Bytes32,
+    /// Anchored root before the registration
+    pub old_root: Bytes32,
+    /// Anchored root after the registration
+    pub new_root: Bytes32,
+}
+
+impl Reanchor {
+    /// Checks that registering the wallet next to the wallets in
+    /// `wallet_roots`, which must not include it, moves `old_root` to
+    /// `new_root`.
+    pub fn verify(&self, wallet_roots: &HashMap<Bytes32, Bytes32>) -> bool {
+        if wallet_roots.contains_key(&self.wallet_id) {
+            return false;
+        }
+        let mut registered = wallet_roots.clone();
+        registered.insert(self.wallet_id, self.wallet_root);
+        let root = |roots: &HashMap<Bytes32, Bytes32>| state_tree_root(&sorted_wallet_roots(roots));
+        matches!(
+            (root(wallet_roots), root(&registered)),
+            (Ok(old_root), Ok(new_root)) if (old_root, new_root) == (self.old_root, self.new_root)
+        )
+    }
+}
+
 #[derive(Debug)]
-/// Global Root Contract manages wallet roots and their proofs.
-pub struct GlobalRootContract {
+/// Global Root Contract manages wallet roots and their proofs, verifying
+/// wallet updates with the proof system `P`.
+pub struct GlobalRootContract<P = Plonky2ProofSystem> {
     wallet_roots: HashMap<Bytes32, Bytes32>,
     latest_proofs: HashMap<Bytes32, StateProof>,
     params: PedersenParameters,
     merkle_root: Bytes32,
     merkle_tree: MerkleTree,
+    /// Poseidon state tree root over the wallet roots, which epoch proofs
+    /// prove in-circuit; see `global_circuit`.
+    state_root: Bytes32,
+    /// State root at the start of the current epoch
+    anchored_root: Bytes32,
+    epoch: u64,
+    /// Wallet updates of the current epoch and the paths of their leaves,
+    /// in the order they were applied
+    pending_updates: Vec<(StateProof, StateTreePath)>,
+    /// Published moves of the anchored root by registrations
+    reanchors: Vec<Reanchor>,
+    proof_system: P,
+    clock: Arc<dyn Clock>,
+    /// How long wallet update proofs are accepted
+    freshness: ProofFreshness,
 }
 
 impl GlobalRootContract {
-    /// Creates a new GlobalRootContract with given Pedersen parameters.
+    /// Creates a new GlobalRootContract with given Pedersen parameters,
+    /// verifying plonky2 wallet proofs.
     pub fn new(params: PedersenParameters) -> Self {
-        let merkle_tree = MerkleTree::new();
-        let merkle_root = merkle_tree.root;
-        Self {
-            wallet_roots: HashMap::new(),
-            latest_proofs: HashMap::new(),
-            params,
-            merkle_root,
-            merkle_tree,
-        }
+        Self::with_proof_system(params, Plonky2ProofSystem::default())
     }
 
     /// Saves PedersenParameters to a file in serialized form.
@@ -93,7 +156,70 @@ impl GlobalRootContract {
         Ok(serde_params.into())
     }
 
+    /// Proves the wallet updates of the current epoch with
+    /// `GlobalRootCircuit`, then starts the next epoch anchored at the
+    /// current state root. The proof can be checked with
+    /// `GlobalRootCircuit::verify_epoch` against the root that was anchored
+    /// for the epoch. Only plonky2 wallet proofs can be verified in-circuit.
+    pub fn prove_epoch(&mut self) -> Result<GlobalRootProof, GlobalRootContractError> {
+        let steps = self
+            .pending_updates
+            .iter()
+            .map(|(proof, path)| {
+                Ok(GlobalUpdateStep {
+                    wallet_proof: self.proof_system.wallet_proof(proof)?,
+                    path: path.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, ProofSystemError>>()
+            .map_err(|e| GlobalRootContractError::ComputationError(e.to_string()))?;
+        let proof = GlobalRootCircuit::shared()?.prove(&self.anchored_root, self.epoch, &steps)?;
+
+        self.anchored_root = self.state_root;
+        self.epoch += 1;
+        self.pending_updates.clear();
+        Ok(proof)
+    }
+}
+
+impl<P: ProofSystem> GlobalRootContract<P> {
+    /// Creates a new GlobalRootContract verifying wallet proofs of
+    /// `proof_system`.
+    pub fn with_proof_system(params: PedersenParameters, proof_system: P) -> Self {
+        let merkle_tree = MerkleTree::new();
+        let merkle_root = merkle_tree.root;
+        Self {
+            wallet_roots: HashMap::new(),
+            latest_proofs: HashMap::new(),
+            params,
+            merkle_root,
+            merkle_tree,
+            state_root: [0u8; 32],
+            anchored_root: [0u8; 32],
+            epoch: 0,
+            pending_updates: Vec::new(),
+            reanchors: Vec::new(),
+            proof_system,
+            clock: Arc::new(SystemClock),
+            freshness: ProofFreshness::default(),
+        }
+    }
+
+    /// Reads time from `clock` and only accepts wallet update proofs while
+    /// they are `freshness`-fresh. Defaults to the system clock and
+    /// `ProofFreshness::default`.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>, freshness: ProofFreshness) -> Self {
+        self.clock = clock;
+        self.freshness = freshness;
+        self
+    }
+
     /// Registers a new wallet with its Merkle root.
+    ///
+    /// Registration changes the global state tree outside of any epoch
+    /// proof, so it is only possible between epochs. It re-anchors the
+    /// current epoch at the new state root and publishes the move as a
+    /// `Reanchor`; see `reanchors`.
     pub fn register_wallet(
         &mut self,
         wallet_id: Bytes32,
@@ -102,9 +228,23 @@ impl GlobalRootContract {
         if self.wallet_roots.contains_key(&wallet_id) {
             return Err(GlobalRootContractError::WalletAlreadyRegistered);
         }
+        if !self.pending_updates.is_empty() {
+            return Err(GlobalRootContractError::EpochInProgress);
+        }
+        bytes_to_hash_out(&wallet_merkle_root)
+            .map_err(|e| GlobalRootContractError::InvalidInput(e.to_string()))?;
 
         self.wallet_roots.insert(wallet_id, wallet_merkle_root);
         self.merkle_tree.insert(wallet_merkle_root)?;
+        self.state_root = state_tree_root(&sorted_wallet_roots(&self.wallet_roots))?;
+        self.reanchors.push(Reanchor {
+            epoch: self.epoch,
+            wallet_id,
+            wallet_root: wallet_merkle_root,
+            old_root: self.anchored_root,
+            new_root: self.state_root,
+        });
+        self.anchored_root = self.state_root;
 
         match compute_global_root(&self.wallet_roots) {
             Ok(root) => {
@@ -115,34 +255,37 @@ impl GlobalRootContract {
         }
     }
 
-    /// Updates a wallet's Merkle root with a new proof.
+    /// Updates a wallet's root, given a fresh wallet update proof that valid
+    /// channel transitions move the registered root to `wallet_root_update`.
+    /// The update is recorded for the current epoch's proof; see
+    /// `prove_epoch`.
+    ///
+    /// An epoch holds at most `GLOBAL_UPDATE_SLOTS` updates, the slots of
+    /// one epoch proof. Further updates fail with `EpochFull` until
+    /// `prove_epoch` starts the next epoch, so the epoch has to be proven
+    /// at least once every `GLOBAL_UPDATE_SLOTS` updates to keep accepting
+    /// them. Contracts whose proof system has no epoch proofs stop accepting
+    /// updates once their first epoch is full.
     pub fn update_wallet(
         &mut self,
         wallet_id: Bytes32,
         wallet_root_update: Bytes32,
-        proof: state_proof::StateProof, // Use fully qualified type
+        proof: StateProof,
     ) -> Result<(), GlobalRootContractError> {
-        // let old_root = self
-        // .wallet_roots
-        // .get(&wallet_id)
-        // .ok_or(GlobalRootContractError::WalletNotFound)?
-        // .clone();
-
         let old_root =
             *self.wallet_roots.get(&wallet_id).ok_or(GlobalRootContractError::WalletNotFound)?;
 
-        // Convert state_proof::StateProof to helpers::StateProof for verification
-        let helper_proof = HelperStateProof {
-            pi: proof.pi,
-            public_inputs: proof.public_inputs.clone(),
-            timestamp: proof.timestamp,
-            params: self.params.clone(),
-        };
-
-        if !verify_wallet_proof(&old_root, &wallet_root_update, &helper_proof, &self.params) {
-            return Err(GlobalRootContractError::ProofVerificationFailed);
+        if !self.freshness.is_fresh(&proof, &*self.clock) {
+            return Err(GlobalRootContractError::StaleProof);
+        }
+        self.proof_system
+            .verify_wallet_update(&proof, &old_root, &wallet_root_update)
+            .map_err(|_| GlobalRootContractError::ProofVerificationFailed)?;
+        if self.pending_updates.len() >= GLOBAL_UPDATE_SLOTS {
+            return Err(GlobalRootContractError::EpochFull);
         }
 
+        let path = wallet_tree_path(&self.wallet_roots, &wallet_id)?;
         self.wallet_roots.insert(wallet_id, wallet_root_update);
 
         self.merkle_tree
@@ -152,6 +295,8 @@ impl GlobalRootContract {
         match compute_global_root(&self.wallet_roots) {
             Ok(root) => {
                 self.merkle_root = root;
+                self.state_root = path.compute_root(&wallet_root_update)?;
+                self.pending_updates.push((proof.clone(), path));
                 self.latest_proofs.insert(wallet_id, proof);
                 Ok(())
             }
@@ -174,6 +319,21 @@ impl GlobalRootContract {
     /// Retrieves the current global Merkle root.
     pub fn get_global_merkle_root(&self) -> Bytes32 { self.merkle_root }
 
+    /// Gets the Pedersen parameters the contract was created with.
+    pub fn get_params(&self) -> &PedersenParameters { &self.params }
+
+    /// Retrieves the current global state root.
+    pub fn get_state_root(&self) -> Bytes32 { self.state_root }
+
+    /// Retrieves the state root the current epoch's proof starts from.
+    pub fn get_anchored_root(&self) -> Bytes32 { self.anchored_root }
+
+    /// Gets the number of the current epoch.
+    pub fn current_epoch(&self) -> u64 { self.epoch }
+
+    /// Lists the moves of the anchored root by registrations, oldest first.
+    pub fn reanchors(&self) -> &[Reanchor] { &self.reanchors }
+
     /// Generates a Merkle proof for a given wallet.
     pub fn generate_proof(
         &self,
@@ -200,10 +360,35 @@ impl GlobalRootContract {
     }
 }
 
+/// Wallet roots ordered by wallet ID, the leaves of the global state tree.
+fn sorted_wallet_roots(wallet_roots: &HashMap<Bytes32, Bytes32>) -> Vec<Bytes32> {
+    let mut entries: Vec<(&Bytes32, &Bytes32)> = wallet_roots.iter().collect();
+    entries.sort_by_key(|(wallet_id, _)| **wallet_id);
+    entries.into_iter().map(|(_, root)| *root).collect()
+}
+
+/// Path of a wallet's leaf in the global state tree.
+fn wallet_tree_path(
+    wallet_roots: &HashMap<Bytes32, Bytes32>,
+    wallet_id: &Bytes32,
+) -> Result<StateTreePath, GlobalRootContractError> {
+    let mut wallet_ids: Vec<Bytes32> = wallet_roots.keys().copied().collect();
+    wallet_ids.sort();
+    let index =
+        wallet_ids.binary_search(wallet_id).map_err(|_| GlobalRootContractError::WalletNotFound)?;
+    Ok(StateTreePath::new(&sorted_wallet_roots(wallet_roots), index)?)
+}
+
 #[cfg(test)]
 mod tests {
+    use secp256k1::{Keypair, SECP256K1};
+
     use super::*;
-    use crate::state::{current_timestamp, generate_state_proof};
+    use crate::channel::{ChannelParticipants, ChannelState};
+    use crate::clock::ManualClock;
+    use crate::proof_system::MockProofSystem;
+    use crate::signing::x_only_public_key;
+    use crate::wallet::WalletContract;
 
     fn setup_test_contract() -> GlobalRootContract {
         let params = PedersenParameters::default();
@@ -241,51 +426,254 @@ mod tests {
         let result = contract.register_wallet(wallet_id, wallet_merkle_root);
         assert!(matches!(result, Err(GlobalRootContractError::WalletAlreadyRegistered)));
 
+        // Registration re-anchors the epoch at the new state root, publicly
+        assert_eq!(contract.get_state_root(), state_tree_root(&[wallet_merkle_root])?);
+        assert_eq!(contract.get_anchored_root(), contract.get_state_root());
+        let reanchor = contract.reanchors()[0];
+        assert_eq!(contract.reanchors().len(), 1);
+        assert_eq!((reanchor.epoch, reanchor.old_root), (0, [0u8; 32]));
+        assert_eq!(reanchor.new_root, contract.get_anchored_root());
+        assert!(reanchor.verify(&HashMap::new()));
+        assert!(!reanchor.verify(&contract.wallet_roots));
+
+        // Re-anchors chain, and each one can be checked against the wallets
+        // registered before it
+        let before = contract.wallet_roots.clone();
+        contract.register_wallet([3u8; 32], [4u8; 32])?;
+        let reanchor = contract.reanchors()[1];
+        assert_eq!(reanchor.old_root, contract.reanchors()[0].new_root);
+        assert!(reanchor.verify(&before));
+        let forged = Reanchor { wallet_root: [5u8; 32], ..reanchor };
+        assert!(!forged.verify(&before));
+
+        // Roots must be encodable as state tree leaves
+        let result = contract.register_wallet([2u8; 32], [0xff; 32]);
+        assert!(matches!(result, Err(GlobalRootContractError::InvalidInput(_))));
+
         Ok(())
     }
 
     #[test]
-    fn test_update_wallet() -> Result<(), GlobalRootContractError> {
-        let mut contract = setup_test_contract();
-        let wallet_id = [1u8; 32];
-        let init_wallet_state_commitment = [2u8; 32];
-
-        contract.register_wallet(wallet_id, init_wallet_state_commitment)?;
-
-        // Create a mock proof
-        let new_commitment = [3u8; 32];
-        let new_global_merkle_root = [4u8; 32];
-        let mock_proof = StateProof {
-            pi: generate_state_proof(
-                init_wallet_state_commitment,
-                new_commitment,
-                new_global_merkle_root,
-                &contract.params,
-            )
-            .pi,
-            public_inputs: vec![
-                init_wallet_state_commitment,
-                new_commitment,
-                new_global_merkle_root,
-            ],
-            timestamp: current_timestamp(),
-        };
+    fn test_update_wallet() -> Result<()> {
+        let signer = Keypair::from_seckey_slice(SECP256K1, &[1u8; 32])?;
+        let participants =
+            ChannelParticipants { sender: x_only_public_key(&signer), receiver: [9u8; 32] };
+        let channel_id = [5u8; 32];
+        let clock = ManualClock::new(1_000, 800_000);
+        let system = Plonky2ProofSystem::new(Arc::new(clock.clone()));
+        let mut wallet = WalletContract::with_proof_system(
+            [1u8; 32],
+            PedersenParameters::default(),
+            setup_test_contract(),
+            system.clone(),
+        );
+        wallet.register_channel(channel_id, ChannelState::new(100, Vec::new(), participants)?)?;
+
+        let mut contract =
+            setup_test_contract().with_clock(Arc::new(clock.clone()), ProofFreshness::Blocks(6));
+        let wallet_id = wallet.wallet_id;
+        let old_root = wallet.state_root;
+        contract.register_wallet(wallet_id, old_root)?;
+
+        let mut next = wallet.get_channel(&channel_id).unwrap().clone();
+        next.transfer_with_proof(&system, channel_id, 25, &signer)?;
+        let proof = wallet.apply_proven_updates(vec![(channel_id, next)])?;
+        assert_eq!((proof.timestamp, proof.block_height), (1_000, Some(800_000)));
+
+        // Proofs are only accepted for a few blocks, and cannot be restamped
+        // to look fresher
+        clock.mine(7);
+        assert!(matches!(
+            contract.update_wallet(wallet_id, wallet.state_root, proof.clone()),
+            Err(GlobalRootContractError::StaleProof)
+        ));
+        let restamped = StateProof { block_height: Some(800_007), ..proof.clone() };
+        assert!(matches!(
+            contract.update_wallet(wallet_id, wallet.state_root, restamped),
+            Err(GlobalRootContractError::ProofVerificationFailed)
+        ));
+        clock.set_block_height(800_006);
+
+        // Public inputs cannot be swapped for another root
+        let mut forged = proof.clone();
+        forged.public_inputs[1] = [7u8; 32];
+        assert!(matches!(
+            contract.update_wallet(wallet_id, [7u8; 32], forged),
+            Err(GlobalRootContractError::ProofVerificationFailed)
+        ));
+        assert_eq!(contract.get_wallet_root(&wallet_id), Some(old_root));
 
         // Update wallet with new root and proof
-        contract.update_wallet(wallet_id, new_commitment, mock_proof.clone())?;
+        contract.update_wallet(wallet_id, wallet.state_root, proof.clone())?;
+        assert_eq!(contract.get_wallet_root(&wallet_id), Some(wallet.state_root));
+        let latest = contract.get_latest_proof(&wallet_id).map(|proof| proof.public_inputs[1]);
+        assert_eq!(latest, Some(wallet.state_root));
 
-        // Verify the update
-        assert_eq!(contract.get_wallet_root(&wallet_id), Some(new_commitment));
+        // A proof only moves the root it was generated against, so it cannot
+        // be replayed
+        assert!(matches!(
+            contract.update_wallet(wallet_id, wallet.state_root, proof.clone()),
+            Err(GlobalRootContractError::ProofVerificationFailed)
+        ));
 
         // Test updating non-existent wallet
-        let invalid_wallet = [22u8; 32];
-        let invalid_result =
-            contract.update_wallet(invalid_wallet, new_global_merkle_root, mock_proof.clone());
+        let invalid_result = contract.update_wallet([22u8; 32], wallet.state_root, proof);
         assert!(matches!(invalid_result, Err(GlobalRootContractError::WalletNotFound)));
 
         Ok(())
     }
 
+    #[test]
+    fn test_mock_wallet_update() -> Result<()> {
+        let clock = ManualClock::new(1_000, 800_000);
+        let freshness = ProofFreshness::Blocks(6);
+        let params = PedersenParameters::default();
+        let mock = MockProofSystem::new(params.clone(), Arc::new(clock.clone()));
+        let signer = Keypair::from_seckey_slice(SECP256K1, &[1u8; 32])?;
+        let participants =
+            ChannelParticipants { sender: x_only_public_key(&signer), receiver: [9u8; 32] };
+        let channel_id = [5u8; 32];
+        let global_contract = GlobalRootContract::with_proof_system(params.clone(), mock.clone());
+        let mut wallet = WalletContract::with_proof_system(
+            [1u8; 32],
+            params.clone(),
+            global_contract,
+            mock.clone(),
+        );
+        wallet.register_channel(channel_id, ChannelState::new(100, Vec::new(), participants)?)?;
+
+        let mut contract = GlobalRootContract::with_proof_system(params, mock.clone())
+            .with_clock(Arc::new(clock.clone()), freshness);
+        contract.register_wallet(wallet.wallet_id, wallet.state_root)?;
+
+        let mut next = wallet.get_channel(&channel_id).unwrap().clone();
+        next.transfer_with_proof(&mock, channel_id, 25, &signer)?;
+        assert!(next.has_valid_proof(&mock, &channel_id));
+        let proof = wallet.apply_proven_updates(vec![(channel_id, next)])?;
+
+        // Mock proofs still bind their public inputs
+        let mut forged = proof.clone();
+        forged.public_inputs[1] = [7u8; 32];
+        assert!(matches!(
+            contract.update_wallet(wallet.wallet_id, [7u8; 32], forged),
+            Err(GlobalRootContractError::ProofVerificationFailed)
+        ));
+
+        contract.update_wallet(wallet.wallet_id, wallet.state_root, proof)?;
+        assert_eq!(contract.get_wallet_root(&wallet.wallet_id), Some(wallet.state_root));
+        assert_eq!(contract.get_state_root(), state_tree_root(&[wallet.state_root])?);
+
+        // Proofs are only accepted for a few blocks
+        let old_root = wallet.state_root;
+        let mut next = wallet.get_channel(&channel_id).unwrap().clone();
+        next.transfer_with_proof(&mock, channel_id, 25, &signer)?;
+        let proof = wallet.apply_proven_updates(vec![(channel_id, next)])?;
+        clock.mine(7);
+        assert!(matches!(
+            contract.update_wallet(wallet.wallet_id, wallet.state_root, proof),
+            Err(GlobalRootContractError::StaleProof)
+        ));
+        assert_eq!(contract.get_wallet_root(&wallet.wallet_id), Some(old_root));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prove_epoch() -> Result<()> {
+        let signer = Keypair::from_seckey_slice(SECP256K1, &[1u8; 32])?;
+        let participants =
+            ChannelParticipants { sender: x_only_public_key(&signer), receiver: [9u8; 32] };
+        let channel_id = [5u8; 32];
+        let mut wallet = WalletContract::new(
+            [1u8; 32],
+            PedersenParameters::default(),
+            setup_test_contract(),
+        );
+        wallet.register_channel(channel_id, ChannelState::new(100, Vec::new(), participants)?)?;
+
+        let mut contract = setup_test_contract();
+        contract.register_wallet(wallet.wallet_id, wallet.state_root)?;
+        contract.register_wallet([2u8; 32], [3u8; 32])?;
+        let anchored_root = contract.get_anchored_root();
+
+        let mut next = wallet.get_channel(&channel_id).unwrap().clone();
+        next.transfer_with_proof(&Plonky2ProofSystem::default(), channel_id, 25, &signer)?;
+        let wallet_proof = wallet.apply_proven_updates(vec![(channel_id, next)])?;
+        contract.update_wallet(wallet.wallet_id, wallet.state_root, wallet_proof)?;
+
+        // Wallets cannot join while the epoch has unproven updates
+        assert!(matches!(
+            contract.register_wallet([4u8; 32], [4u8; 32]),
+            Err(GlobalRootContractError::EpochInProgress)
+        ));
+        assert_eq!(contract.reanchors().len(), 2);
+
+        let proof = contract.prove_epoch()?;
+        let circuit = GlobalRootCircuit::shared()?;
+        circuit.verify_epoch(&anchored_root, 0, &proof)?;
+        assert_eq!(proof.new_root(), contract.get_state_root());
+        assert_eq!(proof.wallet_updates(), 1);
+
+        // The next epoch starts from the proven root
+        assert_eq!(contract.current_epoch(), 1);
+        assert_eq!(contract.get_anchored_root(), proof.new_root());
+        assert!(circuit.verify_epoch(&contract.get_anchored_root(), 1, &proof).is_err());
+
+        // A registration before the next epoch proof is published as a move
+        // away from the proven root
+        let before = contract.wallet_roots.clone();
+        contract.register_wallet([4u8; 32], [4u8; 32])?;
+        let reanchor = contract.reanchors()[2];
+        assert_eq!((reanchor.epoch, reanchor.old_root), (1, proof.new_root()));
+        assert_eq!(reanchor.new_root, contract.get_anchored_root());
+        assert!(reanchor.verify(&before));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_epoch_capacity() -> Result<()> {
+        let params = PedersenParameters::default();
+        let mock = MockProofSystem::new(params.clone(), Arc::new(SystemClock));
+        let signer = Keypair::from_seckey_slice(SECP256K1, &[1u8; 32])?;
+        let participants =
+            ChannelParticipants { sender: x_only_public_key(&signer), receiver: [9u8; 32] };
+        let channel_id = [5u8; 32];
+        let global_contract = GlobalRootContract::with_proof_system(params.clone(), mock.clone());
+        let mut wallet = WalletContract::with_proof_system(
+            [1u8; 32],
+            params.clone(),
+            global_contract,
+            mock.clone(),
+        );
+        wallet.register_channel(channel_id, ChannelState::new(100, Vec::new(), participants)?)?;
+        let mut contract = GlobalRootContract::with_proof_system(params, mock.clone());
+        contract.register_wallet(wallet.wallet_id, wallet.state_root)?;
+
+        // An epoch takes as many updates as its proof has slots
+        let update = |wallet: &mut WalletContract<MockProofSystem>| -> Result<StateProof> {
+            let mut next = wallet.get_channel(&channel_id).unwrap().clone();
+            next.transfer_with_proof(&mock, channel_id, 5, &signer)?;
+            Ok(wallet.apply_proven_updates(vec![(channel_id, next)])?)
+        };
+        for _ in 0..GLOBAL_UPDATE_SLOTS {
+            let proof = update(&mut wallet)?;
+            contract.update_wallet(wallet.wallet_id, wallet.state_root, proof)?;
+        }
+
+        // Further updates wait for the epoch proof
+        let root = contract.get_wallet_root(&wallet.wallet_id);
+        let proof = update(&mut wallet)?;
+        assert!(matches!(
+            contract.update_wallet(wallet.wallet_id, wallet.state_root, proof),
+            Err(GlobalRootContractError::EpochFull)
+        ));
+        assert_eq!(contract.get_wallet_root(&wallet.wallet_id), root);
+        assert_eq!(contract.pending_updates.len(), GLOBAL_UPDATE_SLOTS);
+
+        Ok(())
+    }
+
     #[test]
     fn test_generate_and_verify_proof() -> Result<(), GlobalRootContractError> {
         let mut contract = setup_test_contract();
@@ -1,21 +1,32 @@
 // ./src/lib.rs
 
+pub mod aggregation;
 pub mod channel;
+pub mod clock;
+pub mod codec;
 pub mod commitments;
 pub mod error;
+pub mod global_circuit;
 pub mod global_root_contract;
 pub mod merkle;
+pub mod merkle_circuit;
 pub mod pedersen_parameters;
+pub mod proof_system;
+pub mod prover_pool;
+pub mod range_proof;
+pub mod signing;
 pub mod state;
 pub mod state_proof;
 pub mod state_transition;
+pub mod transition_log;
 pub mod tree;
 pub mod types;
 pub mod wallet;
+pub mod wallet_circuit;
 
 pub use channel::ChannelState;
 pub use pedersen_parameters::PedersenParameters;
-pub use state::StateProof;
-pub use tree::MerkleTree;
+pub use state_proof::StateProof;
+pub use tree::{MerkleTree, PoseidonMerkleTree};
 pub use types::Bytes32;
 pub use wallet::WalletContract;
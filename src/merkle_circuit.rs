@@ -0,0 +1,380 @@
+//! Poseidon Merkle trees over channel states, provable in-circuit
+//!
+//! Wallets commit to their channels with a state tree: a binary Poseidon
+//! Merkle tree whose leaves are the `hash_state` digests of the channels,
+//! sorted by channel ID. Levels are padded to a power of two with zero
+//! hashes rather than by duplicating the last node, so that a single
+//! authentication path proves how replacing one leaf changes the root.
+//! `tree::PoseidonMerkleTree` pads the same way and has the same roots.
+//!
+//! `MerkleUpdateTargets` is a circuit gadget proving that an old and a new
+//! leaf sit at the same index under an old and a new root, either for a fixed
+//! depth or for any depth up to a maximum.
+//! `ChannelUpdateCircuit` wraps it with public inputs
+//! `[old root (4), new root (4), old leaf (4), new leaf (4), leaf index]`, so a
+//! wallet root update can be checked against the leaves proven by
+//! `StateTransitionCircuit` and the position of the channel they belong to.
+
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context, Result};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2_field::types::Field;
+
+use crate::codec::{bytes_to_hash_out, elements_to_bytes, field_to_u64, hash_out_to_bytes};
+use crate::tree::hash_children;
+use crate::types::Bytes32;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Deepest state tree supported by `ChannelUpdateCircuit`.
+pub const MAX_STATE_TREE_DEPTH: usize = 32;
+
+/// Depth of the state tree over `leaf_count` leaves.
+pub fn state_tree_depth(leaf_count: usize) -> usize {
+    leaf_count.next_power_of_two().trailing_zeros() as usize
+}
+
+/// Computes the state tree root over `leaves`. The root of an empty tree is
+/// all zeros.
+pub fn state_tree_root(leaves: &[Bytes32]) -> Result<Bytes32> {
+    let levels = state_tree_levels(leaves)?;
+    Ok(levels.last().map_or([0u8; 32], |root| hash_out_to_bytes(&root[0])))
+}
+
+/// Authentication path from a leaf of the state tree to its root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateTreePath {
+    /// Position of the leaf
+    pub index: usize,
+    /// Sibling of each node on the path, from the leaf level up
+    pub siblings: Vec<Bytes32>,
+}
+
+impl StateTreePath {
+    /// Builds the path of the leaf at `index`.
+    pub fn new(leaves: &[Bytes32], index: usize) -> Result<Self> {
+        if index >= leaves.len() {
+            return Err(anyhow!("Leaf index {index} out of range for {} leaves", leaves.len()));
+        }
+        let levels = state_tree_levels(leaves)?;
+        let siblings = levels[..levels.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(level, nodes)| hash_out_to_bytes(&nodes[(index >> level) ^ 1]))
+            .collect();
+        Ok(Self { index, siblings })
+    }
+
+    /// Computes the root of a tree holding `leaf` at this path's position.
+    pub fn compute_root(&self, leaf: &Bytes32) -> Result<Bytes32> {
+        let mut node = bytes_to_hash_out(leaf)?;
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            let sibling = bytes_to_hash_out(sibling)?;
+            node = if (self.index >> level) & 1 == 0 {
+                hash_children(&node, &sibling)
+            } else {
+                hash_children(&sibling, &node)
+            };
+        }
+        Ok(hash_out_to_bytes(&node))
+    }
+}
+
+/// All levels of the state tree, from the padded leaves up to the root.
+fn state_tree_levels(leaves: &[Bytes32]) -> Result<Vec<Vec<HashOut<F>>>> {
+    if leaves.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut level = leaves.iter().map(bytes_to_hash_out).collect::<Result<Vec<_>, _>>()?;
+    level.resize(leaves.len().next_power_of_two(), HashOut { elements: [F::ZERO; 4] });
+
+    let mut levels = vec![level];
+    while levels[levels.len() - 1].len() > 1 {
+        let next = levels[levels.len() - 1]
+            .chunks(2)
+            .map(|pair| hash_children(&pair[0], &pair[1]))
+            .collect();
+        levels.push(next);
+    }
+    Ok(levels)
+}
+
+/// Gadget proving that replacing `old_leaf` by `new_leaf` at the same index
+/// turns `old_root` into `new_root`.
+pub struct MerkleUpdateTargets {
+    pub old_leaf: HashOutTarget,
+    pub new_leaf: HashOutTarget,
+    pub old_root: HashOutTarget,
+    pub new_root: HashOutTarget,
+    index_bits: Vec<BoolTarget>,
+    siblings: Vec<HashOutTarget>,
+    /// Per-level flags of a variable-depth gadget; empty for a fixed depth
+    level_active: Vec<BoolTarget>,
+}
+
+impl MerkleUpdateTargets {
+    /// Adds the gadget for a tree of the given depth to `builder`.
+    pub fn add(builder: &mut CircuitBuilder<F, D>, depth: usize) -> Self {
+        Self::build(builder, depth, false)
+    }
+
+    /// Adds the gadget for trees of any depth up to `max_depth`. Levels above
+    /// the actual depth pass the node through unchanged.
+    pub fn add_up_to(builder: &mut CircuitBuilder<F, D>, max_depth: usize) -> Self {
+        Self::build(builder, max_depth, true)
+    }
+
+    fn build(builder: &mut CircuitBuilder<F, D>, depth: usize, variable: bool) -> Self {
+        let old_leaf = builder.add_virtual_hash();
+        let new_leaf = builder.add_virtual_hash();
+        let index_bits: Vec<_> =
+            (0..depth).map(|_| builder.add_virtual_bool_target_safe()).collect();
+        let siblings = builder.add_virtual_hashes(depth);
+
+        // Active levels form a prefix, so the flags encode a single depth.
+        let level_active: Vec<_> = if variable {
+            (0..depth).map(|_| builder.add_virtual_bool_target_safe()).collect()
+        } else {
+            Vec::new()
+        };
+        for pair in level_active.windows(2) {
+            let below_inactive = builder.not(pair[0]);
+            let gap = builder.and(pair[1], below_inactive);
+            builder.assert_zero(gap.target);
+        }
+
+        // Both roots use the same index and siblings, so the two leaves sit at
+        // the same position and every other leaf is unchanged.
+        let old_root =
+            compute_root_target(builder, old_leaf, &index_bits, &siblings, &level_active);
+        let new_root =
+            compute_root_target(builder, new_leaf, &index_bits, &siblings, &level_active);
+
+        Self { old_leaf, new_leaf, old_root, new_root, index_bits, siblings, level_active }
+    }
+
+    /// Assigns the leaves and the path they share.
+    pub fn set_witness(
+        &self,
+        pw: &mut PartialWitness<F>,
+        old_leaf: &Bytes32,
+        new_leaf: &Bytes32,
+        path: &StateTreePath,
+    ) -> Result<()> {
+        let fits = if self.level_active.is_empty() {
+            path.siblings.len() == self.siblings.len()
+        } else {
+            path.siblings.len() <= self.siblings.len()
+        };
+        if !fits {
+            return Err(anyhow!(
+                "Path of depth {} does not fit a tree of depth {}",
+                path.siblings.len(),
+                self.siblings.len()
+            ));
+        }
+        pw.set_hash_target(self.old_leaf, bytes_to_hash_out(old_leaf)?)
+            .context("Failed to set old leaf")?;
+        pw.set_hash_target(self.new_leaf, bytes_to_hash_out(new_leaf)?)
+            .context("Failed to set new leaf")?;
+        for (level, &bit) in self.index_bits.iter().enumerate() {
+            // Levels above the path are inactive and filled with zeros.
+            let (is_right, sibling) = match path.siblings.get(level) {
+                Some(sibling) => ((path.index >> level) & 1 == 1, bytes_to_hash_out(sibling)?),
+                None => (false, HashOut { elements: [F::ZERO; 4] }),
+            };
+            pw.set_bool_target(bit, is_right).context("Failed to set index bit")?;
+            pw.set_hash_target(self.siblings[level], sibling).context("Failed to set sibling")?;
+            if let Some(&active) = self.level_active.get(level) {
+                pw.set_bool_target(active, level < path.siblings.len())
+                    .context("Failed to set level flag")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recomputes a root from a leaf and its path inside the circuit, skipping
+/// levels whose flag in `level_active` is unset.
+fn compute_root_target(
+    builder: &mut CircuitBuilder<F, D>,
+    leaf: HashOutTarget,
+    index_bits: &[BoolTarget],
+    siblings: &[HashOutTarget],
+    level_active: &[BoolTarget],
+) -> HashOutTarget {
+    let mut node = leaf;
+    for (level, (&is_right, sibling)) in index_bits.iter().zip(siblings).enumerate() {
+        let mut children = Vec::with_capacity(8);
+        // The node is the right child when its index bit is set.
+        for i in 0..4 {
+            children.push(builder.select(is_right, sibling.elements[i], node.elements[i]));
+        }
+        for i in 0..4 {
+            children.push(builder.select(is_right, node.elements[i], sibling.elements[i]));
+        }
+        let parent = builder.hash_n_to_hash_no_pad::<PoseidonHash>(children);
+        node = match level_active.get(level) {
+            Some(&active) => HashOutTarget {
+                elements: std::array::from_fn(|i| {
+                    builder.select(active, parent.elements[i], node.elements[i])
+                }),
+            },
+            None => parent,
+        };
+    }
+    node
+}
+
+/// Proof that one channel leaf of a wallet's state tree was replaced.
+#[derive(Debug, Clone)]
+pub struct ChannelUpdateProof {
+    /// Depth of the state tree
+    pub depth: usize,
+    /// The update proof
+    pub proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl ChannelUpdateProof {
+    /// State tree root before the update.
+    pub fn old_root(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[0..4]) }
+
+    /// State tree root after the update.
+    pub fn new_root(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[4..8]) }
+
+    /// Channel state hash before the update.
+    pub fn old_leaf(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[8..12]) }
+
+    /// Channel state hash after the update.
+    pub fn new_leaf(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[12..16]) }
+
+    /// Position of the updated leaf in the state tree.
+    pub fn index(&self) -> usize { field_to_u64(self.proof.public_inputs[16]) as usize }
+}
+
+/// Circuit proving a single leaf update in a state tree of fixed depth.
+pub struct ChannelUpdateCircuit {
+    circuit_data: CircuitData<F, C, D>,
+    targets: MerkleUpdateTargets,
+    depth: usize,
+}
+
+impl ChannelUpdateCircuit {
+    /// Builds the circuit for state trees of the given depth.
+    pub fn new(depth: usize) -> Self {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+
+        let targets = MerkleUpdateTargets::add(&mut builder, depth);
+        builder.register_public_inputs(&targets.old_root.elements);
+        builder.register_public_inputs(&targets.new_root.elements);
+        builder.register_public_inputs(&targets.old_leaf.elements);
+        builder.register_public_inputs(&targets.new_leaf.elements);
+        let index = builder.le_sum(targets.index_bits.iter().copied());
+        builder.register_public_input(index);
+
+        let circuit_data = builder.build::<C>();
+
+        Self { circuit_data, targets, depth }
+    }
+
+    /// Gets a circuit for `depth` shared by the whole process, built on
+    /// first use.
+    pub fn for_depth(depth: usize) -> Result<&'static Self> {
+        static CIRCUITS: [OnceLock<ChannelUpdateCircuit>; MAX_STATE_TREE_DEPTH + 1] =
+            [const { OnceLock::new() }; MAX_STATE_TREE_DEPTH + 1];
+        let circuit = CIRCUITS
+            .get(depth)
+            .ok_or_else(|| anyhow!("State tree depth {depth} exceeds {MAX_STATE_TREE_DEPTH}"))?;
+        Ok(circuit.get_or_init(|| Self::new(depth)))
+    }
+
+    /// Proves that replacing `old_leaf` by `new_leaf` at `path` changes the
+    /// root accordingly.
+    pub fn prove(
+        &self,
+        old_leaf: &Bytes32,
+        new_leaf: &Bytes32,
+        path: &StateTreePath,
+    ) -> Result<ChannelUpdateProof> {
+        let mut pw = PartialWitness::new();
+        self.targets.set_witness(&mut pw, old_leaf, new_leaf, path)?;
+        let proof = self.circuit_data.prove(pw).context("Update proof generation failed")?;
+        Ok(ChannelUpdateProof { depth: self.depth, proof })
+    }
+
+    /// Verifies an update proof produced by this circuit.
+    pub fn verify(&self, proof: &ChannelUpdateProof) -> Result<()> {
+        if proof.depth != self.depth {
+            return Err(anyhow!(
+                "Proof depth {} does not match circuit depth {}",
+                proof.depth,
+                self.depth
+            ));
+        }
+        self.circuit_data.verify(proof.proof.clone()).context("Update proof verification failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: u8) -> Vec<Bytes32> { (1..=count).map(|i| [i; 32]).collect() }
+
+    #[test]
+    fn test_state_tree_paths() -> Result<()> {
+        assert_eq!(state_tree_root(&[])?, [0u8; 32]);
+        assert_eq!(state_tree_root(&leaves(1))?, [1u8; 32]);
+        assert_eq!(state_tree_depth(5), 3);
+
+        let mut leaves = leaves(5);
+        let root = state_tree_root(&leaves)?;
+        for index in 0..leaves.len() {
+            let path = StateTreePath::new(&leaves, index)?;
+            assert_eq!(path.siblings.len(), 3);
+            assert_eq!(path.compute_root(&leaves[index])?, root);
+        }
+        assert!(StateTreePath::new(&leaves, 5).is_err());
+
+        // The path of the last leaf also proves the root after replacing it
+        let path = StateTreePath::new(&leaves, 4)?;
+        leaves[4] = [9u8; 32];
+        assert_eq!(path.compute_root(&leaves[4])?, state_tree_root(&leaves)?);
+        assert_ne!(state_tree_root(&leaves)?, root);
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_update_proof() -> Result<()> {
+        let mut leaves = leaves(3);
+        let old_root = state_tree_root(&leaves)?;
+        let path = StateTreePath::new(&leaves, 1)?;
+        let old_leaf = leaves[1];
+        leaves[1] = [7u8; 32];
+
+        let circuit = ChannelUpdateCircuit::for_depth(path.siblings.len())?;
+        let proof = circuit.prove(&old_leaf, &leaves[1], &path)?;
+        circuit.verify(&proof)?;
+        assert_eq!(proof.old_root(), old_root);
+        assert_eq!(proof.new_root(), state_tree_root(&leaves)?);
+        assert_eq!((proof.old_leaf(), proof.new_leaf()), (old_leaf, leaves[1]));
+        assert_eq!(proof.index(), 1);
+
+        // A path of the wrong depth cannot be proven
+        let short = StateTreePath { index: 0, siblings: Vec::new() };
+        assert!(circuit.prove(&old_leaf, &leaves[1], &short).is_err());
+        Ok(())
+    }
+}
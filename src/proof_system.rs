@@ -0,0 +1,642 @@
+//! Pluggable proof systems
+//!
+//! Channel, wallet and global root code proves two statements: that a channel
+//! moved from one state to the next, and that a batch of proven channel
+//! transitions moved a wallet's state root. `ProofSystem` abstracts over how
+//! these statements are proven. Every proof travels in a versioned
+//! `StateProof` envelope recording the system that produced it.
+//!
+//! Two systems are provided:
+//!
+//! - `MockProofSystem` hashes the public inputs with SHA-256. Anyone can
+//!   compute its proofs, so it only suits tests and simulations.
+//! - `Plonky2ProofSystem` proves transitions with `StateTransitionCircuit`
+//!   and wallet updates with `WalletTransitionCircuit`.
+//!
+//! Transition proofs of both systems start their public inputs with the old
+//! and new `hash_state` digests; wallet update proofs start theirs with the
+//! old and new wallet state roots.
+
+use std::fmt::Display;
+use std::sync::Arc;
+
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::channel::{ChannelState, ChannelTransition};
+use crate::clock::{Clock, SystemClock};
+use crate::merkle_circuit::StateTreePath;
+use crate::pedersen_parameters::PedersenParameters;
+use crate::state::{
+    generate_mock_proof, generate_state_proof, hash_state, verify_mock_proof,
+    verify_mock_wallet_proof,
+};
+use crate::state_proof::StateProof;
+use crate::state_transition::{
+    apply_transition, StateTransitionCircuit, TransitionData, TransitionKind,
+};
+use crate::types::Bytes32;
+use crate::wallet_circuit::{WalletTransitionCircuit, WalletTransitionProof, WalletUpdateStep};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Identifies the proof system that produced a `StateProof`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProofSystemId {
+    /// `MockProofSystem`
+    MockHash,
+    /// `Plonky2ProofSystem`
+    Plonky2,
+    /// Envelope of a channel's initial state over its state hash. Initial
+    /// states follow no transition, so `ChannelState::verify_genesis` checks
+    /// these natively instead of a proof system.
+    Genesis,
+}
+
+/// Represents errors in proof system operations.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ProofSystemError {
+    #[error("Unsupported proof envelope version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Expected a {expected:?} proof, found {found:?}")]
+    WrongSystem { expected: ProofSystemId, found: ProofSystemId },
+    #[error("Proof generation failed: {0}")]
+    ProofGeneration(String),
+    #[error("Invalid proof: {0}")]
+    InvalidProof(String),
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+    #[error("Unsupported transition: {0}")]
+    Unsupported(String),
+}
+
+/// A proven channel transition applied to a wallet's state tree.
+#[derive(Debug, Clone)]
+pub struct ProvenChannelUpdate {
+    /// Transition proof, from the same proof system
+    pub transition_proof: StateProof,
+    /// Path of the channel's leaf in the tree before the update
+    pub path: StateTreePath,
+}
+
+/// Backend proving channel transitions and wallet root updates.
+pub trait ProofSystem {
+    /// Identifier recorded in the envelopes this system produces.
+    const ID: ProofSystemId;
+
+    /// Clock stamping this system's proofs, which also dates the channel
+    /// checks made when proving.
+    fn clock(&self) -> &dyn Clock;
+
+    /// Proves that `next` is a valid successor of `prior` in channel
+    /// `channel_id`.
+    fn prove_transition(
+        &self,
+        channel_id: &Bytes32,
+        prior: &ChannelState,
+        next: &ChannelState,
+    ) -> Result<StateProof, ProofSystemError>;
+
+    /// Verifies a transition proof ending in `next` in channel `channel_id`
+    /// and, if given, starting from `prior`.
+    fn verify_transition(
+        &self,
+        proof: &StateProof,
+        channel_id: &Bytes32,
+        prior: Option<&ChannelState>,
+        next: &ChannelState,
+    ) -> Result<(), ProofSystemError>;
+
+    /// Proves applying `updates`, in order, to the wallet state tree with
+    /// root `old_root`.
+    fn prove_wallet_update(
+        &self,
+        old_root: &Bytes32,
+        updates: &[ProvenChannelUpdate],
+    ) -> Result<StateProof, ProofSystemError>;
+
+    /// Verifies a wallet update proof moving `old_root` to `new_root`.
+    fn verify_wallet_update(
+        &self,
+        proof: &StateProof,
+        old_root: &Bytes32,
+        new_root: &Bytes32,
+    ) -> Result<(), ProofSystemError>;
+
+    /// Serializes a proof produced by this system.
+    fn serialize(&self, proof: &StateProof) -> Result<Vec<u8>, ProofSystemError> {
+        proof.ensure_system(Self::ID)?;
+        proof.to_bytes()
+    }
+
+    /// Deserializes a proof, rejecting proofs of other systems.
+    fn deserialize(&self, bytes: &[u8]) -> Result<StateProof, ProofSystemError> {
+        let proof = StateProof::from_bytes(bytes)?;
+        proof.ensure_system(Self::ID)?;
+        Ok(proof)
+    }
+}
+
+fn generation_error(e: impl Display) -> ProofSystemError {
+    ProofSystemError::ProofGeneration(e.to_string())
+}
+
+fn invalid_proof(e: impl Display) -> ProofSystemError {
+    ProofSystemError::InvalidProof(e.to_string())
+}
+
+/// Checks that a transition proof's public state hashes match the states.
+fn check_state_hashes(
+    proof: &StateProof,
+    prior: Option<&ChannelState>,
+    next: &ChannelState,
+) -> Result<(), ProofSystemError> {
+    let hash = |state| hash_state(state).map_err(invalid_proof);
+    let [old_hash, new_hash, ..] = proof.public_inputs.as_slice() else {
+        return Err(invalid_proof("missing state hashes"));
+    };
+    if prior.map(hash).transpose()?.is_some_and(|prior| prior != *old_hash)
+        || hash(next)? != *new_hash
+    {
+        return Err(invalid_proof("proof is for other states"));
+    }
+    Ok(())
+}
+
+/// Hash-based mock proofs. They bind their public inputs and stamp but prove
+/// nothing, since anyone can compute them.
+#[derive(Debug, Clone)]
+pub struct MockProofSystem {
+    /// Generators hashed into every proof
+    pub params: PedersenParameters,
+    /// Clock stamping new proofs
+    pub clock: Arc<dyn Clock>,
+}
+
+impl MockProofSystem {
+    /// Creates a mock system stamping proofs with `clock`'s reading.
+    pub fn new(params: PedersenParameters, clock: Arc<dyn Clock>) -> Self {
+        Self { params, clock }
+    }
+}
+
+impl Default for MockProofSystem {
+    fn default() -> Self { Self::new(PedersenParameters::default(), Arc::new(SystemClock)) }
+}
+
+impl ProofSystem for MockProofSystem {
+    const ID: ProofSystemId = ProofSystemId::MockHash;
+
+    fn clock(&self) -> &dyn Clock { &*self.clock }
+
+    /// Proves over `[old state hash, new state hash, channel root]`.
+    fn prove_transition(
+        &self,
+        channel_id: &Bytes32,
+        prior: &ChannelState,
+        next: &ChannelState,
+    ) -> Result<StateProof, ProofSystemError> {
+        next.verify_transition(prior, &*self.clock).map_err(generation_error)?;
+        Ok(generate_state_proof(
+            hash_state(prior).map_err(generation_error)?,
+            hash_state(next).map_err(generation_error)?,
+            next.compute_merkle_root(*channel_id).map_err(generation_error)?,
+            &self.params,
+            &*self.clock,
+        ))
+    }
+
+    fn verify_transition(
+        &self,
+        proof: &StateProof,
+        channel_id: &Bytes32,
+        prior: Option<&ChannelState>,
+        next: &ChannelState,
+    ) -> Result<(), ProofSystemError> {
+        proof.ensure_system(Self::ID)?;
+        check_state_hashes(proof, prior, next)?;
+        let channel_root = next.compute_merkle_root(*channel_id).map_err(invalid_proof)?;
+        if proof.public_inputs.get(2) != Some(&channel_root) {
+            return Err(invalid_proof("proof is for another channel"));
+        }
+        if !verify_mock_proof(proof, &self.params) {
+            return Err(invalid_proof("digest mismatch"));
+        }
+        Ok(())
+    }
+
+    /// Checks the chain of updates natively and proves over
+    /// `[old root, new root]`.
+    fn prove_wallet_update(
+        &self,
+        old_root: &Bytes32,
+        updates: &[ProvenChannelUpdate],
+    ) -> Result<StateProof, ProofSystemError> {
+        let mut root = *old_root;
+        for (i, update) in updates.iter().enumerate() {
+            let proof = &update.transition_proof;
+            proof.ensure_system(Self::ID)?;
+            let [old_leaf, new_leaf, ..] = proof.public_inputs.as_slice() else {
+                return Err(invalid_proof(format!("update {i} lacks state hashes")));
+            };
+            if !verify_mock_proof(proof, &self.params) {
+                return Err(invalid_proof(format!("update {i} has an invalid proof")));
+            }
+            if update.path.compute_root(old_leaf).map_err(generation_error)? != root {
+                return Err(generation_error(format!(
+                    "Update {i} does not apply to the current wallet root"
+                )));
+            }
+            root = update.path.compute_root(new_leaf).map_err(generation_error)?;
+        }
+
+        Ok(generate_mock_proof(vec![*old_root, root], &self.params, &*self.clock))
+    }
+
+    fn verify_wallet_update(
+        &self,
+        proof: &StateProof,
+        old_root: &Bytes32,
+        new_root: &Bytes32,
+    ) -> Result<(), ProofSystemError> {
+        proof.ensure_system(Self::ID)?;
+        if !verify_mock_wallet_proof(old_root, new_root, proof, &self.params) {
+            return Err(invalid_proof("wallet proof rejected"));
+        }
+        Ok(())
+    }
+}
+
+/// Plonky2 proofs from the process-wide `StateTransitionCircuit` and
+/// `WalletTransitionCircuit`. Every transition the circuit models can be
+/// proven; refunds and lifecycle moves fail with
+/// `ProofSystemError::Unsupported`.
+///
+/// Proofs are stamped with the clock's reading. Wallet update proofs commit
+/// to their stamp, which freshness is checked against; the stamps of
+/// transition proofs are informational.
+#[derive(Debug, Clone)]
+pub struct Plonky2ProofSystem {
+    /// Clock stamping new proofs
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Plonky2ProofSystem {
+    /// Creates a system stamping proofs with `clock`'s reading.
+    pub fn new(clock: Arc<dyn Clock>) -> Self { Self { clock } }
+
+    /// Current time and block height of the clock.
+    fn stamp(&self) -> (u64, Option<u64>) { (self.clock.now(), self.clock.block_height()) }
+
+    /// Decodes the transition proof held by an envelope.
+    pub fn transition_proof(
+        &self,
+        proof: &StateProof,
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ProofSystemError> {
+        proof.ensure_system(Self::ID)?;
+        let common = &StateTransitionCircuit::shared().circuit_data().common;
+        let inner = ProofWithPublicInputs::from_bytes(proof.pi.clone(), common)
+            .map_err(|e| ProofSystemError::Serialization(e.to_string()))?;
+
+        // The envelope must state what the proof proves.
+        if proof.public_inputs != transition_public_inputs(&inner).map_err(invalid_proof)? {
+            return Err(invalid_proof("public inputs mismatch"));
+        }
+        Ok(inner)
+    }
+
+    /// Decodes the wallet proof held by an envelope.
+    pub fn wallet_proof(
+        &self,
+        proof: &StateProof,
+    ) -> Result<WalletTransitionProof, ProofSystemError> {
+        proof.ensure_system(Self::ID)?;
+        let common = &wallet_circuit()?.circuit_data().common;
+        let inner = ProofWithPublicInputs::from_bytes(proof.pi.clone(), common)
+            .map_err(|e| ProofSystemError::Serialization(e.to_string()))?;
+        let wallet_proof = WalletTransitionProof { proof: inner };
+        if proof.public_inputs != [wallet_proof.old_root(), wallet_proof.new_root()] {
+            return Err(invalid_proof("public inputs mismatch"));
+        }
+        if !wallet_proof.is_stamped(proof.timestamp, proof.block_height) {
+            return Err(invalid_proof("stamp mismatch"));
+        }
+        Ok(wallet_proof)
+    }
+}
+
+impl Default for Plonky2ProofSystem {
+    fn default() -> Self { Self::new(Arc::new(SystemClock)) }
+}
+
+/// `[old state hash, new state hash, transition digest]` of a transition proof.
+fn transition_public_inputs(
+    proof: &ProofWithPublicInputs<F, C, D>,
+) -> anyhow::Result<Vec<Bytes32>> {
+    let (old_hash, new_hash) = StateTransitionCircuit::public_state_hashes(proof)?;
+    let digest = StateTransitionCircuit::public_transition_digest(proof)?;
+    let channel_id = StateTransitionCircuit::public_channel_id(proof)?;
+    Ok(vec![old_hash, new_hash, digest, channel_id])
+}
+
+/// Describes the transition from `prior` to `next` as `TransitionData` for
+/// `StateTransitionCircuit`, checking that it is valid at `clock`'s time.
+fn circuit_transition(
+    prior: &ChannelState,
+    next: &ChannelState,
+    clock: &dyn Clock,
+) -> Result<TransitionData, ProofSystemError> {
+    let data = match &next.transition {
+        ChannelTransition::Transfer => {
+            let amount = prior
+                .sender_balance
+                .checked_sub(next.sender_balance)
+                .ok_or_else(|| generation_error("Transfer raises the sender balance"))?;
+            TransitionData::transfer(amount)
+        }
+        ChannelTransition::Deposit { amount, funding } => {
+            TransitionData::deposit(*amount, *funding)
+        }
+        ChannelTransition::Claim { output } => {
+            TransitionData::new(TransitionKind::Claim, output.amount)
+        }
+        ChannelTransition::TransferBatch { count, total } => {
+            TransitionData::transfer_batch(*count, *total)
+        }
+        ChannelTransition::Close => TransitionData::new(TransitionKind::Close, 0),
+        other => return Err(ProofSystemError::Unsupported(format!("{other:?}"))),
+    };
+    if next.transition == ChannelTransition::Close {
+        next.verify_close(prior, clock).map_err(generation_error)?;
+    } else {
+        next.verify_transition(prior, clock).map_err(generation_error)?;
+    }
+    Ok(TransitionData { memo: next.memo, ..data })
+}
+
+/// Wraps a Plonky2 proof in an envelope with the given stamp.
+fn envelope(
+    pi: Vec<u8>,
+    public_inputs: Vec<Bytes32>,
+    (timestamp, block_height): (u64, Option<u64>),
+) -> StateProof {
+    let mut proof = StateProof::new(ProofSystemId::Plonky2, pi, public_inputs, timestamp);
+    proof.block_height = block_height;
+    proof
+}
+
+fn wallet_circuit() -> Result<&'static WalletTransitionCircuit, ProofSystemError> {
+    WalletTransitionCircuit::shared().map_err(generation_error)
+}
+
+impl ProofSystem for Plonky2ProofSystem {
+    const ID: ProofSystemId = ProofSystemId::Plonky2;
+
+    fn clock(&self) -> &dyn Clock { &*self.clock }
+
+    /// Proves over `[old state hash, new state hash, transition digest,
+    /// channel id]`.
+    fn prove_transition(
+        &self,
+        channel_id: &Bytes32,
+        prior: &ChannelState,
+        next: &ChannelState,
+    ) -> Result<StateProof, ProofSystemError> {
+        let data = circuit_transition(prior, next, &*self.clock)?;
+        // The circuit only proves the state `apply_transition` produces, e.g.
+        // claims paying the channel's receiver.
+        let hash = |state| hash_state(state).map_err(generation_error);
+        let proven = apply_transition(prior, &data).map_err(generation_error)?;
+        if hash(&proven)? != hash(next)? {
+            return Err(generation_error("Next state differs from the one the circuit proves"));
+        }
+        let inner = StateTransitionCircuit::shared()
+            .generate_zkp(channel_id, prior, &data)
+            .map_err(generation_error)?;
+
+        let public_inputs = transition_public_inputs(&inner).map_err(generation_error)?;
+        Ok(envelope(inner.to_bytes(), public_inputs, self.stamp()))
+    }
+
+    fn verify_transition(
+        &self,
+        proof: &StateProof,
+        channel_id: &Bytes32,
+        prior: Option<&ChannelState>,
+        next: &ChannelState,
+    ) -> Result<(), ProofSystemError> {
+        let inner = self.transition_proof(proof)?;
+        check_state_hashes(proof, prior, next)?;
+        if proof.public_inputs.get(3) != Some(channel_id) {
+            return Err(invalid_proof("proof is for another channel"));
+        }
+        StateTransitionCircuit::shared()
+            .verify_proof(inner)
+            .map(|_| ())
+            .map_err(invalid_proof)
+    }
+
+    /// Proves with `WalletTransitionCircuit` over `[old root, new root]`,
+    /// committing to the envelope's stamp.
+    fn prove_wallet_update(
+        &self,
+        old_root: &Bytes32,
+        updates: &[ProvenChannelUpdate],
+    ) -> Result<StateProof, ProofSystemError> {
+        let steps = updates
+            .iter()
+            .map(|update| {
+                Ok(WalletUpdateStep {
+                    transition_proof: self.transition_proof(&update.transition_proof)?,
+                    path: update.path.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, ProofSystemError>>()?;
+        let (timestamp, block_height) = self.stamp();
+        let wallet_proof = wallet_circuit()?
+            .prove(old_root, &steps, timestamp, block_height)
+            .map_err(generation_error)?;
+
+        let public_inputs = vec![wallet_proof.old_root(), wallet_proof.new_root()];
+        Ok(envelope(wallet_proof.proof.to_bytes(), public_inputs, (timestamp, block_height)))
+    }
+
+    fn verify_wallet_update(
+        &self,
+        proof: &StateProof,
+        old_root: &Bytes32,
+        new_root: &Bytes32,
+    ) -> Result<(), ProofSystemError> {
+        let wallet_proof = self.wallet_proof(proof)?;
+        if wallet_proof.old_root() != *old_root || wallet_proof.new_root() != *new_root {
+            return Err(invalid_proof("proof is for other roots"));
+        }
+        wallet_circuit()?.verify(&wallet_proof).map_err(invalid_proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{Keypair, SECP256K1};
+
+    use super::*;
+    use crate::channel::ChannelParticipants;
+    use crate::clock::ManualClock;
+    use crate::merkle_circuit::state_tree_root;
+    use crate::signing::x_only_public_key;
+    use crate::state_proof::STATE_PROOF_VERSION;
+
+    fn signer() -> Keypair { Keypair::from_seckey_slice(SECP256K1, &[1u8; 32]).unwrap() }
+
+    fn receiver() -> Keypair { Keypair::from_seckey_slice(SECP256K1, &[2u8; 32]).unwrap() }
+
+    fn clock() -> ManualClock { ManualClock::new(1_000, 800_000) }
+
+    fn channel(balance: u64) -> ChannelState {
+        let participants = ChannelParticipants {
+            sender: x_only_public_key(&signer()),
+            receiver: x_only_public_key(&receiver()),
+        };
+        ChannelState::new(balance, Vec::new(), participants).unwrap()
+    }
+
+    fn transfer(prior: &ChannelState, amount: u64) -> ChannelState {
+        let mut next = prior.transfer(amount, &clock()).unwrap();
+        next.sign(&signer()).unwrap();
+        next
+    }
+
+    #[test]
+    fn test_mock_transition_proof() -> Result<(), ProofSystemError> {
+        let mock = MockProofSystem::default();
+        let prior = channel(100);
+        let next = transfer(&prior, 30);
+
+        let proof = mock.prove_transition(&[1u8; 32], &prior, &next)?;
+        assert_eq!(proof.system, ProofSystemId::MockHash);
+        mock.verify_transition(&proof, &[1u8; 32], Some(&prior), &next)?;
+        mock.verify_transition(&proof, &[1u8; 32], None, &next)?;
+
+        // The proof is bound to the channel and states it was generated for
+        assert!(mock.verify_transition(&proof, &[2u8; 32], Some(&prior), &next).is_err());
+        assert!(mock.verify_transition(&proof, &[1u8; 32], Some(&next), &next).is_err());
+        assert!(mock.verify_transition(&proof, &[1u8; 32], None, &transfer(&prior, 31)).is_err());
+        let mut tampered = proof.clone();
+        tampered.public_inputs[2] = [4u8; 32];
+        assert!(mock.verify_transition(&tampered, &[1u8; 32], Some(&prior), &next).is_err());
+
+        // Invalid transitions cannot be proven
+        let unsigned = prior.transfer(30, &clock()).unwrap();
+        assert!(mock.prove_transition(&[1u8; 32], &prior, &unsigned).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plonky2_transition_proof() -> Result<(), ProofSystemError> {
+        fn signed(mut state: ChannelState, keypair: Keypair) -> ChannelState {
+            state.sign(&keypair).unwrap();
+            state
+        }
+        let steps: [fn(&ChannelState) -> ChannelState; 5] = [
+            |state| signed(state.deposit(50, [5u8; 32], &clock()).unwrap(), signer()),
+            |state| transfer(state, 70),
+            |state| signed(state.transfer_batch(&[10, 20], &clock()).unwrap(), signer()),
+            |state| signed(state.claim(30, &clock()).unwrap(), receiver()),
+            |state| signed(state.close(&clock()).unwrap(), signer()),
+        ];
+
+        // Every kind the circuit models is proven and bound to its channel.
+        // Proofs are stamped by the injected clock.
+        let system = Plonky2ProofSystem::new(Arc::new(clock()));
+        let mut prior = channel(100);
+        for step in steps {
+            let next = step(&prior);
+            let proof = system.prove_transition(&[1u8; 32], &prior, &next)?;
+            assert_eq!(proof.public_inputs[3], [1u8; 32]);
+            assert_eq!((proof.timestamp, proof.block_height), (1_000, Some(800_000)));
+            system.verify_transition(&proof, &[1u8; 32], Some(&prior), &next)?;
+            system.verify_transition(&proof, &[1u8; 32], None, &next)?;
+            assert!(system.verify_transition(&proof, &[2u8; 32], Some(&prior), &next).is_err());
+            prior = next;
+        }
+
+        // Expiry is checked against the system's clock, which the circuit lacks
+        let expiring = channel(100).with_expiry(1_500).unwrap();
+        let closed = signed(expiring.close(&clock()).unwrap(), signer());
+        let late = Plonky2ProofSystem::new(Arc::new(ManualClock::new(1_500, 800_000)));
+        assert!(late.prove_transition(&[1u8; 32], &expiring, &closed).is_err());
+
+        // Transitions outside the circuit are reported as unsupported
+        let paid = transfer(&channel(100), 30);
+        let refund = ChannelState { transition: ChannelTransition::Refund, ..paid.clone() };
+        assert_eq!(
+            system.prove_transition(&[1u8; 32], &paid, &refund),
+            Err(ProofSystemError::Unsupported("Refund".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mock_wallet_update() -> Result<(), ProofSystemError> {
+        let mock = MockProofSystem::default();
+        let channels = [channel(100), channel(50)];
+        let mut leaves = channels.iter().map(|c| hash_state(c).unwrap()).collect::<Vec<_>>();
+        let old_root = state_tree_root(&leaves).unwrap();
+
+        let mut updates = Vec::new();
+        let mut states = channels.to_vec();
+        for (index, amount) in [(0, 10), (1, 5), (0, 20)] {
+            let path = StateTreePath::new(&leaves, index).unwrap();
+            let next = transfer(&states[index], amount);
+            let transition_proof = mock.prove_transition(&[1u8; 32], &states[index], &next)?;
+            leaves[index] = hash_state(&next).unwrap();
+            states[index] = next;
+            updates.push(ProvenChannelUpdate { transition_proof, path });
+        }
+        let new_root = state_tree_root(&leaves).unwrap();
+
+        let proof = mock.prove_wallet_update(&old_root, &updates)?;
+        assert_eq!(proof.public_inputs, vec![old_root, new_root]);
+        mock.verify_wallet_update(&proof, &old_root, &new_root)?;
+        assert!(mock.verify_wallet_update(&proof, &new_root, &old_root).is_err());
+
+        // Updates must apply to the root they are proven against
+        updates.swap(0, 2);
+        assert!(mock.prove_wallet_update(&old_root, &updates).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_serialization() -> Result<(), ProofSystemError> {
+        let mock = MockProofSystem::default();
+        let prior = channel(100);
+        let proof = mock.prove_transition(&[1u8; 32], &prior, &transfer(&prior, 30))?;
+
+        let bytes = mock.serialize(&proof)?;
+        assert_eq!(mock.deserialize(&bytes)?, proof);
+
+        // Proofs of other systems are rejected
+        assert_eq!(
+            Plonky2ProofSystem::default().deserialize(&bytes),
+            Err(ProofSystemError::WrongSystem {
+                expected: ProofSystemId::Plonky2,
+                found: ProofSystemId::MockHash,
+            })
+        );
+        assert!(Plonky2ProofSystem::default().serialize(&proof).is_err());
+
+        // So are envelopes of unknown versions
+        let future = StateProof { version: STATE_PROOF_VERSION + 1, ..proof };
+        assert_eq!(
+            mock.deserialize(&future.to_bytes()?),
+            Err(ProofSystemError::UnsupportedVersion(STATE_PROOF_VERSION + 1))
+        );
+        assert!(matches!(mock.deserialize(b"junk"), Err(ProofSystemError::Serialization(_))));
+        Ok(())
+    }
+}
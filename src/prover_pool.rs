@@ -0,0 +1,247 @@
+//! Asynchronous proving of channel transitions
+//!
+//! `StateTransitionCircuit::generate_zkp` is synchronous and takes long enough
+//! to stall an async runtime. `ProverPool` queues proof requests on a bounded
+//! channel and runs them on tokio's blocking threads, at most `workers` at a
+//! time, all sharing one circuit. Each request gets a `ProofHandle` future
+//! that resolves to its proof.
+//!
+//! When the queue is full, `submit` waits for space and `try_submit` fails
+//! with `ProverPoolError::QueueFull`, so callers choose how to apply
+//! backpressure. Dropping or cancelling a handle withdraws its request if
+//! proving has not started yet; a proof already being generated runs to
+//! completion and is discarded.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use thiserror::Error;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::task::JoinHandle;
+
+use crate::channel::ChannelState;
+use crate::state_transition::{StateTransitionCircuit, TransitionData};
+use crate::types::Bytes32;
+
+/// Transition proof produced by the pool.
+pub type TransitionProof = ProofWithPublicInputs<GoldilocksField, PoseidonGoldilocksConfig, 2>;
+
+/// Represents errors in ProverPool operations.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ProverPoolError {
+    #[error("Proof queue is full")]
+    QueueFull,
+    #[error("Prover pool has shut down")]
+    ShutDown,
+    #[error("Proof generation failed: {0}")]
+    ProofGeneration(String),
+}
+
+/// Sizing of a `ProverPool`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProverPoolConfig {
+    /// Number of proofs generated concurrently
+    pub workers: usize,
+    /// Number of requests that can wait for a worker
+    pub queue_capacity: usize,
+}
+
+impl Default for ProverPoolConfig {
+    fn default() -> Self {
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self { workers, queue_capacity: 1024 }
+    }
+}
+
+/// A queued proof request.
+struct ProofJob {
+    channel_id: Bytes32,
+    initial_state: ChannelState,
+    transition_data: TransitionData,
+    reply: oneshot::Sender<Result<TransitionProof, ProverPoolError>>,
+}
+
+/// Pending result of a proof request.
+#[derive(Debug)]
+pub struct ProofHandle {
+    receiver: oneshot::Receiver<Result<TransitionProof, ProverPoolError>>,
+}
+
+impl ProofHandle {
+    /// Withdraws the request. Same as dropping the handle.
+    pub fn cancel(self) {}
+}
+
+impl Future for ProofHandle {
+    type Output = Result<TransitionProof, ProverPoolError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The reply is only dropped unanswered when the pool shuts down.
+        let reply = ready!(Pin::new(&mut self.receiver).poll(cx));
+        Poll::Ready(reply.unwrap_or(Err(ProverPoolError::ShutDown)))
+    }
+}
+
+/// Pool of blocking workers proving transitions of a shared circuit.
+pub struct ProverPool {
+    jobs: mpsc::Sender<ProofJob>,
+    dispatcher: JoinHandle<()>,
+}
+
+impl ProverPool {
+    /// Starts a pool proving with `circuit`. Must be called from within a
+    /// tokio runtime.
+    pub fn new(circuit: Arc<StateTransitionCircuit>, config: ProverPoolConfig) -> Self {
+        let (jobs, receiver) = mpsc::channel(config.queue_capacity.max(1));
+        let dispatcher = tokio::spawn(dispatch(circuit, receiver, config.workers.max(1)));
+        Self { jobs, dispatcher }
+    }
+
+    /// Queues a proof of `transition_data` applied to `initial_state` in
+    /// channel `channel_id`, waiting for space in the queue.
+    pub async fn submit(
+        &self,
+        channel_id: Bytes32,
+        initial_state: ChannelState,
+        transition_data: TransitionData,
+    ) -> Result<ProofHandle, ProverPoolError> {
+        let (job, handle) = new_job(channel_id, initial_state, transition_data);
+        self.jobs.send(job).await.map_err(|_| ProverPoolError::ShutDown)?;
+        Ok(handle)
+    }
+
+    /// Queues a proof like `submit`, but fails with `QueueFull` instead of
+    /// waiting for space.
+    pub fn try_submit(
+        &self,
+        channel_id: Bytes32,
+        initial_state: ChannelState,
+        transition_data: TransitionData,
+    ) -> Result<ProofHandle, ProverPoolError> {
+        let (job, handle) = new_job(channel_id, initial_state, transition_data);
+        self.jobs.try_send(job).map_err(|e| match e {
+            TrySendError::Full(_) => ProverPoolError::QueueFull,
+            TrySendError::Closed(_) => ProverPoolError::ShutDown,
+        })?;
+        Ok(handle)
+    }
+
+    /// Number of requests waiting in the queue.
+    pub fn queued(&self) -> usize { self.jobs.max_capacity() - self.jobs.capacity() }
+
+    /// Stops accepting requests and waits until every queued request has
+    /// been proven or withdrawn.
+    pub async fn shutdown(self) {
+        drop(self.jobs);
+        // The dispatcher only ends once its queue is closed and drained.
+        let _ = self.dispatcher.await;
+    }
+}
+
+fn new_job(
+    channel_id: Bytes32,
+    initial_state: ChannelState,
+    transition_data: TransitionData,
+) -> (ProofJob, ProofHandle) {
+    let (reply, receiver) = oneshot::channel();
+    let job = ProofJob { channel_id, initial_state, transition_data, reply };
+    (job, ProofHandle { receiver })
+}
+
+/// Hands queued jobs to blocking workers, at most `workers` at a time.
+async fn dispatch(
+    circuit: Arc<StateTransitionCircuit>,
+    mut jobs: mpsc::Receiver<ProofJob>,
+    workers: usize,
+) {
+    let permits = Arc::new(Semaphore::new(workers));
+    while let Some(job) = jobs.recv().await {
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            return;
+        };
+        let circuit = circuit.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            // Skip requests whose handle was dropped while they were queued.
+            if job.reply.is_closed() {
+                return;
+            }
+            let result = circuit
+                .generate_zkp(&job.channel_id, &job.initial_state, &job.transition_data)
+                .map_err(|e| ProverPoolError::ProofGeneration(e.to_string()));
+            let _ = job.reply.send(result);
+        });
+    }
+
+    // Wait for the running workers to finish.
+    let _ = permits.acquire_many(workers as u32).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::ChannelParticipants;
+    use crate::state_transition::apply_transition;
+
+    const CHANNEL_ID: Bytes32 = [4u8; 32];
+
+    fn channel(balance: u64) -> ChannelState {
+        let participants = ChannelParticipants { sender: [2u8; 32], receiver: [3u8; 32] };
+        ChannelState::new(balance, Vec::new(), participants).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_prover_pool() -> anyhow::Result<()> {
+        let circuit = Arc::new(StateTransitionCircuit::new());
+        let config = ProverPoolConfig { workers: 2, queue_capacity: 4 };
+        let pool = ProverPool::new(circuit.clone(), config);
+
+        let mut requests = Vec::new();
+        for balance in [10, 20, 30] {
+            let data = TransitionData::transfer(balance / 2);
+            let handle = pool.submit(CHANNEL_ID, channel(balance), data).await?;
+            requests.push((channel(balance), data, handle));
+        }
+        let cancelled = pool.submit(CHANNEL_ID, channel(40), TransitionData::transfer(1)).await?;
+        cancelled.cancel();
+
+        for (initial, data, handle) in requests {
+            let proof = handle.await?;
+            let next = apply_transition(&initial, &data)?;
+            assert!(circuit.verify_transition(proof, &CHANNEL_ID, &initial, &next)?);
+        }
+
+        // Failures are reported per request
+        let overdrawn = pool.submit(CHANNEL_ID, channel(5), TransitionData::transfer(6)).await?;
+        assert!(matches!(overdrawn.await, Err(ProverPoolError::ProofGeneration(_))));
+
+        pool.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prover_pool_backpressure() {
+        let circuit = Arc::new(StateTransitionCircuit::new());
+        let config = ProverPoolConfig { workers: 1, queue_capacity: 1 };
+        let pool = ProverPool::new(circuit, config);
+
+        // The single-threaded runtime does not run the dispatcher before the
+        // test yields, so the first request stays queued.
+        let queued = pool.try_submit(CHANNEL_ID, channel(10), TransitionData::transfer(1)).unwrap();
+        assert_eq!(pool.queued(), 1);
+        assert_eq!(
+            pool.try_submit(CHANNEL_ID, channel(10), TransitionData::transfer(2)).unwrap_err(),
+            ProverPoolError::QueueFull
+        );
+
+        // A withdrawn request is dropped without being proven
+        queued.cancel();
+        pool.shutdown().await;
+    }
+}
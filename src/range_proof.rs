@@ -0,0 +1,228 @@
+//! Range proofs for committed channel balances
+//!
+//! This module proves, with Bulletproofs over the channel's Pedersen
+//! generators, that committed balances and transfer amounts lie in
+//! `[0, 2^64)` without revealing them.
+
+use bulletproofs::{BulletproofGens, PedersenGens, RangeProof};
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use serde::{Deserialize, Serialize};
+
+use crate::commitments::ChannelCommitment;
+use crate::error::ZkpError;
+use crate::pedersen_parameters::PedersenParameters;
+use crate::types::Bytes32;
+
+/// Bit length of every range proof.
+pub const RANGE_BITS: usize = 64;
+
+/// Domain separator for channel balance range proofs.
+const BALANCES_LABEL: &[u8] = b"overpass.channel.balances";
+
+/// Domain separator for channel transfer amount range proofs.
+const TRANSFER_LABEL: &[u8] = b"overpass.channel.transfer";
+
+/// Channel and state a range proof is bound to. Both are absorbed into the
+/// proof transcripts, so a proof cannot be replayed into another channel or
+/// at another nonce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeProofBinding {
+    /// Digest identifying the channel, see `ChannelState::channel_digest`
+    pub channel: Bytes32,
+    /// Nonce of the state carrying the proof
+    pub nonce: u64,
+}
+
+/// Range proofs attached to a committed channel state.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChannelRangeProof {
+    /// Aggregated proof that the sender and receiver balances are in range
+    pub balances: Vec<u8>,
+    /// Proof that the amount moved out of the sender balance is in range,
+    /// present when the state follows a transfer
+    pub transfer: Option<Vec<u8>>,
+}
+
+impl ChannelRangeProof {
+    /// Proves that the balances committed in `commitment` are in range.
+    ///
+    /// When `prior` is given, also proves that `transfer_amount`, committed as
+    /// the difference of the two sender commitments, is in range.
+    pub fn prove(
+        binding: &RangeProofBinding,
+        prior: Option<&ChannelCommitment>,
+        commitment: &ChannelCommitment,
+        sender_balance: u64,
+        receiver_balance: u64,
+        transfer_amount: u64,
+        params: &PedersenParameters,
+    ) -> Result<Self, ZkpError> {
+        let pc_gens = pedersen_gens(params);
+        let bp_gens = BulletproofGens::new(RANGE_BITS, 2);
+
+        let (balances, _) = RangeProof::prove_multiple(
+            &bp_gens,
+            &pc_gens,
+            &mut transcript(BALANCES_LABEL, binding),
+            &[sender_balance, receiver_balance],
+            &[commitment.sender.blinding_scalar(), commitment.receiver.blinding_scalar()],
+            RANGE_BITS,
+        )
+        .map_err(|_| ZkpError::ProofGenerationFailed)?;
+
+        let transfer = match prior {
+            Some(prior) => {
+                let blinding = transfer_blinding(prior, commitment);
+                let (proof, _) = RangeProof::prove_single(
+                    &bp_gens,
+                    &pc_gens,
+                    &mut transcript(TRANSFER_LABEL, binding),
+                    transfer_amount,
+                    &blinding,
+                    RANGE_BITS,
+                )
+                .map_err(|_| ZkpError::ProofGenerationFailed)?;
+                Some(proof.to_bytes())
+            }
+            None => None,
+        };
+
+        Ok(Self { balances: balances.to_bytes(), transfer })
+    }
+
+    /// Verifies the proofs against the commitment points only.
+    ///
+    /// When `prior` is given, a transfer proof is required and checked against
+    /// the difference of the prior and current sender commitments.
+    pub fn verify(
+        &self,
+        binding: &RangeProofBinding,
+        prior: Option<&ChannelCommitment>,
+        commitment: &ChannelCommitment,
+        params: &PedersenParameters,
+    ) -> Result<(), ZkpError> {
+        let pc_gens = pedersen_gens(params);
+        let bp_gens = BulletproofGens::new(RANGE_BITS, 2);
+
+        let balances =
+            RangeProof::from_bytes(&self.balances).map_err(|_| ZkpError::InvalidProofData)?;
+        balances
+            .verify_multiple(
+                &bp_gens,
+                &pc_gens,
+                &mut transcript(BALANCES_LABEL, binding),
+                &[
+                    CompressedRistretto(commitment.sender.point),
+                    CompressedRistretto(commitment.receiver.point),
+                ],
+                RANGE_BITS,
+            )
+            .map_err(|_| ZkpError::InvalidProof)?;
+
+        if let Some(prior) = prior {
+            let transfer_bytes = self.transfer.as_ref().ok_or(ZkpError::InvalidProofData)?;
+            let transfer =
+                RangeProof::from_bytes(transfer_bytes).map_err(|_| ZkpError::InvalidProofData)?;
+            let prior_sender = prior.sender.decompress().ok_or(ZkpError::InvalidProofData)?;
+            let sender = commitment.sender.decompress().ok_or(ZkpError::InvalidProofData)?;
+            let amount_commitment = (prior_sender - sender).compress();
+            transfer
+                .verify_single(
+                    &bp_gens,
+                    &pc_gens,
+                    &mut transcript(TRANSFER_LABEL, binding),
+                    &amount_commitment,
+                    RANGE_BITS,
+                )
+                .map_err(|_| ZkpError::InvalidProof)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Starts a proof transcript bound to `binding`.
+fn transcript(label: &'static [u8], binding: &RangeProofBinding) -> Transcript {
+    let mut transcript = Transcript::new(label);
+    transcript.append_message(b"channel", &binding.channel);
+    transcript.append_u64(b"nonce", binding.nonce);
+    transcript
+}
+
+/// Uses the channel's Pedersen parameters as Bulletproofs generators.
+fn pedersen_gens(params: &PedersenParameters) -> PedersenGens {
+    PedersenGens { B: params.g, B_blinding: params.h }
+}
+
+/// Blinding of the commitment to the amount moved out of the sender balance.
+fn transfer_blinding(prior: &ChannelCommitment, commitment: &ChannelCommitment) -> Scalar {
+    prior.sender.blinding_scalar() - commitment.sender.blinding_scalar()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINDING: RangeProofBinding = RangeProofBinding { channel: [7u8; 32], nonce: 1 };
+
+    #[test]
+    fn test_prove_and_verify_balances() {
+        let params = PedersenParameters::default();
+        let commitment = ChannelCommitment::random(100, 0, &params);
+
+        let proof =
+            ChannelRangeProof::prove(&BINDING, None, &commitment, 100, 0, 0, &params).unwrap();
+        assert!(proof.transfer.is_none());
+        assert!(proof.verify(&BINDING, None, &commitment, &params).is_ok());
+
+        // The proof does not verify against other commitments
+        let other = ChannelCommitment::random(100, 0, &params);
+        assert_eq!(proof.verify(&BINDING, None, &other, &params), Err(ZkpError::InvalidProof));
+    }
+
+    #[test]
+    fn test_prove_and_verify_transfer() {
+        let params = PedersenParameters::default();
+        let prior = ChannelCommitment::random(100, 0, &params);
+        let next = prior.next(70, 30, &params);
+
+        let proof =
+            ChannelRangeProof::prove(&BINDING, Some(&prior), &next, 70, 30, 30, &params).unwrap();
+        assert!(proof.verify(&BINDING, Some(&prior), &next, &params).is_ok());
+
+        // The proof is bound to its channel and nonce
+        for binding in [
+            RangeProofBinding { channel: [8u8; 32], ..BINDING },
+            RangeProofBinding { nonce: 2, ..BINDING },
+        ] {
+            assert_eq!(
+                proof.verify(&binding, Some(&prior), &next, &params),
+                Err(ZkpError::InvalidProof)
+            );
+        }
+
+        // A proof without a transfer part is rejected for a transition
+        let balances_only = ChannelRangeProof { transfer: None, ..proof.clone() };
+        assert_eq!(
+            balances_only.verify(&BINDING, Some(&prior), &next, &params),
+            Err(ZkpError::InvalidProofData)
+        );
+    }
+
+    #[test]
+    fn test_negative_transfer_rejected() {
+        let params = PedersenParameters::default();
+        let prior = ChannelCommitment::random(70, 30, &params);
+        // Sender balance increases, so the committed amount is negative
+        let next = prior.next(100, 0, &params);
+
+        let proof =
+            ChannelRangeProof::prove(&BINDING, Some(&prior), &next, 100, 0, 0, &params).unwrap();
+        assert_eq!(
+            proof.verify(&BINDING, Some(&prior), &next, &params),
+            Err(ZkpError::InvalidProof)
+        );
+    }
+}
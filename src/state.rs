@@ -2,70 +2,128 @@
 
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use plonky2::hash::hash_types::HashOut;
 use plonky2::hash::poseidon::PoseidonHash;
 use plonky2::plonk::config::Hasher;
 use plonky2_field::goldilocks_field::GoldilocksField;
-use plonky2_field::types::{Field, PrimeField64};
+use plonky2_field::types::Field;
 use sha2::{Digest, Sha256};
 
-use crate::channel::ChannelState;
+use crate::channel::{ChannelState, ChannelTransition};
+use crate::clock::Clock;
+use crate::codec::{bytes_to_limbs, hash_out_to_bytes, pack_bytes, u64_to_limbs};
 use crate::pedersen_parameters::PedersenParameters;
-
+use crate::proof_system::ProofSystemId;
+use crate::state_proof::StateProof;
 use crate::types::Bytes32;
+
+/// Number of field elements hashed by `hash_state`.
+pub const STATE_HASH_INPUTS: usize = 31;
+
+/// Position of the memo flag within `state_hash_inputs`, followed by the memo.
+pub const MEMO_OFFSET: usize = 17;
+
+/// Position of the lifecycle tag within `state_hash_inputs`.
+pub const LIFECYCLE_OFFSET: usize = 26;
+
+/// Position of the channel aux digest within `state_hash_inputs`.
+pub const AUX_DIGEST_OFFSET: usize = 27;
+
+/// Number of field elements hashed by `channel_aux_digest`.
+pub const CHANNEL_AUX_INPUTS: usize = 23;
+
+/// Position of the receiver key limbs within `channel_aux_inputs`.
+pub const AUX_RECEIVER_OFFSET: usize = 8;
+
 /// Converts ChannelState into a 32-byte hash using PoseidonHash.
 pub fn hash_state(state: &ChannelState) -> anyhow::Result<Bytes32> {
-    let mut inputs = Vec::new();
+    let hash_out = PoseidonHash::hash_no_pad(&state_hash_inputs(state)?);
+    Ok(hash_out_to_bytes(&hash_out))
+}
 
-    inputs.push(GoldilocksField::from_canonical_u64(state.sender_balance));
-    inputs.push(GoldilocksField::from_canonical_u64(state.receiver_balance));
+/// Field elements hashed by `hash_state`, laid out as
+/// `[sender_balance (2 limbs), receiver_balance (2 limbs), nonce (2 limbs),
+/// transition tag, transition amount (2 limbs), transition reference
+/// (8 limbs), memo flag, memo (8 limbs), lifecycle tag, channel aux digest
+/// (4 elements)]`. The reference is the claim recipient or the deposit
+/// funding, and zero otherwise; the memo is zero without one.
+///
+/// Every u64 is split by `u64_to_limbs`, so any balance, nonce or amount can
+/// be hashed. The layout has a fixed length so `StateTransitionCircuit` can
+/// recompute it.
+pub fn state_hash_inputs(state: &ChannelState) -> anyhow::Result<Vec<GoldilocksField>> {
+    let mut inputs = Vec::with_capacity(STATE_HASH_INPUTS);
 
-    inputs.push(GoldilocksField::from_canonical_u64(state.nonce));
+    inputs.extend(u64_to_limbs(state.sender_balance));
+    inputs.extend(u64_to_limbs(state.receiver_balance));
 
-    for &byte in &state.metadata {
-        inputs.push(GoldilocksField::from_canonical_u8(byte));
-    }
+    inputs.extend(u64_to_limbs(state.nonce));
 
-    let hash_out = PoseidonHash::hash_no_pad(&inputs);
-    let mut bytes = [0u8; 32];
-    for (i, &element) in hash_out.elements.iter().enumerate() {
-        let elem_u64 = element.to_canonical_u64();
-        bytes[i * 8..(i + 1) * 8].copy_from_slice(&elem_u64.to_le_bytes());
-    }
+    inputs.push(GoldilocksField::from_canonical_u8(state.transition.tag()));
+    inputs.extend(u64_to_limbs(state.transition.amount()));
+    let reference = match &state.transition {
+        ChannelTransition::Claim { output } => output.recipient,
+        ChannelTransition::Deposit { funding, .. } => *funding,
+        _ => [0u8; 32],
+    };
+    inputs.extend(bytes_to_limbs(&reference));
 
-    Ok(bytes)
-}
+    inputs.push(GoldilocksField::from_bool(state.memo.is_some()));
+    inputs.extend(bytes_to_limbs(&state.memo.unwrap_or_default()));
 
-pub fn current_timestamp() -> u64 {
-    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards");
-    now.as_secs()
+    inputs.push(GoldilocksField::from_canonical_u8(state.lifecycle.tag()));
+
+    inputs.extend(channel_aux_digest(state)?.elements);
+
+    Ok(inputs)
 }
 
-#[derive(Debug, Clone)]
-pub struct StateProof {
-    pub pi: Bytes32,
-    pub public_inputs: Vec<Bytes32>,
-    pub timestamp: u64,
-    pub params: PedersenParameters,
+/// Poseidon digest of the channel fields that do not change on transfers:
+/// participant keys, expiry and the `metadata_digest`.
+pub fn channel_aux_digest(state: &ChannelState) -> anyhow::Result<HashOut<GoldilocksField>> {
+    Ok(PoseidonHash::hash_no_pad(&channel_aux_inputs(state)))
 }
 
-pub fn convert_helper_proof(proof: StateProof) -> crate::state_proof::StateProof {
-    crate::state_proof::StateProof {
-        pi: proof.pi,
-        public_inputs: proof.public_inputs,
-        timestamp: proof.timestamp,
+/// Field elements hashed by `channel_aux_digest`, laid out as
+/// `[sender key (8 limbs), receiver key (8 limbs), expiry flag,
+/// expiry (2 limbs), metadata digest (4 elements)]`.
+pub fn channel_aux_inputs(state: &ChannelState) -> Vec<GoldilocksField> {
+    let mut inputs = Vec::with_capacity(CHANNEL_AUX_INPUTS);
+
+    for key in [&state.participants.sender, &state.participants.receiver] {
+        inputs.extend(bytes_to_limbs(key));
     }
+
+    inputs.push(GoldilocksField::from_bool(state.expiry.is_some()));
+    inputs.extend(u64_to_limbs(state.expiry.unwrap_or_default()));
+
+    inputs.extend(metadata_digest(&state.metadata).elements);
+
+    inputs
+}
+
+/// Poseidon digest of channel metadata, absorbed as length-prefixed 7-byte
+/// limbs so that hashing costs one field element per seven bytes.
+pub fn metadata_digest(metadata: &[u8]) -> HashOut<GoldilocksField> {
+    PoseidonHash::hash_no_pad(&pack_bytes(metadata))
+}
+
+pub fn current_timestamp() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards");
+    now.as_secs()
 }
 
-pub fn verify_wallet_proof(
+/// Checks a mock wallet proof: it must move `old_root` to `new_root` and
+/// `pi` must be the `mock_proof_digest` of the envelope. Anyone can compute
+/// that hash, so this proves nothing about the roots; it backs
+/// `MockProofSystem` only. Freshness is left to the verifier's
+/// `ProofFreshness`, e.g. in `GlobalRootContract::update_wallet`.
+pub fn verify_mock_wallet_proof(
     old_root: &Bytes32,
     new_root: &Bytes32,
     proof: &StateProof,
-    _params: &PedersenParameters,
+    params: &PedersenParameters,
 ) -> bool {
-    if current_timestamp().saturating_sub(proof.timestamp) > 3600 {
-        return false;
-    }
-
     if proof.public_inputs.len() < 2 {
         return false;
     }
@@ -73,13 +131,26 @@ pub fn verify_wallet_proof(
         return false;
     }
 
+    verify_mock_proof(proof, params)
+}
+
+/// Checks that a mock proof's `pi` matches the rest of its envelope.
+pub fn verify_mock_proof(proof: &StateProof, params: &PedersenParameters) -> bool {
+    proof.system == ProofSystemId::MockHash && proof.pi == mock_proof_digest(proof, params)
+}
+
+/// SHA-256 of the public inputs, timestamp, block height and generators,
+/// used as `pi` by mock proofs.
+pub fn mock_proof_digest(proof: &StateProof, params: &PedersenParameters) -> Bytes32 {
     let mut hasher = Sha256::new();
     proof.public_inputs.iter().for_each(|input| hasher.update(input));
     hasher.update(proof.timestamp.to_le_bytes());
-    hasher.update(proof.params.g.compress().as_bytes());
-    hasher.update(proof.params.h.compress().as_bytes());
-
-    proof.pi == hasher.finalize().as_slice()
+    if let Some(block_height) = proof.block_height {
+        hasher.update(block_height.to_le_bytes());
+    }
+    hasher.update(params.g.compress().as_bytes());
+    hasher.update(params.h.compress().as_bytes());
+    hasher.finalize().into()
 }
 
 pub fn verify_zk_proof(
@@ -103,36 +174,40 @@ pub fn verify_zk_proof(
     proof == &expected
 }
 
+/// Generates a mock proof over `[old_commitment, new_commitment,
+/// merkle_root]`, stamped with `clock`'s reading; see `mock_proof_digest`.
 pub fn generate_state_proof(
     old_commitment: Bytes32,
     new_commitment: Bytes32,
     merkle_root: Bytes32,
     params: &PedersenParameters,
+    clock: &dyn Clock,
 ) -> StateProof {
-    let mut hasher = Sha256::new();
-    hasher.update(old_commitment);
-    hasher.update(new_commitment);
-    hasher.update(merkle_root);
-
-    let timestamp = current_timestamp();
-    hasher.update(timestamp.to_le_bytes());
-    hasher.update(params.g.compress().as_bytes());
-    hasher.update(params.h.compress().as_bytes());
-
-    let mut pi = [0u8; 32];
-    pi.copy_from_slice(&hasher.finalize());
+    let public_inputs = vec![old_commitment, new_commitment, merkle_root];
+    generate_mock_proof(public_inputs, params, clock)
+}
 
-    StateProof {
-        pi,
-        public_inputs: vec![old_commitment, new_commitment, merkle_root],
-        timestamp,
-        params: params.clone(),
-    }
+/// Generates a mock proof over `public_inputs`, stamped with `clock`'s
+/// reading.
+pub fn generate_mock_proof(
+    public_inputs: Vec<Bytes32>,
+    params: &PedersenParameters,
+    clock: &dyn Clock,
+) -> StateProof {
+    let mut proof =
+        StateProof::new(ProofSystemId::MockHash, Vec::new(), public_inputs, clock.now());
+    proof.block_height = clock.block_height();
+    proof.pi = mock_proof_digest(&proof, params).to_vec();
+    proof
 }
 
 #[cfg(test)]
 mod tests {
+    use plonky2_field::types::Field64;
+
     use super::*;
+    use crate::channel::ChannelParticipants;
+    use crate::clock::ManualClock;
 
     #[test]
     fn test_current_timestamp_nonzero() {
@@ -140,18 +215,96 @@ mod tests {
         assert!(ts > 0);
     }
 
+    #[test]
+    fn test_state_hash_inputs_layout() {
+        let participants = ChannelParticipants { sender: [2u8; 32], receiver: [3u8; 32] };
+        let state = ChannelState::new(100, b"meta".to_vec(), participants).unwrap();
+        let next = state.transfer(30, &ManualClock::new(1_000, 800_000)).unwrap();
+
+        let inputs = state_hash_inputs(&state).unwrap();
+        let next_inputs = state_hash_inputs(&next).unwrap();
+        assert_eq!(inputs.len(), STATE_HASH_INPUTS);
+        assert_eq!(next_inputs.len(), STATE_HASH_INPUTS);
+        assert_eq!(next_inputs[..2], u64_to_limbs(70));
+        assert_eq!(next_inputs[4..6], u64_to_limbs(1));
+
+        // Transfers leave the channel aux digest untouched
+        assert_eq!(inputs[AUX_DIGEST_OFFSET..], next_inputs[AUX_DIGEST_OFFSET..]);
+        let other = ChannelState { metadata: b"other".to_vec(), ..state.clone() };
+        assert_ne!(channel_aux_digest(&other).unwrap(), channel_aux_digest(&state).unwrap());
+        assert_ne!(hash_state(&other).unwrap(), hash_state(&state).unwrap());
+
+        // Metadata differing only in trailing zeros hashes differently
+        let padded = ChannelState { metadata: b"meta\0".to_vec(), ..state.clone() };
+        assert_ne!(hash_state(&padded).unwrap(), hash_state(&state).unwrap());
+        assert_ne!(metadata_digest(b""), metadata_digest(b"\0"));
+
+        // The memo is committed to, and an all-zero memo differs from none
+        let noted = ChannelState { memo: Some([7u8; 32]), ..next.clone() };
+        let noted_inputs = state_hash_inputs(&noted).unwrap();
+        assert_eq!(noted_inputs[MEMO_OFFSET], GoldilocksField::ONE);
+        assert_eq!(noted_inputs[MEMO_OFFSET + 1..LIFECYCLE_OFFSET], bytes_to_limbs(&[7u8; 32]));
+        let blank = ChannelState { memo: Some([0u8; 32]), ..next.clone() };
+        assert_ne!(hash_state(&blank).unwrap(), hash_state(&next).unwrap());
+        assert_ne!(hash_state(&blank).unwrap(), hash_state(&noted).unwrap());
+    }
+
+    #[test]
+    fn test_hash_state_accepts_any_u64() {
+        let participants = ChannelParticipants { sender: [2u8; 32], receiver: [3u8; 32] };
+        let state = ChannelState::new(u64::MAX, Vec::new(), participants).unwrap();
+        let exhausted = ChannelState { nonce: u64::MAX, ..state.clone() };
+        assert!(hash_state(&state).is_ok());
+        assert_ne!(hash_state(&exhausted).unwrap(), hash_state(&state).unwrap());
+
+        // Values at or above the field order do not collide with their reduction
+        let reduced = ChannelState { nonce: u64::MAX - GoldilocksField::ORDER, ..state.clone() };
+        assert_ne!(hash_state(&exhausted).unwrap(), hash_state(&reduced).unwrap());
+
+        // Expiries are split into limbs too, so any timestamp can be hashed
+        let expiring = ChannelState::new(100, Vec::new(), participants).unwrap();
+        assert!(hash_state(&expiring.with_expiry(u64::MAX).unwrap()).is_ok());
+    }
+
     #[test]
     fn test_generate_and_verify_state_proof() {
         let params = PedersenParameters::default();
+        let clock = ManualClock::new(1_000, 800_000);
         let old_commitment = [1u8; 32];
         let new_commitment = [2u8; 32];
         let merkle_root = [3u8; 32];
 
-        let proof = generate_state_proof(old_commitment, new_commitment, merkle_root, &params);
+        let proof =
+            generate_state_proof(old_commitment, new_commitment, merkle_root, &params, &clock);
 
         assert_eq!(proof.public_inputs.len(), 3);
         assert_eq!(proof.public_inputs[0], old_commitment);
         assert_eq!(proof.public_inputs[1], new_commitment);
         assert_eq!(proof.public_inputs[2], merkle_root);
+        assert_eq!((proof.timestamp, proof.block_height), (1_000, Some(800_000)));
+        assert!(verify_mock_proof(&proof, &params));
+
+        // Wallet proofs bind the roots they move between
+        assert!(verify_mock_wallet_proof(&old_commitment, &new_commitment, &proof, &params));
+        assert!(!verify_mock_wallet_proof(
+            &new_commitment,
+            &old_commitment,
+            &proof,
+            &params
+        ));
+        let mut tampered = proof.clone();
+        tampered.public_inputs[2] = [4u8; 32];
+        assert!(!verify_mock_proof(&tampered, &params));
+
+        // The timestamp and block height are bound too
+        let mut restamped = proof.clone();
+        restamped.block_height = Some(800_001);
+        assert!(!verify_mock_proof(&restamped, &params));
+        assert!(!verify_mock_wallet_proof(
+            &old_commitment,
+            &new_commitment,
+            &restamped,
+            &params
+        ));
     }
 }
@@ -2,16 +2,70 @@
 
 use serde::{Deserialize, Serialize};
 
-/// A 32-byte array, representing bytes32 in Python.
-pub type Bytes32 = [u8; 32];
+use crate::proof_system::{ProofSystemError, ProofSystemId};
+use crate::types::Bytes32;
 
-/// Zero-knowledge proof of state transition validity.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Version of the `StateProof` envelope format. Version 2 binds the block
+/// height into mock proof digests and the stamp into Plonky2 wallet proofs.
+pub const STATE_PROOF_VERSION: u16 = 2;
+
+/// Versioned envelope for a proof produced by any `ProofSystem`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StateProof {
-    /// The proof itself.
-    pub pi: Bytes32,
+    /// Envelope format version.
+    pub version: u16,
+    /// Proof system that produced the proof.
+    pub system: ProofSystemId,
+    /// The proof itself, in the producing system's encoding.
+    pub pi: Vec<u8>,
     /// Publicly verifiable inputs.
     pub public_inputs: Vec<Bytes32>,
     /// Proof generation timestamp.
     pub timestamp: u64,
+    /// Block height at generation, if the prover's clock follows a chain.
+    #[serde(default)]
+    pub block_height: Option<u64>,
+}
+
+impl StateProof {
+    /// Wraps a proof in an envelope of the current version, without a block
+    /// height.
+    pub fn new(
+        system: ProofSystemId,
+        pi: Vec<u8>,
+        public_inputs: Vec<Bytes32>,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            version: STATE_PROOF_VERSION,
+            system,
+            pi,
+            public_inputs,
+            timestamp,
+            block_height: None,
+        }
+    }
+
+    /// Serializes the envelope.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofSystemError> {
+        serde_json::to_vec(self).map_err(|e| ProofSystemError::Serialization(e.to_string()))
+    }
+
+    /// Deserializes an envelope, rejecting unknown versions.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofSystemError> {
+        let proof: Self = serde_json::from_slice(bytes)
+            .map_err(|e| ProofSystemError::Serialization(e.to_string()))?;
+        if proof.version != STATE_PROOF_VERSION {
+            return Err(ProofSystemError::UnsupportedVersion(proof.version));
+        }
+        Ok(proof)
+    }
+
+    /// Checks that the envelope was produced by `system`.
+    pub fn ensure_system(&self, system: ProofSystemId) -> Result<(), ProofSystemError> {
+        if self.system != system {
+            return Err(ProofSystemError::WrongSystem { expected: system, found: self.system });
+        }
+        Ok(())
+    }
 }
@@ -1,33 +1,182 @@
 // src/zkp/state_transition.rs
 
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
 
 use anyhow::{anyhow, Context, Result};
 use plonky2::field::goldilocks_field::GoldilocksField;
-use plonky2::hash::hash_types::{HashOut, HashOutTarget};
 use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
 use plonky2::iop::witness::{PartialWitness, WitnessWrite};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
-use plonky2::plonk::config::{Hasher, PoseidonGoldilocksConfig};
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, VerifierCircuitData};
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::config::Hasher;
 use plonky2::plonk::proof::ProofWithPublicInputs;
-use plonky2_field::types::{Field, PrimeField64};
+use plonky2::util::serialization::DefaultGateSerializer;
+use plonky2_field::types::Field;
+use serde::{Deserialize, Serialize};
 
-use crate::channel::ChannelState;
-use crate::state::hash_state;
+use crate::channel::{ChannelLifecycle, ChannelState, ChannelTransition, SettlementOutput};
+use crate::codec::{
+    bytes_to_limbs, elements_to_bytes, hash_out_to_bytes, limbs_to_bytes, u64_to_limbs,
+    BYTES32_LIMBS,
+};
+use crate::merkle::hash_pair;
+use crate::signing::tagged_hash;
+use crate::state::{
+    channel_aux_inputs, hash_state, state_hash_inputs, AUX_DIGEST_OFFSET, AUX_RECEIVER_OFFSET,
+    CHANNEL_AUX_INPUTS, LIFECYCLE_OFFSET, STATE_HASH_INPUTS,
+};
 use crate::tree::{MerkleProof, MerkleTree};
+use crate::types::Bytes32;
+
+/// BIP340 tag for transition history leaves.
+const HISTORY_LEAF_TAG: &[u8] = b"Overpass/TransitionHistoryLeaf";
 
 /// Type alias for Poseidon configuration
 type PoseidonConfig = PoseidonGoldilocksConfig;
 
+/// Bit width of balances, nonces and amounts constrained by the circuit.
+/// Kept below the 64-bit field size so that a subtraction that underflows
+/// wraps to a value the range check rejects.
+pub const CIRCUIT_VALUE_BITS: usize = 62;
+
+/// Number of field elements in the encoding of `TransitionData`.
+pub const TRANSITION_DATA_ELEMENTS: usize = 22;
+
+/// Number of public inputs of a transition proof: the old and new state
+/// hashes, the transition digest and the limbs of the channel id.
+const TRANSITION_PUBLIC_INPUTS: usize = 20;
+
+/// Kind of channel update described by `TransitionData`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionKind {
+    /// Payment from the sender to the receiver
+    Transfer,
+    /// Sender top-up raising the channel capacity
+    Deposit,
+    /// Receiver withdrawal into a settlement output
+    Claim,
+    /// `count` transfers applied at once, moving the amount in total
+    TransferBatch { count: u64 },
+    /// Final state closing the channel
+    Close,
+}
+
+impl TransitionKind {
+    /// Numeric tag of the kind, equal to `ChannelTransition::tag` of the
+    /// resulting state.
+    pub fn tag(self) -> u8 {
+        match self {
+            TransitionKind::Transfer => 1,
+            TransitionKind::Deposit => 2,
+            TransitionKind::Claim => 4,
+            TransitionKind::TransferBatch { .. } => 5,
+            TransitionKind::Close => 7,
+        }
+    }
+}
+
+/// Input to a state transition: what kind of update to apply, the amount it
+/// moves, the funding backing a deposit and an optional memo hash committed
+/// to by the proof.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransitionData {
+    /// Kind of update
+    pub kind: TransitionKind,
+    /// Amount moved by the update; zero for `Close`
+    pub amount: u64,
+    /// Funding outpoint, or a commitment to it, backing a `Deposit`
+    pub funding: Option<Bytes32>,
+    /// Hash of an application-defined memo
+    pub memo: Option<Bytes32>,
+}
+
+impl TransitionData {
+    /// Creates transition data without funding or a memo.
+    pub fn new(kind: TransitionKind, amount: u64) -> Self {
+        Self { kind, amount, funding: None, memo: None }
+    }
+
+    /// Creates transition data for a transfer of `amount`.
+    pub fn transfer(amount: u64) -> Self { Self::new(TransitionKind::Transfer, amount) }
+
+    /// Creates transition data for a deposit of `amount` backed by `funding`.
+    pub fn deposit(amount: u64, funding: Bytes32) -> Self {
+        Self { funding: Some(funding), ..Self::new(TransitionKind::Deposit, amount) }
+    }
+
+    /// Creates transition data for a batch of `count` transfers moving
+    /// `total`.
+    pub fn transfer_batch(count: u64, total: u64) -> Self {
+        Self::new(TransitionKind::TransferBatch { count }, total)
+    }
+
+    /// Attaches a memo hash.
+    pub fn with_memo(mut self, memo: Bytes32) -> Self {
+        self.memo = Some(memo);
+        self
+    }
+
+    /// Canonical encoding consumed by `StateTransitionCircuit`, laid out as
+    /// `[kind tag, amount low 32 bits, amount high 32 bits, memo flag,
+    /// memo (8 little-endian u32 limbs, zero without a memo),
+    /// funding (8 limbs, zero without funding),
+    /// batch count (2 limbs, zero for other kinds)]`.
+    ///
+    /// The amount and count are split into limbs so every u64 maps to
+    /// canonical field elements.
+    pub fn to_field_elements(&self) -> [GoldilocksField; TRANSITION_DATA_ELEMENTS] {
+        let count = match self.kind {
+            TransitionKind::TransferBatch { count } => count,
+            _ => 0,
+        };
+        let mut elements = [GoldilocksField::ZERO; TRANSITION_DATA_ELEMENTS];
+        elements[0] = GoldilocksField::from_canonical_u8(self.kind.tag());
+        elements[1..3].copy_from_slice(&u64_to_limbs(self.amount));
+        elements[3] = GoldilocksField::from_bool(self.memo.is_some());
+        elements[4..12].copy_from_slice(&bytes_to_limbs(&self.memo.unwrap_or_default()));
+        elements[12..20].copy_from_slice(&bytes_to_limbs(&self.funding.unwrap_or_default()));
+        elements[20..].copy_from_slice(&u64_to_limbs(count));
+        elements
+    }
+
+    /// Poseidon digest of the encoding, exposed by transition proofs.
+    pub fn digest(&self) -> Bytes32 {
+        hash_out_to_bytes(&PoseidonHash::hash_no_pad(&self.to_field_elements()))
+    }
+}
+
 /// Represents the state transition circuit using Plonky2.
+///
+/// The circuit proves that the state hashed to the second public hash is the
+/// `apply_transition` of the transition data to the state hashed to the first
+/// one, for every `TransitionKind`:
+///
+/// - transfers and batches move the amount from the sender to the receiver,
+///   deposits add it to the sender and claims pay it out of the receiver
+///   balance to the receiver key; balances stay in range, so nothing is
+///   overdrawn;
+/// - the amount is non-zero, except for closes, which move nothing;
+/// - batches count at least one transfer and move at least one unit per
+///   transfer, and deposits are backed by non-zero funding;
+/// - the nonce increases by one, or by the batch count;
+/// - closes start from any stage but `Funding` or `Closed` and end `Closed`,
+///   while every other kind needs an open channel and leaves it open.
+///
+/// Expiry depends on the time of verification and is checked natively.
+/// Public inputs are the old and new `hash_state` outputs, followed by the
+/// `TransitionData::digest` of the transition and the `bytes_to_limbs` limbs
+/// of the channel the proof is bound to.
 pub struct StateTransitionCircuit {
     circuit_data: CircuitData<GoldilocksField, PoseidonConfig, 2>,
-    current_state_target: HashOutTarget,
-    next_state_target: HashOutTarget,
-    transition_data_target: HashOutTarget,
-    channel_roots: HashMap<[u8; 32], [u8; 32]>, // Changed to [u8; 32]
-    merkle_tree: MerkleTree,
+    current_state_targets: Vec<Target>,
+    transition_data_targets: Vec<Target>,
+    aux_inputs_targets: Vec<Target>,
+    channel_id_targets: Vec<Target>,
 }
 
 impl StateTransitionCircuit {
@@ -35,81 +184,244 @@ impl StateTransitionCircuit {
     pub fn new() -> Self {
         let config = CircuitConfig::standard_recursion_zk_config();
         let mut builder = CircuitBuilder::<GoldilocksField, 2>::new(config);
+        let zero = builder.zero();
+
+        // Witness the current state's `state_hash_inputs`, the encoded
+        // `TransitionData` and the `channel_aux_inputs` of the channel.
+        let current_state_targets = builder.add_virtual_targets(STATE_HASH_INPUTS);
+        let transition_data_targets = builder.add_virtual_targets(TRANSITION_DATA_ELEMENTS);
+        let aux_inputs_targets = builder.add_virtual_targets(CHANNEL_AUX_INPUTS);
 
-        // Define virtual hash targets for current state, transition data, and next state.
-        let current_state_target = builder.add_virtual_hash();
-        let transition_data_target = builder.add_virtual_hash();
-        let next_state_target = builder.add_virtual_hash();
+        // Balances and the nonce are hashed as 32-bit limbs; rebuild them.
+        let sender_balance = limbs_to_value(&mut builder, &current_state_targets[0..2]);
+        let receiver_balance = limbs_to_value(&mut builder, &current_state_targets[2..4]);
+        let nonce = limbs_to_value(&mut builder, &current_state_targets[4..6]);
+        let lifecycle = current_state_targets[LIFECYCLE_OFFSET];
+        let aux_digest = &current_state_targets[AUX_DIGEST_OFFSET..];
 
-        // Register current and next states as public inputs.
-        builder.register_public_inputs(&current_state_target.elements);
-        builder.register_public_inputs(&next_state_target.elements);
+        // Open the channel aux digest to get at the receiver key claims pay.
+        let opened_aux =
+            builder.hash_n_to_hash_no_pad::<PoseidonHash>(aux_inputs_targets.clone());
+        for (&opened, &digest) in opened_aux.elements.iter().zip(aux_digest) {
+            builder.connect(opened, digest);
+        }
+        let receiver_key =
+            &aux_inputs_targets[AUX_RECEIVER_OFFSET..AUX_RECEIVER_OFFSET + BYTES32_LIMBS];
 
-        // Prepare inputs for Poseidon hash: interleaving current state and transition data.
-        let inputs = current_state_target
-            .elements
+        // Exactly one kind of transition is applied.
+        let kind = transition_data_targets[0];
+        let [is_transfer, is_deposit, is_claim, is_batch, is_close] = [
+            TransitionKind::Transfer,
+            TransitionKind::Deposit,
+            TransitionKind::Claim,
+            TransitionKind::TransferBatch { count: 0 },
+            TransitionKind::Close,
+        ]
+        .map(|candidate| {
+            let tag = builder.constant(GoldilocksField::from_canonical_u8(candidate.tag()));
+            builder.is_equal(kind, tag)
+        });
+        let kinds = [is_transfer, is_deposit, is_claim, is_batch, is_close]
             .iter()
-            .zip(transition_data_target.elements.iter())
-            .flat_map(|(&c, &t)| vec![c, t])
-            .collect::<Vec<_>>();
+            .fold(zero, |sum, flag| builder.add(sum, flag.target));
+        builder.assert_one(kinds);
+
+        // The amount and batch count are rebuilt from their limbs. Only
+        // closes move nothing.
+        let amount = limbs_to_value(&mut builder, &transition_data_targets[1..3]);
+        let count = limbs_to_value(&mut builder, &transition_data_targets[20..22]);
+        let amount_is_zero = builder.is_equal(amount, zero);
+        builder.connect(amount_is_zero.target, is_close.target);
+
+        // A batch counts at least one transfer, each moving at least one
+        // unit. Other kinds carry no count.
+        let not_batch = builder.not(is_batch);
+        let stray_count = builder.mul(not_batch.target, count);
+        builder.assert_zero(stray_count);
+        let count_is_zero = builder.is_equal(count, zero);
+        let empty_batch = builder.and(is_batch, count_is_zero);
+        builder.assert_zero(empty_batch.target);
+        let surplus = builder.sub(amount, count);
+        builder.range_check(surplus, CIRCUIT_VALUE_BITS);
+
+        // Deposits are backed by funding. Its limbs are range-checked so that
+        // their sum is only zero for zero funding.
+        let funding = &transition_data_targets[12..20];
+        let mut funding_sum = zero;
+        for &limb in funding {
+            builder.range_check(limb, 32);
+            funding_sum = builder.add(funding_sum, limb);
+        }
+        let funding_is_zero = builder.is_equal(funding_sum, zero);
+        let unfunded_deposit = builder.and(is_deposit, funding_is_zero);
+        builder.assert_zero(unfunded_deposit.target);
+
+        // Closes are accepted from any stage but `Funding` or `Closed` and
+        // end the channel; every other kind needs an open channel and leaves
+        // it open.
+        let [funding_stage, open, closed] =
+            [ChannelLifecycle::Funding, ChannelLifecycle::Open, ChannelLifecycle::Closed]
+                .map(|stage| builder.constant(GoldilocksField::from_canonical_u8(stage.tag())));
+        let not_close = builder.not(is_close);
+        let off_open = builder.sub(lifecycle, open);
+        let stage_gap = builder.mul(not_close.target, off_open);
+        builder.assert_zero(stage_gap);
+        let is_funding = builder.is_equal(lifecycle, funding_stage);
+        let is_closed = builder.is_equal(lifecycle, closed);
+        let unclosable = builder.or(is_funding, is_closed);
+        let bad_close = builder.and(is_close, unclosable);
+        builder.assert_zero(bad_close.target);
+        let next_lifecycle = builder.select(is_close, closed, lifecycle);
+
+        // Apply the amount. Transfers and batches conserve the total, and
+        // splitting the results into limbs range-checks them, which rules
+        // out an overdraft.
+        let pays = builder.add(is_transfer.target, is_batch.target);
+        let paid = builder.mul(pays, amount);
+        let deposited = builder.mul(is_deposit.target, amount);
+        let claimed = builder.mul(is_claim.target, amount);
+        let sender_out = builder.sub(sender_balance, paid);
+        let next_sender_balance = builder.add(sender_out, deposited);
+        let receiver_in = builder.add(receiver_balance, paid);
+        let next_receiver_balance = builder.sub(receiver_in, claimed);
 
-        // Compute the next state hash using Poseidon without padding.
-        let computed_next_state = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+        // The nonce moves by the batch count, or by one for other kinds,
+        // whose count is zero.
+        let nonce_step = builder.add(count, not_batch.target);
+        let next_nonce = builder.add(nonce, nonce_step);
 
-        // Enforce that the computed hash matches the declared next state.
-        for i in 0..4 {
-            builder.connect(computed_next_state.elements[i], next_state_target.elements[i]);
+        // Lay out the next state as `state_hash_inputs` does. The kind tag is
+        // the `ChannelTransition` tag, every kind but a transfer records its
+        // amount, deposits reference their funding and claims the receiver.
+        // The memo comes from the transition data and the channel aux digest
+        // is carried over unchanged.
+        let mut next_state_targets = Vec::with_capacity(STATE_HASH_INPUTS);
+        for value in [next_sender_balance, next_receiver_balance, next_nonce] {
+            next_state_targets.extend(value_to_limbs(&mut builder, value));
         }
+        next_state_targets.push(kind);
+        let not_transfer = builder.not(is_transfer);
+        for &limb in &transition_data_targets[1..3] {
+            next_state_targets.push(builder.mul(not_transfer.target, limb));
+        }
+        for (&funding_limb, &receiver_limb) in funding.iter().zip(receiver_key) {
+            let funded = builder.mul(is_deposit.target, funding_limb);
+            next_state_targets.push(builder.mul_add(is_claim.target, receiver_limb, funded));
+        }
+        next_state_targets.extend_from_slice(&transition_data_targets[3..12]);
+        next_state_targets.push(next_lifecycle);
+        next_state_targets.extend_from_slice(aux_digest);
+
+        // Recompute `hash_state` for both states and expose them as public inputs.
+        let current_state_hash =
+            builder.hash_n_to_hash_no_pad::<PoseidonHash>(current_state_targets.clone());
+        let next_state_hash = builder.hash_n_to_hash_no_pad::<PoseidonHash>(next_state_targets);
+        builder.register_public_inputs(&current_state_hash.elements);
+        builder.register_public_inputs(&next_state_hash.elements);
+
+        // Expose the transition data digest so the proof commits to the memo.
+        let transition_digest =
+            builder.hash_n_to_hash_no_pad::<PoseidonHash>(transition_data_targets.clone());
+        builder.register_public_inputs(&transition_digest.elements);
+
+        // Bind the proof to its channel. Range-checked limbs decode to
+        // exactly one channel id.
+        let channel_id_targets = builder.add_virtual_targets(BYTES32_LIMBS);
+        for &limb in &channel_id_targets {
+            builder.range_check(limb, 32);
+        }
+        builder.register_public_inputs(&channel_id_targets);
 
         // Finalize the circuit.
         let circuit_data = builder.build::<PoseidonConfig>();
 
         Self {
             circuit_data,
-            current_state_target,
-            next_state_target,
-            transition_data_target,
-            channel_roots: HashMap::new(),
-            merkle_tree: MerkleTree::new(),
+            current_state_targets,
+            transition_data_targets,
+            aux_inputs_targets,
+            channel_id_targets,
         }
     }
 
-    /// Generates a zero-knowledge proof for a state transition.
+    /// Gets a circuit shared by the whole process, built on first use.
+    pub fn shared() -> &'static Self {
+        static CIRCUIT: OnceLock<StateTransitionCircuit> = OnceLock::new();
+        CIRCUIT.get_or_init(Self::new)
+    }
+
+    /// Generates a zero-knowledge proof for a state transition of channel
+    /// `channel_id`.
     pub fn generate_zkp(
         &self,
+        channel_id: &Bytes32,
         initial_state: &ChannelState,
-        transition_data: &[u8; 32],
+        transition_data: &TransitionData,
     ) -> Result<ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>> {
         let mut pw = PartialWitness::new();
 
-        let next_state = apply_transition(initial_state, transition_data)
-            .context("Failed to apply transition to initial state")?;
-
-        // Serialize and hash the initial and next states
-        let initial_state_bytes =
-            hash_state(initial_state).context("Failed to hash initial state")?;
-        let next_state_bytes = hash_state(&next_state).context("Failed to hash next state")?;
-
-        // Convert byte arrays to HashOut targets.
-        let initial_hash =
-            Self::to_hash_out(initial_state_bytes).context("Failed to convert initial hash")?;
-        let transition_hash = Self::to_hash_out(*transition_data)
-            .context("Failed to convert transition data hash")?;
-        let next_hash =
-            Self::to_hash_out(next_state_bytes).context("Failed to convert next hash")?;
-
-        // Assign hashes to their respective targets.
-        pw.set_hash_target(self.current_state_target, initial_hash)
-            .context("Failed to set initial state hash")?;
-        pw.set_hash_target(self.transition_data_target, transition_hash)
-            .context("Failed to set transition data hash")?;
-        pw.set_hash_target(self.next_state_target, next_hash)
-            .context("Failed to set next state hash")?;
+        // Check natively what the circuit enforces on top of
+        // `apply_transition` rather than failing inside the prover.
+        let next_state = apply_transition(initial_state, transition_data)?;
+        if transition_data.kind != TransitionKind::Close {
+            initial_state.ensure_open()?;
+            if transition_data.amount == 0 {
+                return Err(anyhow!("{:?} amount cannot be zero", transition_data.kind));
+            }
+        }
+        let max_value = (1u64 << CIRCUIT_VALUE_BITS) - 1;
+        if [
+            initial_state.sender_balance,
+            initial_state.receiver_balance,
+            next_state.sender_balance,
+            next_state.receiver_balance,
+            next_state.nonce,
+        ]
+        .into_iter()
+        .any(|value| value > max_value)
+        {
+            return Err(anyhow!("Channel values exceed {CIRCUIT_VALUE_BITS} bits"));
+        }
+
+        // Assign the current state, the transition data and the channel aux
+        // inputs to their targets.
+        let current_state_inputs =
+            state_hash_inputs(initial_state).context("Failed to hash initial state")?;
+        for (&target, &value) in self.current_state_targets.iter().zip(&current_state_inputs) {
+            pw.set_target(target, value).context("Failed to set initial state input")?;
+        }
+        let transition_inputs = transition_data.to_field_elements();
+        for (&target, &value) in self.transition_data_targets.iter().zip(&transition_inputs) {
+            pw.set_target(target, value).context("Failed to set transition data input")?;
+        }
+        let aux_inputs = channel_aux_inputs(initial_state);
+        for (&target, &value) in self.aux_inputs_targets.iter().zip(&aux_inputs) {
+            pw.set_target(target, value).context("Failed to set channel aux input")?;
+        }
+        for (&target, value) in self.channel_id_targets.iter().zip(bytes_to_limbs(channel_id)) {
+            pw.set_target(target, value).context("Failed to set channel id input")?;
+        }
 
         // Generate and return the proof.
         self.circuit_data.prove(pw).context("Proof generation failed")
     }
 
+    /// Generates a zero-knowledge proof for a transfer of `transfer_amount`
+    /// from `initial_state` in channel `channel_id`.
+    pub fn prove_transfer(
+        &self,
+        channel_id: &Bytes32,
+        initial_state: &ChannelState,
+        transfer_amount: u64,
+    ) -> Result<ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>> {
+        self.generate_zkp(channel_id, initial_state, &TransitionData::transfer(transfer_amount))
+    }
+
+    /// Gets the underlying circuit data, e.g. to verify its proofs recursively.
+    pub fn circuit_data(&self) -> &CircuitData<GoldilocksField, PoseidonConfig, 2> {
+        &self.circuit_data
+    }
+
     /// Verifies a zero-knowledge proof for a state transition.
     pub fn verify_proof(
         &self,
@@ -118,117 +430,767 @@ impl StateTransitionCircuit {
         self.circuit_data.verify(proof).map(|_| true).context("Proof verification failed")
     }
 
-    /// Converts a byte array to a Poseidon HashOut.
-    fn to_hash_out(data: [u8; 32]) -> Result<HashOut<GoldilocksField>, anyhow::Error> {
-        let elements = data
-            .chunks(8)
-            .map(|chunk| {
-                let bytes: [u8; 8] = chunk
-                    .try_into()
-                    .map_err(|_| anyhow!("Invalid byte length for field element"))?;
-                Ok(GoldilocksField::from_canonical_u64(u64::from_le_bytes(bytes)))
-            })
-            .collect::<Result<Vec<_>, anyhow::Error>>()?;
-
-        Ok(HashOut::from_partial(&elements))
-    }
-
-    /// Converts a Poseidon HashOut back to a byte array.
-    fn hash_out_to_bytes(hash: &HashOut<GoldilocksField>) -> Result<[u8; 32]> {
-        let mut bytes = [0u8; 32];
-        for (i, &element) in hash.elements.iter().enumerate() {
-            let elem_u64 = element.to_noncanonical_u64();
-            bytes[i * 8..(i + 1) * 8].copy_from_slice(&elem_u64.to_le_bytes());
-        }
-        Ok(bytes)
-    }
-
-    /// Computes the Poseidon hash for the next state based on current state and transition data.
-    fn compute_poseidon_hash(
-        current_hash: &HashOut<GoldilocksField>,
-        transition_hash: &HashOut<GoldilocksField>,
-    ) -> HashOut<GoldilocksField> {
-        let inputs = vec![
-            current_hash.elements[0],
-            transition_hash.elements[0],
-            current_hash.elements[1],
-            transition_hash.elements[1],
-            current_hash.elements[2],
-            transition_hash.elements[2],
-            current_hash.elements[3],
-            transition_hash.elements[3],
-        ];
-        PoseidonHash::hash_no_pad(&inputs)
-    }
-
-    /// Computes the next state based on current state and transition data.
-    pub fn compute_next_state(
+    /// Verifies a proof and checks that it proves the transition from
+    /// `initial_state` to `next_state` in channel `channel_id`.
+    pub fn verify_transition(
+        &self,
+        proof: ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>,
+        channel_id: &Bytes32,
+        initial_state: &ChannelState,
+        next_state: &ChannelState,
+    ) -> Result<bool> {
+        if !proves_states(&proof, channel_id, Some(initial_state), next_state)? {
+            return Ok(false);
+        }
+        self.verify_proof(proof)
+    }
+
+    /// Deserializes a proof stored in `ChannelState::proof`, verifies it and
+    /// checks that it proves a transition ending in `next_state` in channel
+    /// `channel_id`.
+    pub fn verify_proof_bytes(
         &self,
-        current_state: [u8; 32],
-        transition_data: [u8; 32],
-    ) -> Result<[u8; 32]> {
-        let current_hash =
-            Self::to_hash_out(current_state).context("Failed to convert current state hash")?;
-        let transition_hash =
-            Self::to_hash_out(transition_data).context("Failed to convert transition data hash")?;
-        let next_hash = Self::compute_poseidon_hash(&current_hash, &transition_hash);
-        Self::hash_out_to_bytes(&next_hash).context("Failed to convert next hash to bytes")
+        proof_bytes: &[u8],
+        channel_id: &Bytes32,
+        next_state: &ChannelState,
+    ) -> Result<bool> {
+        let proof =
+            ProofWithPublicInputs::from_bytes(proof_bytes.to_vec(), &self.circuit_data.common)
+                .context("Failed to deserialize proof")?;
+        if !proves_states(&proof, channel_id, None, next_state)? {
+            return Ok(false);
+        }
+        self.verify_proof(proof)
     }
 
-    /// Generates a Merkle proof for a channel's transaction history.
-    pub fn generate_merkle_proof(&self, channel_id: [u8; 32]) -> Option<MerkleProof> {
-        self.channel_roots.get(&channel_id).and_then(|root| {
-            self.merkle_tree.get_proof(root).map(|proof| MerkleProof { path: proof })
-        })
+    /// Gets the data needed to verify proofs of this circuit.
+    pub fn verifier(&self) -> StateTransitionVerifier {
+        StateTransitionVerifier { verifier_data: self.circuit_data.verifier_data() }
     }
 
-    /// Verifies a Merkle proof for a channel's transaction history.
-    pub fn verify_merkle_proof(&self, channel_id: [u8; 32], proof: &MerkleProof) -> bool {
-        if let Some(root) = self.channel_roots.get(&channel_id) {
-            self.merkle_tree.verify_proof(root, &proof.path, root)
-        } else {
-            false
+    /// Digest identifying this circuit. Proofs only verify against circuits
+    /// with the same digest.
+    pub fn circuit_digest(&self) -> [u8; 32] {
+        hash_out_to_bytes(&self.circuit_data.verifier_only.circuit_digest)
+    }
+
+    /// Returns the old and new state hashes a proof commits to.
+    pub fn public_state_hashes(
+        proof: &ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>,
+    ) -> Result<([u8; 32], [u8; 32])> {
+        if proof.public_inputs.len() != TRANSITION_PUBLIC_INPUTS {
+            return Err(anyhow!("Unexpected number of public inputs"));
         }
+        let old_hash = elements_to_bytes(&proof.public_inputs[..4]);
+        let new_hash = elements_to_bytes(&proof.public_inputs[4..8]);
+        Ok((old_hash, new_hash))
+    }
+
+    /// Extracts the `TransitionData::digest` a proof was generated for.
+    pub fn public_transition_digest(
+        proof: &ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>,
+    ) -> Result<Bytes32> {
+        if proof.public_inputs.len() != TRANSITION_PUBLIC_INPUTS {
+            return Err(anyhow!("Unexpected number of public inputs"));
+        }
+        Ok(elements_to_bytes(&proof.public_inputs[8..12]))
+    }
+
+    /// Extracts the id of the channel a proof is bound to.
+    pub fn public_channel_id(
+        proof: &ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>,
+    ) -> Result<Bytes32> {
+        let limbs = proof
+            .public_inputs
+            .get(12..)
+            .and_then(|limbs| <&[GoldilocksField; BYTES32_LIMBS]>::try_from(limbs).ok())
+            .ok_or_else(|| anyhow!("Unexpected number of public inputs"))?;
+        limbs_to_bytes(limbs).context("Invalid channel id limbs")
     }
 }
 
+/// Rebuilds a value witnessed as the two `u64_to_limbs` limbs, constraining
+/// it to `CIRCUIT_VALUE_BITS` bits.
+fn limbs_to_value(builder: &mut CircuitBuilder<GoldilocksField, 2>, limbs: &[Target]) -> Target {
+    builder.range_check(limbs[0], 32);
+    builder.range_check(limbs[1], CIRCUIT_VALUE_BITS - 32);
+    builder.mul_const_add(GoldilocksField::from_canonical_u64(1 << 32), limbs[1], limbs[0])
+}
+
+/// Splits a value into the two `u64_to_limbs` limbs, constraining it to
+/// `CIRCUIT_VALUE_BITS` bits. A subtraction that underflowed wraps to a value
+/// above that and cannot be split.
+fn value_to_limbs(builder: &mut CircuitBuilder<GoldilocksField, 2>, value: Target) -> [Target; 2] {
+    let (low, high) = builder.split_low_high(value, 32, CIRCUIT_VALUE_BITS);
+    [low, high]
+}
+
 impl Default for StateTransitionCircuit {
     fn default() -> Self { Self::new() }
 }
 
+/// Verifier for `StateTransitionCircuit` proofs that holds only the
+/// verifier data, so it can be persisted and loaded without building the
+/// prover.
+pub struct StateTransitionVerifier {
+    verifier_data: VerifierCircuitData<GoldilocksField, PoseidonConfig, 2>,
+}
+
+impl StateTransitionVerifier {
+    /// Serializes the verifier data.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.verifier_data
+            .to_bytes(&DefaultGateSerializer)
+            .map_err(|_| anyhow!("Failed to serialize verifier data"))
+    }
+
+    /// Deserializes verifier data produced by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let verifier_data = VerifierCircuitData::from_bytes(bytes.to_vec(), &DefaultGateSerializer)
+            .map_err(|_| anyhow!("Failed to deserialize verifier data"))?;
+        Ok(Self { verifier_data })
+    }
+
+    /// Deserializes verifier data, rejecting it unless it belongs to the
+    /// circuit identified by `expected_digest`.
+    pub fn from_bytes_with_digest(bytes: &[u8], expected_digest: &[u8; 32]) -> Result<Self> {
+        let verifier = Self::from_bytes(bytes)?;
+        verifier.ensure_digest(expected_digest)?;
+        Ok(verifier)
+    }
+
+    /// Writes the serialized verifier data to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_bytes()?).context("Failed to write verifier data")
+    }
+
+    /// Reads verifier data written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_bytes(&fs::read(path).context("Failed to read verifier data")?)
+    }
+
+    /// Digest identifying the circuit this verifier checks proofs of.
+    pub fn circuit_digest(&self) -> [u8; 32] {
+        hash_out_to_bytes(&self.verifier_data.verifier_only.circuit_digest)
+    }
+
+    /// Returns an error if this verifier is for a different circuit version
+    /// than `expected_digest`.
+    pub fn ensure_digest(&self, expected_digest: &[u8; 32]) -> Result<()> {
+        if self.circuit_digest() != *expected_digest {
+            return Err(anyhow!(
+                "Circuit digest mismatch: expected 0x{}, found 0x{}",
+                hex::encode(expected_digest),
+                hex::encode(self.circuit_digest())
+            ));
+        }
+        Ok(())
+    }
+
+    /// Verifies a zero-knowledge proof for a state transition.
+    pub fn verify_proof(
+        &self,
+        proof: ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>,
+    ) -> Result<bool> {
+        self.verifier_data.verify(proof).map(|_| true).context("Proof verification failed")
+    }
+
+    /// Verifies a proof and checks that it proves the transition from
+    /// `initial_state` to `next_state` in channel `channel_id`.
+    pub fn verify_transition(
+        &self,
+        proof: ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>,
+        channel_id: &Bytes32,
+        initial_state: &ChannelState,
+        next_state: &ChannelState,
+    ) -> Result<bool> {
+        if !proves_states(&proof, channel_id, Some(initial_state), next_state)? {
+            return Ok(false);
+        }
+        self.verify_proof(proof)
+    }
+
+    /// Deserializes a proof stored in `ChannelState::proof`, verifies it and
+    /// checks that it proves a transition ending in `next_state` in channel
+    /// `channel_id`.
+    pub fn verify_proof_bytes(
+        &self,
+        proof_bytes: &[u8],
+        channel_id: &Bytes32,
+        next_state: &ChannelState,
+    ) -> Result<bool> {
+        let proof =
+            ProofWithPublicInputs::from_bytes(proof_bytes.to_vec(), &self.verifier_data.common)
+                .context("Failed to deserialize proof")?;
+        if !proves_states(&proof, channel_id, None, next_state)? {
+            return Ok(false);
+        }
+        self.verify_proof(proof)
+    }
+}
+
+/// A proven transition as recorded in a `TransitionHistory`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransitionRecord {
+    /// Position of the transition in its channel's history
+    pub index: u64,
+    /// `hash_state` of the state the transition starts from
+    pub old_state_hash: Bytes32,
+    /// `hash_state` of the state the transition produces
+    pub new_state_hash: Bytes32,
+    /// `TransitionData::digest` of the applied transition
+    pub transition_digest: Bytes32,
+}
+
+impl TransitionRecord {
+    /// Leaf committing to the record in the history of `channel_id`.
+    pub fn leaf(&self, channel_id: &Bytes32) -> Bytes32 {
+        let mut msg = Vec::with_capacity(136);
+        msg.extend_from_slice(channel_id);
+        msg.extend_from_slice(&self.index.to_le_bytes());
+        msg.extend_from_slice(&self.old_state_hash);
+        msg.extend_from_slice(&self.new_state_hash);
+        msg.extend_from_slice(&self.transition_digest);
+        tagged_hash(HISTORY_LEAF_TAG, &msg)
+    }
+}
+
+/// History of one channel: the hash of its genesis state, its records and a
+/// Merkle tree over their leaves.
+#[derive(Debug)]
+struct ChannelHistory {
+    genesis_hash: Bytes32,
+    records: Vec<TransitionRecord>,
+    tree: MerkleTree,
+}
+
+/// Per-channel history of proven transitions.
+///
+/// A channel's history is opened at its genesis state. Its records form a
+/// chain from there, every transition starting from the state the previous
+/// one produced, and are committed to by a Merkle tree whose root proves
+/// inclusion of any recorded transition.
+#[derive(Debug, Default)]
+pub struct TransitionHistory {
+    channels: HashMap<Bytes32, ChannelHistory>,
+}
+
+impl TransitionHistory {
+    /// Creates an empty history.
+    pub fn new() -> Self { Self::default() }
+
+    /// Opens the history of `channel_id` at its genesis state, which the
+    /// first recorded transition has to start from.
+    pub fn open(&mut self, channel_id: Bytes32, genesis: &ChannelState) -> Result<()> {
+        if self.channels.contains_key(&channel_id) {
+            return Err(anyhow!("Channel history is already open"));
+        }
+        genesis.verify_genesis().context("Invalid genesis state")?;
+        let genesis_hash = hash_state(genesis).context("Failed to hash genesis state")?;
+        let history =
+            ChannelHistory { genesis_hash, records: Vec::new(), tree: MerkleTree::new() };
+        self.channels.insert(channel_id, history);
+        Ok(())
+    }
+
+    /// Verifies a `circuit` proof bound to `channel_id` and records the
+    /// transition it proves in the channel's history.
+    pub fn record(
+        &mut self,
+        circuit: &StateTransitionCircuit,
+        channel_id: Bytes32,
+        proof: &ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>,
+    ) -> Result<TransitionRecord> {
+        let history = self
+            .channels
+            .get_mut(&channel_id)
+            .ok_or_else(|| anyhow!("Channel history is not open"))?;
+        if StateTransitionCircuit::public_channel_id(proof)? != channel_id {
+            return Err(anyhow!("Proof is bound to another channel"));
+        }
+        let (old_state_hash, new_state_hash) = StateTransitionCircuit::public_state_hashes(proof)?;
+        let transition_digest = StateTransitionCircuit::public_transition_digest(proof)?;
+        let last_state_hash =
+            history.records.last().map_or(history.genesis_hash, |last| last.new_state_hash);
+        if last_state_hash != old_state_hash {
+            return Err(anyhow!("Transition does not start from the last recorded state"));
+        }
+        circuit.verify_proof(proof.clone())?;
+
+        let record = TransitionRecord {
+            index: history.records.len() as u64,
+            old_state_hash,
+            new_state_hash,
+            transition_digest,
+        };
+        history.tree.insert(record.leaf(&channel_id)).context("Failed to extend history")?;
+        history.records.push(record);
+        Ok(record)
+    }
+
+    /// Hash of the genesis state a channel's history was opened at.
+    pub fn genesis_hash(&self, channel_id: &Bytes32) -> Option<Bytes32> {
+        self.channels.get(channel_id).map(|history| history.genesis_hash)
+    }
+
+    /// Recorded transitions of a channel, oldest first.
+    pub fn records(&self, channel_id: &Bytes32) -> &[TransitionRecord] {
+        self.channels.get(channel_id).map_or(&[], |history| &history.records)
+    }
+
+    /// Root committing to a channel's history, if it has any transitions.
+    pub fn root(&self, channel_id: &Bytes32) -> Option<Bytes32> {
+        let history = self.channels.get(channel_id)?;
+        (!history.records.is_empty()).then_some(history.tree.root)
+    }
+
+    /// Generates a proof that the transition at `index` is part of the
+    /// channel's history.
+    pub fn generate_merkle_proof(&self, channel_id: &Bytes32, index: u64) -> Option<MerkleProof> {
+        let history = self.channels.get(channel_id)?;
+        let record = history.records.get(usize::try_from(index).ok()?)?;
+        history.tree.get_proof(&record.leaf(channel_id)).map(|path| MerkleProof { path })
+    }
+
+    /// Verifies that `record` is part of the channel's history.
+    pub fn verify_merkle_proof(
+        &self,
+        channel_id: &Bytes32,
+        record: &TransitionRecord,
+        proof: &MerkleProof,
+    ) -> bool {
+        self.root(channel_id)
+            .is_some_and(|root| verify_inclusion(&root, channel_id, record, proof))
+    }
+}
+
+/// Verifies that `record` is part of the history of `channel_id` committed to
+/// by `root`, without access to the history itself. The record's index picks
+/// the side of each sibling on the path.
+pub fn verify_inclusion(
+    root: &Bytes32,
+    channel_id: &Bytes32,
+    record: &TransitionRecord,
+    proof: &MerkleProof,
+) -> bool {
+    if proof.path.len() < 64 && record.index >> proof.path.len() != 0 {
+        return false;
+    }
+    let mut index = record.index;
+    let mut node = record.leaf(channel_id);
+    for &sibling in &proof.path {
+        node = if index % 2 == 0 { hash_pair(node, sibling) } else { hash_pair(sibling, node) };
+        index /= 2;
+    }
+    node == *root
+}
+
+/// Checks that a proof is bound to `channel_id` and that its public state
+/// hashes match `next_state` and, if given, `initial_state`.
+fn proves_states(
+    proof: &ProofWithPublicInputs<GoldilocksField, PoseidonConfig, 2>,
+    channel_id: &Bytes32,
+    initial_state: Option<&ChannelState>,
+    next_state: &ChannelState,
+) -> Result<bool> {
+    if StateTransitionCircuit::public_channel_id(proof)? != *channel_id {
+        return Ok(false);
+    }
+    let (old_hash, new_hash) = StateTransitionCircuit::public_state_hashes(proof)?;
+    if let Some(initial_state) = initial_state {
+        if old_hash != hash_state(initial_state).context("Failed to hash initial state")? {
+            return Ok(false);
+        }
+    }
+    Ok(new_hash == hash_state(next_state).context("Failed to hash next state")?)
+}
+
 /// Applies transition data to the initial state to produce the next state.
+/// The circuit has no clock, so expiry is not modelled here; provers check
+/// it with `ChannelState::verify_transition` or `verify_close` first.
 pub fn apply_transition(
     initial_state: &ChannelState,
-    transition_data: &[u8; 32],
+    transition_data: &TransitionData,
 ) -> Result<ChannelState> {
-    let transfer_amount = u32::from_le_bytes(
-        transition_data[0..4]
-            .try_into()
-            .context("Failed to parse transfer_amount")?,
-    ) as u64;
-
-    // Calculate new sender balance (decrease by transfer amount)
-    let new_sender_balance = initial_state
-        .sender_balance
-        .checked_sub(transfer_amount)
-        .ok_or_else(|| anyhow!("Negative balance is not allowed"))?;
-
-    // Calculate new receiver balance (increase by transfer amount)
-    let new_receiver_balance = initial_state
-        .receiver_balance
-        .checked_add(transfer_amount)
-        .ok_or_else(|| anyhow!("Balance overflow for positive delta"))?;
+    let amount = transition_data.amount;
 
     // Increment nonce strictly by +1
     let new_nonce = initial_state.nonce.checked_add(1).ok_or_else(|| anyhow!("Nonce overflow"))?;
 
-    let new_state = ChannelState {
-        sender_balance: new_sender_balance,
-        receiver_balance: new_receiver_balance,
+    let mut new_state = ChannelState {
+        sender_balance: initial_state.sender_balance,
+        receiver_balance: initial_state.receiver_balance,
         nonce: new_nonce,
         metadata: initial_state.metadata.clone(),
         proof: None,
+        commitment: None,
+        range_proof: None,
+        participants: initial_state.participants,
+        signature: None,
+        lifecycle: initial_state.lifecycle,
+        expiry: initial_state.expiry,
+        transition: ChannelTransition::Transfer,
+        memo: transition_data.memo,
     };
 
+    match transition_data.kind {
+        TransitionKind::Transfer => {
+            // Move the amount from the sender to the receiver
+            new_state.sender_balance = initial_state
+                .sender_balance
+                .checked_sub(amount)
+                .ok_or_else(|| anyhow!("Negative balance is not allowed"))?;
+            new_state.receiver_balance = initial_state
+                .receiver_balance
+                .checked_add(amount)
+                .ok_or_else(|| anyhow!("Balance overflow for positive delta"))?;
+        }
+        TransitionKind::Deposit => {
+            if amount == 0 {
+                return Err(anyhow!("Deposit amount cannot be zero"));
+            }
+            let funding = transition_data
+                .funding
+                .filter(|funding| *funding != [0u8; 32])
+                .ok_or_else(|| anyhow!("Deposit is not backed by funding"))?;
+            new_state.sender_balance = initial_state
+                .sender_balance
+                .checked_add(amount)
+                .filter(|balance| balance.checked_add(initial_state.receiver_balance).is_some())
+                .ok_or_else(|| anyhow!("Balance overflow for deposit"))?;
+            new_state.transition = ChannelTransition::Deposit { amount, funding };
+        }
+        TransitionKind::TransferBatch { count } => {
+            // Every transfer in the batch moves at least one unit
+            if count == 0 || amount < count {
+                return Err(anyhow!("A batch of {count} transfers cannot move {amount}"));
+            }
+            new_state.nonce = initial_state
+                .nonce
+                .checked_add(count)
+                .ok_or_else(|| anyhow!("Nonce overflow"))?;
+            new_state.sender_balance = initial_state
+                .sender_balance
+                .checked_sub(amount)
+                .ok_or_else(|| anyhow!("Negative balance is not allowed"))?;
+            new_state.receiver_balance = initial_state
+                .receiver_balance
+                .checked_add(amount)
+                .ok_or_else(|| anyhow!("Balance overflow for positive delta"))?;
+            new_state.transition = ChannelTransition::TransferBatch { count, total: amount };
+        }
+        TransitionKind::Claim => {
+            if amount == 0 {
+                return Err(anyhow!("Claim amount cannot be zero"));
+            }
+            new_state.receiver_balance = initial_state
+                .receiver_balance
+                .checked_sub(amount)
+                .ok_or_else(|| anyhow!("Negative balance is not allowed"))?;
+            new_state.transition = ChannelTransition::Claim {
+                output: SettlementOutput { recipient: initial_state.participants.receiver, amount },
+            };
+        }
+        TransitionKind::Close => {
+            if amount != 0 {
+                return Err(anyhow!("Closing a channel does not move funds"));
+            }
+            // Guarded like `ChannelState::close`, except for expiry
+            let lifecycle = initial_state.lifecycle;
+            if matches!(lifecycle, ChannelLifecycle::Funding | ChannelLifecycle::Closed) {
+                return Err(anyhow!("Channel cannot be closed from {lifecycle:?}"));
+            }
+            new_state.lifecycle = ChannelLifecycle::Closed;
+            new_state.transition = ChannelTransition::Close;
+        }
+    }
+
     Ok(new_state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::ChannelParticipants;
+
+    fn initial_state() -> ChannelState {
+        let participants = ChannelParticipants { sender: [2u8; 32], receiver: [3u8; 32] };
+        ChannelState::new(100, b"channel".to_vec(), participants).unwrap()
+    }
+
+    const CHANNEL_ID: Bytes32 = [4u8; 32];
+
+    fn transition_data(amount: u64) -> TransitionData { TransitionData::transfer(amount) }
+
+    #[test]
+    fn test_transition_data_encoding() {
+        let data = TransitionData::transfer(u32::MAX as u64 + 7);
+        let elements = data.to_field_elements();
+        assert_eq!(elements[0], GoldilocksField::ONE);
+        assert_eq!(elements[1], GoldilocksField::from_canonical_u32(6));
+        assert_eq!(elements[2], GoldilocksField::ONE);
+        assert_eq!(elements[3], GoldilocksField::ZERO);
+
+        // The memo and the kind are both committed to by the digest
+        let with_memo = data.with_memo([9u8; 32]);
+        assert_eq!(with_memo.to_field_elements()[3], GoldilocksField::ONE);
+        assert_ne!(with_memo.digest(), data.digest());
+        let deposit = TransitionData::new(TransitionKind::Deposit, data.amount);
+        assert_ne!(deposit.digest(), data.digest());
+
+        // So is the funding backing a deposit
+        let funded = TransitionData::deposit(data.amount, [5u8; 32]);
+        assert_eq!(funded.to_field_elements()[12..20], bytes_to_limbs(&[5u8; 32]));
+        assert_ne!(funded.digest(), deposit.digest());
+
+        // And the number of transfers in a batch
+        let batch = TransitionData::transfer_batch(3, data.amount);
+        assert_eq!(batch.to_field_elements()[20..], u64_to_limbs(3));
+        assert_ne!(batch.digest(), TransitionData::transfer_batch(2, data.amount).digest());
+    }
+
+    #[test]
+    fn test_apply_transition_kinds() -> Result<()> {
+        let initial = initial_state();
+
+        // Amounts above u32::MAX are carried in full
+        let deposit = TransitionData::deposit(5_000_000_000, [5u8; 32]);
+        let funded = apply_transition(&initial, &deposit)?;
+        assert_eq!(funded.sender_balance, 5_000_000_100);
+        assert_eq!(
+            funded.transition,
+            ChannelTransition::Deposit { amount: 5_000_000_000, funding: [5u8; 32] }
+        );
+        let unfunded = TransitionData::new(TransitionKind::Deposit, 5_000_000_000);
+        assert!(apply_transition(&initial, &unfunded).is_err());
+
+        let paid = apply_transition(&funded, &transition_data(5_000_000_000))?;
+        assert_eq!((paid.sender_balance, paid.receiver_balance), (100, 5_000_000_000));
+        assert_eq!(paid.nonce, 2);
+
+        let batched = apply_transition(&paid, &TransitionData::transfer_batch(3, 60))?;
+        assert_eq!((batched.sender_balance, batched.receiver_balance), (40, 5_000_000_060));
+        assert_eq!(batched.nonce, 5);
+        assert_eq!(batched.transition, ChannelTransition::TransferBatch { count: 3, total: 60 });
+        for (count, total) in [(0, 0), (4, 3), (1, 41)] {
+            let batch = TransitionData::transfer_batch(count, total);
+            assert!(apply_transition(&batched, &batch).is_err());
+        }
+
+        let claimed = apply_transition(&paid, &TransitionData::new(TransitionKind::Claim, 40))?;
+        assert_eq!(claimed.receiver_balance, 4_999_999_960);
+        assert_eq!(claimed.transition.amount(), 40);
+
+        let close = TransitionData::new(TransitionKind::Close, 0);
+        let closed = apply_transition(&claimed, &close)?;
+        assert_eq!(closed.lifecycle, ChannelLifecycle::Closed);
+        assert_eq!(closed.transition, ChannelTransition::Close);
+        assert_eq!(closed.transition.tag(), TransitionKind::Close.tag());
+
+        // Closing is guarded like `ChannelState::close`
+        assert!(apply_transition(&closed, &close).is_err());
+        let funding = ChannelState { lifecycle: ChannelLifecycle::Funding, ..initial.clone() };
+        assert!(apply_transition(&funding, &close).is_err());
+        // but without the expiry check, which needs a clock
+        let expired = ChannelState { expiry: Some(1), ..initial.clone() };
+        assert!(apply_transition(&expired, &close).is_ok());
+
+        // The memo is carried into the resulting state and its hash
+        let noted = apply_transition(&initial, &transition_data(30).with_memo([9u8; 32]))?;
+        assert_eq!(noted.memo, Some([9u8; 32]));
+        let plain = apply_transition(&initial, &transition_data(30))?;
+        assert_ne!(hash_state(&noted)?, hash_state(&plain)?);
+
+        assert!(apply_transition(&initial, &transition_data(101)).is_err());
+        for kind in [TransitionKind::Claim, TransitionKind::Close] {
+            assert!(apply_transition(&initial, &TransitionData::new(kind, 1)).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_transition_proof_binds_state_hashes() -> Result<()> {
+        let circuit = StateTransitionCircuit::new();
+        let initial = initial_state();
+        let next = apply_transition(&initial, &transition_data(30))?;
+
+        let proof = circuit.generate_zkp(&CHANNEL_ID, &initial, &transition_data(30))?;
+        assert_eq!(
+            StateTransitionCircuit::public_state_hashes(&proof)?,
+            (hash_state(&initial)?, hash_state(&next)?)
+        );
+        assert_eq!(
+            StateTransitionCircuit::public_transition_digest(&proof)?,
+            transition_data(30).digest()
+        );
+        assert_eq!(StateTransitionCircuit::public_channel_id(&proof)?, CHANNEL_ID);
+        assert!(circuit.verify_transition(proof.clone(), &CHANNEL_ID, &initial, &next)?);
+
+        // The proof does not verify for another channel
+        assert!(!circuit.verify_transition(proof.clone(), &[5u8; 32], &initial, &next)?);
+
+        // The proof does not vouch for any other resulting state
+        let other = apply_transition(&initial, &transition_data(31))?;
+        assert!(!circuit.verify_transition(proof.clone(), &CHANNEL_ID, &initial, &other)?);
+
+        // A memo ends up in the proven state
+        let noted_data = transition_data(30).with_memo([9u8; 32]);
+        let noted = apply_transition(&initial, &noted_data)?;
+        let noted_proof = circuit.generate_zkp(&CHANNEL_ID, &initial, &noted_data)?;
+        assert!(circuit.verify_transition(noted_proof, &CHANNEL_ID, &initial, &noted)?);
+        assert!(!circuit.verify_transition(proof, &CHANNEL_ID, &initial, &noted)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transition_proof_rejects_invalid_transfers() {
+        let circuit = StateTransitionCircuit::new();
+        let initial = initial_state();
+
+        // Overdrafts and zero transfers cannot be proven
+        assert!(circuit.generate_zkp(&CHANNEL_ID, &initial, &transition_data(101)).is_err());
+        assert!(circuit.generate_zkp(&CHANNEL_ID, &initial, &transition_data(0)).is_err());
+        let deposit = TransitionData::new(TransitionKind::Deposit, 30);
+        assert!(circuit.generate_zkp(&CHANNEL_ID, &initial, &deposit).is_err());
+
+        // Tampering with the claimed resulting state invalidates the proof
+        let mut proof =
+            circuit.generate_zkp(&CHANNEL_ID, &initial, &transition_data(30)).unwrap();
+        proof.public_inputs[4] += GoldilocksField::ONE;
+        assert!(circuit.verify_proof(proof).is_err());
+    }
+
+    #[test]
+    fn test_transition_proof_kinds() -> Result<()> {
+        let circuit = StateTransitionCircuit::shared();
+        let initial = initial_state();
+
+        // Every kind proves the state `apply_transition` produces
+        let mut state = initial.clone();
+        for data in [
+            TransitionData::deposit(50, [5u8; 32]),
+            TransitionData::transfer(70).with_memo([9u8; 32]),
+            TransitionData::transfer_batch(4, 40),
+            TransitionData::new(TransitionKind::Claim, 30),
+            TransitionData::new(TransitionKind::Close, 0),
+        ] {
+            let next = apply_transition(&state, &data)?;
+            let proof = circuit.generate_zkp(&CHANNEL_ID, &state, &data)?;
+            assert_eq!(StateTransitionCircuit::public_transition_digest(&proof)?, data.digest());
+            assert!(circuit.verify_transition(proof, &CHANNEL_ID, &state, &next)?);
+            state = next;
+        }
+        assert_eq!(state.lifecycle, ChannelLifecycle::Closed);
+        assert_eq!((state.sender_balance, state.receiver_balance), (40, 80));
+
+        // A claim pays the channel's receiver and nobody else
+        let claim = TransitionData::new(TransitionKind::Claim, 30);
+        let paid = apply_transition(&initial, &TransitionData::transfer(50))?;
+        let claimed = apply_transition(&paid, &claim)?;
+        let proof = circuit.generate_zkp(&CHANNEL_ID, &paid, &claim)?;
+        let mut redirected = claimed.clone();
+        let output = SettlementOutput { recipient: [8u8; 32], amount: 30 };
+        redirected.transition = ChannelTransition::Claim { output };
+        assert!(!circuit.verify_transition(proof, &CHANNEL_ID, &paid, &redirected)?);
+
+        // Transitions the channel rules reject cannot be proven
+        let closed = apply_transition(&initial, &TransitionData::new(TransitionKind::Close, 0))?;
+        for (state, data) in [
+            (&closed, TransitionData::transfer(10)),
+            (&initial, TransitionData::transfer_batch(2, 1)),
+            (&initial, TransitionData::deposit(0, [5u8; 32])),
+        ] {
+            assert!(circuit.generate_zkp(&CHANNEL_ID, state, &data).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_verifier_round_trip() -> Result<()> {
+        let circuit = StateTransitionCircuit::new();
+        let initial = initial_state();
+        let next = apply_transition(&initial, &transition_data(30))?;
+        let proof = circuit.generate_zkp(&CHANNEL_ID, &initial, &transition_data(30))?;
+
+        // A verifier loaded from bytes checks proofs of the original circuit
+        let bytes = circuit.verifier().to_bytes()?;
+        let digest = circuit.circuit_digest();
+        let verifier = StateTransitionVerifier::from_bytes_with_digest(&bytes, &digest)?;
+        assert_eq!(verifier.circuit_digest(), digest);
+        assert!(verifier.verify_transition(proof.clone(), &CHANNEL_ID, &initial, &next)?);
+        assert!(verifier.verify_proof_bytes(&proof.to_bytes(), &CHANNEL_ID, &next)?);
+        assert!(!verifier.verify_proof_bytes(&proof.to_bytes(), &CHANNEL_ID, &initial)?);
+
+        // The digest is stable across builds and guards against other versions
+        assert_eq!(StateTransitionCircuit::new().circuit_digest(), digest);
+        assert!(StateTransitionVerifier::from_bytes_with_digest(&bytes, &[0u8; 32]).is_err());
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("verifier.bin");
+        verifier.save(&path)?;
+        let loaded = StateTransitionVerifier::load(&path)?;
+        assert!(loaded.verify_proof(proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transition_history() -> Result<()> {
+        let circuit = StateTransitionCircuit::shared();
+        let mut history = TransitionHistory::new();
+        let genesis = initial_state();
+        let first = circuit.generate_zkp(&CHANNEL_ID, &genesis, &transition_data(10))?;
+
+        // Transitions are only recorded once the channel is opened at genesis
+        assert!(history.record(circuit, CHANNEL_ID, &first).is_err());
+        let paid = apply_transition(&genesis, &transition_data(10))?;
+        assert!(history.open(CHANNEL_ID, &paid).is_err());
+        history.open(CHANNEL_ID, &genesis)?;
+        assert!(history.open(CHANNEL_ID, &genesis).is_err());
+        assert_eq!(history.genesis_hash(&CHANNEL_ID), Some(hash_state(&genesis)?));
+        assert!(history.root(&CHANNEL_ID).is_none());
+
+        // The first transition has to start from genesis
+        let skipped = circuit.generate_zkp(&CHANNEL_ID, &paid, &transition_data(20))?;
+        assert!(history.record(circuit, CHANNEL_ID, &skipped).is_err());
+
+        let mut state = genesis.clone();
+        let mut records = Vec::new();
+        for amount in [10, 20, 30] {
+            let proof = circuit.generate_zkp(&CHANNEL_ID, &state, &transition_data(amount))?;
+            records.push(history.record(circuit, CHANNEL_ID, &proof)?);
+            state = apply_transition(&state, &transition_data(amount))?;
+        }
+        assert_eq!(history.records(&CHANNEL_ID), records.as_slice());
+        assert_eq!(records[2].new_state_hash, hash_state(&state)?);
+
+        // Every recorded transition is provably part of the history, also
+        // to a verifier holding only the root
+        let root = history.root(&CHANNEL_ID).unwrap();
+        for record in &records {
+            let proof = history.generate_merkle_proof(&CHANNEL_ID, record.index).unwrap();
+            assert!(history.verify_merkle_proof(&CHANNEL_ID, record, &proof));
+            assert!(verify_inclusion(&root, &CHANNEL_ID, record, &proof));
+            assert!(!history.verify_merkle_proof(&[5u8; 32], record, &proof));
+            assert!(!verify_inclusion(&root, &[5u8; 32], record, &proof));
+        }
+        let proof = history.generate_merkle_proof(&CHANNEL_ID, 1).unwrap();
+        let mut forged = records[1];
+        forged.transition_digest = transition_data(21).digest();
+        assert!(!history.verify_merkle_proof(&CHANNEL_ID, &forged, &proof));
+        let mut moved = records[1];
+        moved.index = 5;
+        assert!(!verify_inclusion(&root, &CHANNEL_ID, &moved, &proof));
+        assert!(history.generate_merkle_proof(&CHANNEL_ID, 3).is_none());
+
+        // Transitions must continue from the last recorded state
+        let stale = circuit.generate_zkp(&CHANNEL_ID, &genesis, &transition_data(5))?;
+        assert!(history.record(circuit, CHANNEL_ID, &stale).is_err());
+
+        // And be proven for this channel
+        let other = [5u8; 32];
+        let foreign = circuit.generate_zkp(&other, &state, &transition_data(5))?;
+        assert!(history.record(circuit, CHANNEL_ID, &foreign).is_err());
+        assert_eq!(history.records(&CHANNEL_ID).len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,468 @@
+//! Hash-chained channel transition log
+//!
+//! This module records every accepted state of a channel in an append-only
+//! log. Each entry commits to the previous entry's hash and to the full
+//! recorded state, so the log can be replayed from genesis and exported for
+//! auditors.
+//!
+//! Entry timestamps are read from the appender's `Clock`. Replay checks that
+//! they never decrease and are not ahead of the replayer's clock, but a log
+//! re-hashed with other timestamps replays just as well; pin the head hash
+//! with `import_anchored` to rule that out.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::channel::{ChannelState, ChannelTransition};
+use crate::clock::Clock;
+use crate::error::ChannelError;
+use crate::signing::tagged_hash;
+use crate::state::hash_state;
+use crate::types::Bytes32;
+
+/// BIP340 tag for transition log entry hashes.
+const ENTRY_HASH_TAG: &[u8] = b"Overpass/TransitionLogEntry";
+
+/// Represents errors in transition log operations.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TransitionLogError {
+    #[error("Genesis entry must hold a genesis state")]
+    InvalidGenesis,
+    #[error("Transition log is empty")]
+    EmptyLog,
+    #[error("Entry {index} does not link to the previous entry")]
+    BrokenChain { index: u64 },
+    #[error("Entry {index} hash does not match its contents")]
+    HashMismatch { index: u64 },
+    #[error("Entry {index} is timestamped in the future")]
+    FutureTimestamp { index: u64 },
+    #[error("Log does not end in the anchored head hash")]
+    HeadMismatch,
+    #[error("Entry {index} holds an invalid transition: {source}")]
+    InvalidTransition { index: u64, source: ChannelError },
+    #[error("Failed to hash channel state")]
+    StateHashFailed,
+    #[error("Serialization error: {0}")]
+    SerializationError(String),
+}
+
+impl From<serde_json::Error> for TransitionLogError {
+    fn from(err: serde_json::Error) -> Self { TransitionLogError::SerializationError(err.to_string()) }
+}
+
+/// A single accepted channel state and its position in the hash chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransitionLogEntry {
+    /// Position of the entry in the log, starting at 0 for genesis
+    pub index: u64,
+    /// Unix timestamp (seconds) at which the state was accepted
+    pub timestamp: u64,
+    /// Hash of the previous entry, all zeros for genesis
+    pub prev_hash: Bytes32,
+    /// `hash_state` of the recorded state
+    pub state_hash: Bytes32,
+    /// Hash of this entry, committing to all of the above and the full state
+    pub entry_hash: Bytes32,
+    /// The accepted channel state
+    pub state: ChannelState,
+}
+
+impl TransitionLogEntry {
+    fn new(
+        index: u64,
+        timestamp: u64,
+        prev_hash: Bytes32,
+        state: ChannelState,
+    ) -> Result<Self, TransitionLogError> {
+        let state_hash = hash_state(&state).map_err(|_| TransitionLogError::StateHashFailed)?;
+        let entry_hash = entry_hash(index, timestamp, &prev_hash, &state)?;
+        Ok(Self { index, timestamp, prev_hash, state_hash, entry_hash, state })
+    }
+
+    /// Checks that the stored hashes match the entry's contents.
+    fn verify_hashes(&self) -> Result<(), TransitionLogError> {
+        let state_hash =
+            hash_state(&self.state).map_err(|_| TransitionLogError::StateHashFailed)?;
+        let expected = entry_hash(self.index, self.timestamp, &self.prev_hash, &self.state)?;
+        if state_hash != self.state_hash || expected != self.entry_hash {
+            return Err(TransitionLogError::HashMismatch { index: self.index });
+        }
+        Ok(())
+    }
+}
+
+/// Append-only log of every accepted state of one channel.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransitionLog {
+    /// Channel the log belongs to
+    pub channel_id: Bytes32,
+    entries: Vec<TransitionLogEntry>,
+}
+
+impl TransitionLog {
+    /// Starts a log for `channel_id` from its genesis state, timestamped
+    /// with `clock`'s time.
+    pub fn new(
+        channel_id: Bytes32,
+        genesis: ChannelState,
+        clock: &dyn Clock,
+    ) -> Result<Self, TransitionLogError> {
+        if genesis.transition != ChannelTransition::Genesis {
+            return Err(TransitionLogError::InvalidGenesis);
+        }
+        let entry = TransitionLogEntry::new(0, clock.now(), [0u8; 32], genesis)?;
+        Ok(Self { channel_id, entries: vec![entry] })
+    }
+
+    /// Verifies `state` against the latest state by `clock`'s time and
+    /// appends it, returning the new entry hash.
+    pub fn append(
+        &mut self,
+        state: ChannelState,
+        clock: &dyn Clock,
+    ) -> Result<Bytes32, TransitionLogError> {
+        let timestamp = clock.now();
+        let head = self.head_entry();
+        let index = head.index + 1;
+        verify_step(&head.state, &state, timestamp)
+            .map_err(|source| TransitionLogError::InvalidTransition { index, source })?;
+
+        let entry = TransitionLogEntry::new(index, timestamp, head.entry_hash, state)?;
+        let entry_hash = entry.entry_hash;
+        self.entries.push(entry);
+        Ok(entry_hash)
+    }
+
+    /// Replays the log from genesis, checking the hash chain, the entry
+    /// timestamps against `clock` and every transition, and returns the
+    /// latest state.
+    pub fn replay(&self, clock: &dyn Clock) -> Result<ChannelState, TransitionLogError> {
+        let now = clock.now();
+        if let Some(entry) = self.entries.iter().find(|entry| entry.timestamp > now) {
+            return Err(TransitionLogError::FutureTimestamp { index: entry.index });
+        }
+
+        let genesis = self.entries.first().ok_or(TransitionLogError::EmptyLog)?;
+        if genesis.index != 0 || genesis.prev_hash != [0u8; 32] {
+            return Err(TransitionLogError::BrokenChain { index: 0 });
+        }
+        if genesis.state.transition != ChannelTransition::Genesis {
+            return Err(TransitionLogError::InvalidGenesis);
+        }
+        genesis.verify_hashes()?;
+
+        for (prev, entry) in self.entries.iter().zip(self.entries.iter().skip(1)) {
+            let index = prev.index + 1;
+            if entry.index != index
+                || entry.prev_hash != prev.entry_hash
+                || entry.timestamp < prev.timestamp
+            {
+                return Err(TransitionLogError::BrokenChain { index });
+            }
+            entry.verify_hashes()?;
+            verify_step(&prev.state, &entry.state, entry.timestamp)
+                .map_err(|source| TransitionLogError::InvalidTransition { index, source })?;
+        }
+
+        Ok(self.head().clone())
+    }
+
+    /// Exports the log as JSON for auditors.
+    pub fn export_json(&self) -> Result<String, TransitionLogError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Imports an exported log, replaying it against `clock` before
+    /// returning.
+    pub fn import_json(json: &str, clock: &dyn Clock) -> Result<Self, TransitionLogError> {
+        let log: Self = serde_json::from_str(json)?;
+        log.replay(clock)?;
+        Ok(log)
+    }
+
+    /// Imports an exported log like `import_json`, also requiring it to end
+    /// in `head_hash`, obtained from a source the exporter cannot rewrite.
+    /// This pins every entry, including the timestamps replay relies on.
+    pub fn import_anchored(
+        json: &str,
+        head_hash: &Bytes32,
+        clock: &dyn Clock,
+    ) -> Result<Self, TransitionLogError> {
+        let log = Self::import_json(json, clock)?;
+        if log.head_hash() != *head_hash {
+            return Err(TransitionLogError::HeadMismatch);
+        }
+        Ok(log)
+    }
+
+    /// Gets the latest recorded state.
+    pub fn head(&self) -> &ChannelState { &self.head_entry().state }
+
+    /// Gets the hash of the latest entry.
+    pub fn head_hash(&self) -> Bytes32 { self.head_entry().entry_hash }
+
+    /// Gets all entries, oldest first.
+    pub fn entries(&self) -> &[TransitionLogEntry] { &self.entries }
+
+    /// Number of entries, including genesis.
+    pub fn len(&self) -> usize { self.entries.len() }
+
+    /// Whether the log has no entries. Logs built with `new` never are.
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    fn head_entry(&self) -> &TransitionLogEntry {
+        self.entries.last().expect("transition log always holds a genesis entry")
+    }
+}
+
+/// Verifies a single step of the log, using refund and close rules for final
+/// states and lifecycle rules for lifecycle moves.
+fn verify_step(
+    prior: &ChannelState,
+    next: &ChannelState,
+    timestamp: u64,
+) -> Result<(), ChannelError> {
+    match next.transition {
+        ChannelTransition::Refund => next.verify_refund_at(prior, timestamp),
+        ChannelTransition::Close => next.verify_close_at(prior, timestamp),
+        ChannelTransition::Lifecycle => next.verify_lifecycle(prior),
+        _ => next.verify_transition_at(prior, timestamp),
+    }
+}
+
+/// Hash of a log entry:
+/// `tagged_hash(index || timestamp || prev_hash || state_digest || state)`,
+/// where `state` is the JSON encoding of the whole state, so the hash also
+/// covers the proof, range proofs and signature that `state_digest` leaves out.
+fn entry_hash(
+    index: u64,
+    timestamp: u64,
+    prev_hash: &Bytes32,
+    state: &ChannelState,
+) -> Result<Bytes32, TransitionLogError> {
+    let state_digest = state.state_digest().map_err(|_| TransitionLogError::StateHashFailed)?;
+    let encoded_state = serde_json::to_vec(state)?;
+
+    let mut message = Vec::with_capacity(80 + encoded_state.len());
+    message.extend_from_slice(&index.to_le_bytes());
+    message.extend_from_slice(&timestamp.to_le_bytes());
+    message.extend_from_slice(prev_hash);
+    message.extend_from_slice(&state_digest);
+    message.extend_from_slice(&encoded_state);
+    Ok(tagged_hash(ENTRY_HASH_TAG, &message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{ChannelLifecycle, ChannelParticipants};
+    use crate::clock::ManualClock;
+    use crate::signing::x_only_public_key;
+    use secp256k1::{Keypair, SECP256K1};
+
+    /// Unix time the test clock starts at.
+    const NOW: u64 = 1_700_000_000;
+
+    fn clock() -> ManualClock { ManualClock::new(NOW, 800_000) }
+
+    fn keypair(seed: u8) -> Keypair { Keypair::from_seckey_slice(SECP256K1, &[seed; 32]).unwrap() }
+
+    fn participants() -> ChannelParticipants {
+        ChannelParticipants {
+            sender: x_only_public_key(&keypair(1)),
+            receiver: x_only_public_key(&keypair(2)),
+        }
+    }
+
+    fn signed(mut state: ChannelState, signer: &Keypair) -> ChannelState {
+        state.sign(signer).unwrap();
+        state
+    }
+
+    fn build_log() -> TransitionLog {
+        let genesis = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let mut log = TransitionLog::new([7u8; 32], genesis, &clock()).unwrap();
+
+        let paid = signed(log.head().transfer(40, &clock()).unwrap(), &keypair(1));
+        log.append(paid, &clock()).unwrap();
+        let topped_up = signed(log.head().deposit(20, [5u8; 32], &clock()).unwrap(), &keypair(1));
+        log.append(topped_up, &clock()).unwrap();
+        let claimed = signed(log.head().claim(10, &clock()).unwrap(), &keypair(2));
+        log.append(claimed, &clock()).unwrap();
+        log
+    }
+
+    #[test]
+    fn test_append_and_replay() {
+        let log = build_log();
+        assert_eq!(log.len(), 4);
+        assert_eq!(log.head().nonce, 3);
+
+        for (prev, entry) in log.entries().iter().zip(log.entries().iter().skip(1)) {
+            assert_eq!(entry.prev_hash, prev.entry_hash);
+            assert_eq!(entry.state_hash, hash_state(&entry.state).unwrap());
+        }
+
+        let head = log.replay(&clock()).unwrap();
+        assert_eq!(&head, log.head());
+        assert_eq!((head.sender_balance, head.receiver_balance), (80, 30));
+    }
+
+    #[test]
+    fn test_append_rejects_invalid_transition() {
+        let mut log = build_log();
+        let head_hash = log.head_hash();
+
+        let unsigned = log.head().transfer(5, &clock()).unwrap();
+        assert_eq!(
+            log.append(unsigned, &clock()),
+            Err(TransitionLogError::InvalidTransition {
+                index: 4,
+                source: ChannelError::MissingSignature
+            })
+        );
+        assert_eq!(log.head_hash(), head_hash);
+
+        let not_genesis = log.head().clone();
+        assert_eq!(
+            TransitionLog::new([7u8; 32], not_genesis, &clock()),
+            Err(TransitionLogError::InvalidGenesis)
+        );
+    }
+
+    #[test]
+    fn test_replay_detects_tampering() {
+        let log = build_log();
+
+        // Rewriting a recorded state breaks its hash
+        let mut rewritten = log.clone();
+        rewritten.entries[1].state.metadata = b"forged".to_vec();
+        assert_eq!(rewritten.replay(&clock()), Err(TransitionLogError::HashMismatch { index: 1 }));
+
+        // So does swapping the proof or range proofs of a recorded state
+        let mut reproved = log.clone();
+        reproved.entries[1].state.proof = Some(vec![1u8; 32]);
+        assert_eq!(reproved.replay(&clock()), Err(TransitionLogError::HashMismatch { index: 1 }));
+        let mut swapped = log.clone();
+        swapped.entries[2].state.range_proof = log.entries[1].state.range_proof.clone();
+        assert_eq!(swapped.replay(&clock()), Err(TransitionLogError::HashMismatch { index: 2 }));
+
+        // Dropping an entry breaks the chain
+        let mut truncated = log.clone();
+        truncated.entries.remove(2);
+        assert_eq!(truncated.replay(&clock()), Err(TransitionLogError::BrokenChain { index: 2 }));
+
+        // Re-hashing a forged entry still fails transition verification
+        let mut forged = log.clone();
+        let entry = &forged.entries[1];
+        let mut state = entry.state.clone();
+        state.receiver_balance += 1;
+        forged.entries[1] =
+            TransitionLogEntry::new(1, entry.timestamp, entry.prev_hash, state).unwrap();
+        assert!(matches!(
+            forged.replay(&clock()),
+            Err(TransitionLogError::InvalidTransition { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_replay_refund() {
+        let clock = clock();
+        let expiry = NOW + 60;
+        let genesis = ChannelState::new(100, Vec::new(), participants())
+            .unwrap()
+            .with_expiry(expiry)
+            .unwrap();
+        let mut log = TransitionLog::new([7u8; 32], genesis, &clock).unwrap();
+
+        assert!(log.head().refund(&clock).is_err());
+        clock.set(expiry);
+        let refund = signed(log.head().refund(&clock).unwrap(), &keypair(1));
+
+        // The refund is only appended once the log's clock reaches the expiry
+        let early = ManualClock::new(expiry - 1, 800_000);
+        assert!(matches!(
+            log.append(refund.clone(), &early),
+            Err(TransitionLogError::InvalidTransition { index: 1, .. })
+        ));
+        log.append(refund, &clock).unwrap();
+        assert!(log.replay(&clock).is_ok());
+    }
+
+    #[test]
+    fn test_replay_lifecycle_moves() {
+        let genesis = ChannelState::new_funding(100, Vec::new(), participants()).unwrap();
+        let mut log = TransitionLog::new([7u8; 32], genesis, &clock()).unwrap();
+
+        let mut opened = log.head().clone();
+        opened.advance_lifecycle(ChannelLifecycle::Open, &keypair(1)).unwrap();
+        log.append(opened, &clock()).unwrap();
+        let paid = signed(log.head().transfer(40, &clock()).unwrap(), &keypair(1));
+        log.append(paid, &clock()).unwrap();
+        assert_eq!(log.replay(&clock()).unwrap().nonce, 2);
+
+        // A stage change slipped in without its own entry breaks the chain
+        let mut closed = log.head().clone();
+        closed.lifecycle = ChannelLifecycle::Closed;
+        assert!(matches!(
+            log.append(closed, &clock()),
+            Err(TransitionLogError::InvalidTransition { index: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let log = build_log();
+        let json = log.export_json().unwrap();
+        assert_eq!(TransitionLog::import_json(&json, &clock()).unwrap(), log);
+
+        let mut tampered = log.clone();
+        tampered.entries[3].state.receiver_balance = 0;
+        let json = tampered.export_json().unwrap();
+        assert_eq!(
+            TransitionLog::import_json(&json, &clock()),
+            Err(TransitionLogError::HashMismatch { index: 3 })
+        );
+        assert!(matches!(
+            TransitionLog::import_json("not json", &clock()),
+            Err(TransitionLogError::SerializationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let log = build_log();
+
+        // Entries cannot be dated in the future
+        let mut postdated = log.clone();
+        let entry = &postdated.entries[3];
+        postdated.entries[3] = TransitionLogEntry::new(
+            3,
+            NOW + 3600,
+            entry.prev_hash,
+            entry.state.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            postdated.replay(&clock()),
+            Err(TransitionLogError::FutureTimestamp { index: 3 })
+        );
+
+        // A consistently re-hashed log replays, but not against the anchored head
+        let mut redated = log.clone();
+        let mut prev_hash = [0u8; 32];
+        for entry in redated.entries.iter_mut() {
+            *entry = TransitionLogEntry::new(entry.index, 1, prev_hash, entry.state.clone())
+                .unwrap();
+            prev_hash = entry.entry_hash;
+        }
+        let json = redated.export_json().unwrap();
+        assert!(TransitionLog::import_json(&json, &clock()).is_ok());
+        assert_eq!(
+            TransitionLog::import_anchored(&json, &log.head_hash(), &clock()),
+            Err(TransitionLogError::HeadMismatch)
+        );
+        let json = log.export_json().unwrap();
+        assert_eq!(TransitionLog::import_anchored(&json, &log.head_hash(), &clock()).unwrap(), log);
+    }
+}
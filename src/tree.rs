@@ -8,11 +8,23 @@
 //! hashed using the same `hash_pair` function (which uses double-SHA256). This follows the Bitcoin Core
 //! design.  
 //!
+//! The pair hash is pluggable through `MerkleHasher`. `MerkleTree` defaults to SHA-256 for
+//! Bitcoin-facing commitments, while `PoseidonMerkleTree` hashes over Goldilocks with Poseidon so
+//! its roots are cheap to recompute inside plonky2 circuits.
+//!
 //! *TODO:* We may introduce domain separation (e.g. prefixing leaves with `0x00` and internal nodes with
 //! `0x01`) to eliminate any theoretical ambiguities. Please see section X in the Developer Documentation for more details.
 
+use std::marker::PhantomData;
+
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::hash::hash_types::HashOut;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::config::Hasher;
+use plonky2_field::types::Field;
 use thiserror::Error;
 
+use crate::codec::{bytes_to_hash_out, hash_out_to_bytes, CodecError};
 use crate::merkle::hash_pair;
 use crate::types::Bytes32;
 
@@ -21,6 +33,8 @@ use crate::types::Bytes32;
 pub enum MerkleTreeError {
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+    #[error("Invalid tree node: {0}")]
+    InvalidNode(#[from] CodecError),
     #[error("Proof generation failed: {0}")]
     ProofGenerationFailed(String),
     #[error("Proof verification failed: {0}")]
@@ -28,31 +42,105 @@ pub enum MerkleTreeError {
     // Add other relevant error variants as needed
 }
 
+/// Hash function combining two nodes of a `MerkleTree` into their parent.
+pub trait MerkleHasher {
+    /// Checks that `node` is in the hasher's domain. Leaves failing this check
+    /// are rejected by the tree.
+    fn is_valid_node(_node: &Bytes32) -> bool { true }
+
+    /// Hashes a left and a right node into their parent, failing on nodes
+    /// outside the hasher's domain.
+    fn hash_pair(left: Bytes32, right: Bytes32) -> Result<Bytes32, MerkleTreeError>;
+
+    /// Sibling of `node` when it is the last node of an odd-sized level,
+    /// `level` levels above the leaves. Defaults to `node` itself.
+    fn padding(node: &Bytes32, _level: usize) -> Bytes32 { *node }
+}
+
+/// SHA-256 pair hashing, as in `merkle::hash_pair`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha256Hasher;
+
+impl MerkleHasher for Sha256Hasher {
+    fn hash_pair(left: Bytes32, right: Bytes32) -> Result<Bytes32, MerkleTreeError> {
+        Ok(hash_pair(left, right))
+    }
+}
+
+/// Hashes two child nodes into their parent with Poseidon, natively.
+pub fn hash_children(
+    left: &HashOut<GoldilocksField>,
+    right: &HashOut<GoldilocksField>,
+) -> HashOut<GoldilocksField> {
+    PoseidonHash::hash_no_pad(&[left.elements, right.elements].concat())
+}
+
+/// Poseidon pair hashing over Goldilocks, with `hash_children`.
+///
+/// Nodes are the canonical `HashOut` encodings produced by `codec`, such as
+/// `hash_state` digests. Odd levels are padded with the roots of all-zero
+/// subtrees, which pads the leaves to a power of two with zero hashes as in
+/// the state trees of `merkle_circuit`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoseidonHasher;
+
+impl MerkleHasher for PoseidonHasher {
+    fn is_valid_node(node: &Bytes32) -> bool { bytes_to_hash_out(node).is_ok() }
+
+    fn hash_pair(left: Bytes32, right: Bytes32) -> Result<Bytes32, MerkleTreeError> {
+        let (left, right) = (bytes_to_hash_out(&left)?, bytes_to_hash_out(&right)?);
+        Ok(hash_out_to_bytes(&hash_children(&left, &right)))
+    }
+
+    fn padding(_node: &Bytes32, level: usize) -> Bytes32 {
+        let mut zero = HashOut { elements: [GoldilocksField::ZERO; 4] };
+        for _ in 0..level {
+            zero = hash_children(&zero, &zero);
+        }
+        hash_out_to_bytes(&zero)
+    }
+}
+
+/// Merkle tree hashed with Poseidon, for roots recomputed inside circuits.
+pub type PoseidonMerkleTree = MerkleTree<PoseidonHasher>;
+
 /// Represents a simple Merkle Tree.
 #[derive(Debug, Clone)]
-pub struct MerkleTree {
+pub struct MerkleTree<H = Sha256Hasher> {
     pub leaves: Vec<Bytes32>,
     pub root: Bytes32,
     pub tree: Vec<Vec<Bytes32>>, // Level 0: leaves, Level n: root
+    hasher: PhantomData<H>,
 }
 
-impl MerkleTree {
-    /// Creates a new empty Merkle Tree.
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Creates a new empty Merkle Tree using the hasher `H`.
     pub fn new() -> Self {
         let leaves = Vec::new();
         let root = [0u8; 32];
         let tree = Vec::new();
-        Self { leaves, root, tree }
+        Self { leaves, root, tree, hasher: PhantomData }
     }
 
-    /// Inserts a new leaf and updates the tree incrementally.
+    /// Builds a tree over `leaves`, in order.
+    pub fn from_leaves(leaves: Vec<Bytes32>) -> Result<Self, MerkleTreeError> {
+        leaves.iter().try_for_each(Self::check_leaf)?;
+        let mut tree = Self::new();
+        tree.leaves = leaves;
+        tree.recompute_tree()?;
+        Ok(tree)
+    }
+
+    /// Inserts a new leaf and updates the tree.
     pub fn insert(&mut self, leaf: Bytes32) -> Result<(), MerkleTreeError> {
+        Self::check_leaf(&leaf)?;
         self.leaves.push(leaf);
         self.update_tree_on_insert()
     }
 
     /// Updates a leaf at a given position and recomputes the tree.
     pub fn update(&mut self, old_leaf: Bytes32, new_leaf: Bytes32) -> Result<(), MerkleTreeError> {
+        Self::check_leaf(&new_leaf)?;
         if let Some(pos) = self.leaves.iter().position(|x| x == &old_leaf) {
             self.leaves[pos] = new_leaf;
             self.update_tree_on_update(pos)
@@ -61,7 +149,26 @@ impl MerkleTree {
         }
     }
 
-    /// Deletes a leaf and updates the tree incrementally.
+    /// Replaces the leaf at `index` and updates its path to the root.
+    pub fn update_at(&mut self, index: usize, leaf: Bytes32) -> Result<(), MerkleTreeError> {
+        Self::check_leaf(&leaf)?;
+        let slot = self
+            .leaves
+            .get_mut(index)
+            .ok_or_else(|| MerkleTreeError::InvalidInput("Leaf index out of range".to_string()))?;
+        *slot = leaf;
+        self.update_tree_on_update(index)
+    }
+
+    /// Rejects leaves outside the hasher's domain.
+    fn check_leaf(leaf: &Bytes32) -> Result<(), MerkleTreeError> {
+        if !H::is_valid_node(leaf) {
+            return Err(MerkleTreeError::InvalidInput("Leaf is not a valid tree node".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Deletes a leaf and updates the tree.
     pub fn delete(&mut self, leaf: Bytes32) -> Result<(), MerkleTreeError> {
         if let Some(pos) = self.leaves.iter().position(|x| x == &leaf) {
             self.leaves.remove(pos);
@@ -71,75 +178,42 @@ impl MerkleTree {
         }
     }
 
+    /// Hashes the nodes `level` levels above the leaves into their parents,
+    /// pairing an odd last node with its padding.
+    fn hash_level(nodes: &[Bytes32], level: usize) -> Result<Vec<Bytes32>, MerkleTreeError> {
+        nodes
+            .chunks(2)
+            .map(|pair| match *pair {
+                [left, right] => H::hash_pair(left, right),
+                [last, ..] => H::hash_pair(last, H::padding(&last, level)),
+                [] => unreachable!("chunks are never empty"),
+            })
+            .collect()
+    }
+
     /// Recomputes the entire tree. Use for initial construction or drastic changes.
-    #[allow(dead_code)]
-    fn recompute_tree(&mut self) {
+    fn recompute_tree(&mut self) -> Result<(), MerkleTreeError> {
         if self.leaves.is_empty() {
             self.root = [0u8; 32];
             self.tree = Vec::new();
-            return;
-        }
-        let mut current_level = self.leaves.clone();
-        self.tree = vec![current_level.clone()];
-        while current_level.len() > 1 {
-            if current_level.len() % 2 != 0 {
-                current_level.push(*current_level.last().unwrap());
-            }
-            current_level =
-                current_level.chunks(2).map(|pair| hash_pair(pair[0], pair[1])).collect();
-            self.tree.push(current_level.clone());
-        }
-        self.root = current_level[0];
-    }
-    /// Incrementally updates the tree upon inserting a new leaf.
-    fn update_tree_on_insert(&mut self) -> Result<(), MerkleTreeError> {
-        // Create a mutable clone of leaves to work with.
-        let mut base = self.leaves.clone();
-
-        // if there's exactly one leaf, don't duplicate.
-        if base.len() == 1 {
-            if self.tree.is_empty() {
-                self.tree.push(base.clone());
-            } else {
-                self.tree[0] = base.clone();
-            }
-            self.root = base[0];
             return Ok(());
         }
-
-        // For more than one leaf, if the number is odd, duplicate the last leaf.
-        if base.len() % 2 != 0 {
-            base.push(*base.last().unwrap());
-        }
-
-        // Update the base level.
-        if self.tree.is_empty() {
-            self.tree.push(base.clone());
-        } else {
-            self.tree[0] = base.clone();
+        let mut tree = vec![self.leaves.clone()];
+        while tree[tree.len() - 1].len() > 1 {
+            let next = Self::hash_level(&tree[tree.len() - 1], tree.len() - 1)?;
+            tree.push(next);
         }
-
-        // Recompute each subsequent level.
-        let mut current_level = base;
-        let mut level = 0;
-        while current_level.len() > 1 {
-            let mut next_level = Vec::new();
-            for pair in current_level.chunks(2) {
-                let hash = hash_pair(pair[0], pair[1]);
-                next_level.push(hash);
-            }
-            level += 1;
-            if self.tree.len() > level {
-                self.tree[level] = next_level.clone();
-            } else {
-                self.tree.push(next_level.clone());
-            }
-            current_level = next_level;
-        }
-        self.root = current_level[0];
+        self.root = tree[tree.len() - 1][0];
+        self.tree = tree;
         Ok(())
     }
 
+    /// Updates the tree upon inserting a new leaf. Appending can change the
+    /// padding of every level, so all levels are recomputed.
+    fn update_tree_on_insert(&mut self) -> Result<(), MerkleTreeError> {
+        self.recompute_tree()
+    }
+
     /// Incrementally updates the tree upon updating a leaf.
     fn update_tree_on_update(&mut self, pos: usize) -> Result<(), MerkleTreeError> {
         if self.tree.is_empty() {
@@ -154,14 +228,13 @@ impl MerkleTree {
             let sibling_pos = if current_pos % 2 == 0 { current_pos + 1 } else { current_pos - 1 };
             let parent_pos = current_pos / 2;
 
-            let hash = if sibling_pos < current_level.len() {
-                if current_pos % 2 == 0 {
-                    hash_pair(current_level[current_pos], current_level[sibling_pos])
-                } else {
-                    hash_pair(current_level[sibling_pos], current_level[current_pos])
-                }
+            let node = current_level[current_pos];
+            let hash = if sibling_pos >= current_level.len() {
+                H::hash_pair(node, H::padding(&node, level))?
+            } else if current_pos % 2 == 0 {
+                H::hash_pair(node, current_level[sibling_pos])?
             } else {
-                current_level[current_pos]
+                H::hash_pair(current_level[sibling_pos], node)?
             };
 
             self.tree[level + 1][parent_pos] = hash;
@@ -172,38 +245,11 @@ impl MerkleTree {
         Ok(())
     }
 
-    /// Incrementally updates the tree upon deleting a leaf.
+    /// Updates the tree upon deleting a leaf. Removing a leaf shifts every
+    /// later leaf and can make the tree shallower, so all levels are
+    /// recomputed.
     fn update_tree_on_delete(&mut self, _pos: usize) -> Result<(), MerkleTreeError> {
-        if self.leaves.is_empty() {
-            self.tree.clear();
-            self.root = [0u8; 32];
-            return Ok(());
-        }
-
-        // Update the base level with the current leaves.
-        self.tree[0] = self.leaves.clone();
-
-        // Recompute each level
-        let mut current_level = self.tree[0].clone();
-        for level in 1..self.tree.len() {
-            let mut next_level = Vec::new();
-
-            for chunk in current_level.chunks(2) {
-                if chunk.len() == 2 {
-                    next_level.push(hash_pair(chunk[0], chunk[1]));
-                } else {
-                    // Duplicate the lone element to compute its hash.
-                    next_level.push(hash_pair(chunk[0], chunk[0]));
-                }
-            }
-
-            self.tree[level] = next_level.clone();
-            current_level = next_level;
-        }
-
-        // Update root
-        self.root = self.tree.last().unwrap()[0];
-        Ok(())
+        self.recompute_tree()
     }
 
     /// Generates a Merkle proof for a given leaf.
@@ -212,24 +258,21 @@ impl MerkleTree {
         let mut proof = Vec::new();
         let mut index = pos;
         // Iterate over all levels except the root level.
-        for level in self.tree.iter().take(self.tree.len() - 1) {
-            let sibling_index = if index % 2 == 0 {
-                if index + 1 < level.len() {
-                    index + 1
-                } else {
-                    // No sibling exists; duplicate the node.
-                    index
-                }
+        for (level, nodes) in self.tree.iter().take(self.tree.len() - 1).enumerate() {
+            let sibling = if index % 2 == 0 {
+                // Past the end of the level, the node is paired with its padding.
+                nodes.get(index + 1).copied().unwrap_or_else(|| H::padding(&nodes[index], level))
             } else {
-                index - 1
+                nodes[index - 1]
             };
-            proof.push(level[sibling_index]);
+            proof.push(sibling);
             index /= 2;
         }
         Some(proof)
     }
 
-    /// Verifies a Merkle proof.
+    /// Verifies a Merkle proof. Proofs with nodes outside the hasher's domain
+    /// never verify.
     pub fn verify_proof(&self, leaf: &Bytes32, proof: &[Bytes32], root: &Bytes32) -> bool {
         // Find the position of the leaf in the base level.
         let mut index = match self.leaves.iter().position(|x| x == leaf) {
@@ -239,12 +282,16 @@ impl MerkleTree {
 
         let mut computed_hash = *leaf;
         for sibling in proof {
-            if index % 2 == 0 {
+            let parent = if index % 2 == 0 {
                 // Current node is the left child.
-                computed_hash = hash_pair(computed_hash, *sibling);
+                H::hash_pair(computed_hash, *sibling)
             } else {
                 // Current node is the right child.
-                computed_hash = hash_pair(*sibling, computed_hash);
+                H::hash_pair(*sibling, computed_hash)
+            };
+            match parent {
+                Ok(parent) => computed_hash = parent,
+                Err(_) => return false,
             }
             index /= 2;
         }
@@ -252,7 +299,7 @@ impl MerkleTree {
     }
 }
 
-impl Default for MerkleTree {
+impl<H: MerkleHasher> Default for MerkleTree<H> {
     fn default() -> Self { Self::new() }
 }
 
@@ -268,10 +315,11 @@ mod tests {
 
     use super::*;
     use crate::merkle::hash_pair;
+    use crate::merkle_circuit::{state_tree_root, StateTreePath};
 
     #[test]
     fn test_new_merkle_tree() {
-        let tree = MerkleTree::new();
+        let tree: MerkleTree = MerkleTree::new();
 
         assert!(tree.leaves.is_empty());
         assert_eq!(tree.root, [0u8; 32]);
@@ -280,7 +328,7 @@ mod tests {
 
     #[test]
     fn test_update_tree_on_delete_even_leaves() -> Result<(), MerkleTreeError> {
-        let mut merkle_tree = MerkleTree::new();
+        let mut merkle_tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
         let leaf3 = [3u8; 32];
@@ -309,7 +357,7 @@ mod tests {
 
     #[test]
     fn test_update_tree_on_delete_odd_leaves() -> Result<(), MerkleTreeError> {
-        let mut merkle_tree = MerkleTree::new();
+        let mut merkle_tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
         let leaf3 = [3u8; 32];
@@ -337,7 +385,7 @@ mod tests {
 
     #[test]
     fn test_get_proof_single_leaf() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf = [1u8; 32];
         // Insert one leaf.
         tree.insert(leaf)?;
@@ -350,7 +398,7 @@ mod tests {
 
     #[test]
     fn test_get_proof_even_leaves() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
 
@@ -373,7 +421,7 @@ mod tests {
 
     #[test]
     fn test_get_proof_odd_leaves() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
         let leaf3 = [3u8; 32];
@@ -403,7 +451,7 @@ mod tests {
 
     #[test]
     fn test_get_proof_non_existent_leaf() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
 
@@ -418,7 +466,7 @@ mod tests {
 
     #[test]
     fn test_verify_proof_single_leaf() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf = [1u8; 32];
 
         // Insert one leaf into the tree.
@@ -438,7 +486,7 @@ mod tests {
 
     #[test]
     fn test_verify_proof_even_leaves() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
 
@@ -467,7 +515,7 @@ mod tests {
 
     #[test]
     fn test_verify_proof_odd_leaves() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
         let leaf3 = [3u8; 32];
@@ -495,7 +543,7 @@ mod tests {
 
     #[test]
     fn test_verify_proof_non_existent_leaf() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
 
@@ -515,7 +563,7 @@ mod tests {
 
     #[test]
     fn test_update_tree_on_insert() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf = [1u8; 32];
 
         tree.insert(leaf)?;
@@ -568,7 +616,7 @@ mod tests {
 
     #[test]
     fn test_merkle_tree_insert() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf = [1u8; 32];
 
         assert_ne!(tree.root, leaf);
@@ -580,7 +628,7 @@ mod tests {
 
     #[test]
     fn test_insert_into_empty_tree() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf = [1u8; 32];
 
         tree.insert(leaf)?;
@@ -592,7 +640,7 @@ mod tests {
 
     #[test]
     fn test_insert_even_number_of_leaves() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
         tree.insert(leaf1)?;
@@ -606,7 +654,7 @@ mod tests {
 
     #[test]
     fn test_insert_odd_number_of_leaves() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
         let leaf3 = [3u8; 32];
@@ -625,7 +673,7 @@ mod tests {
 
     #[test]
     fn test_get_proof() -> Result<(), MerkleTreeError> {
-        let mut tree = MerkleTree::new();
+        let mut tree: MerkleTree = MerkleTree::new();
 
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
@@ -658,7 +706,7 @@ mod tests {
 
     #[test]
     fn test_merkle_tree_basic_operations() -> Result<(), MerkleTreeError> {
-        let mut merkle_tree = MerkleTree::new();
+        let mut merkle_tree: MerkleTree = MerkleTree::new();
 
         let leaf1 = [1u8; 32];
         let leaf2 = [2u8; 32];
@@ -699,4 +747,66 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_poseidon_merkle_tree() -> Result<(), MerkleTreeError> {
+        let mut merkle_tree = PoseidonMerkleTree::new();
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+        for leaf in leaves {
+            merkle_tree.insert(leaf)?;
+        }
+
+        let pair = PoseidonHasher::hash_pair;
+        let expected_root = pair(pair(leaves[0], leaves[1])?, pair(leaves[2], leaves[3])?)?;
+        assert_eq!(merkle_tree.root, expected_root);
+
+        let proof = merkle_tree.get_proof(&leaves[2]).unwrap();
+        assert!(merkle_tree.verify_proof(&leaves[2], &proof, &merkle_tree.root));
+        merkle_tree.update(leaves[2], [9u8; 32])?;
+        assert!(!merkle_tree.verify_proof(&leaves[2], &proof, &merkle_tree.root));
+        // Updates keep the leaf's position, so the old path proves the new leaf
+        assert!(merkle_tree.verify_proof(&[9u8; 32], &proof, &merkle_tree.root));
+        merkle_tree.delete(leaves[0])?;
+        let proof = merkle_tree.get_proof(&[9u8; 32]).unwrap();
+        assert!(merkle_tree.verify_proof(&[9u8; 32], &proof, &merkle_tree.root));
+
+        // Leaves that are not field encodings are rejected
+        assert!(merkle_tree.insert([0xff; 32]).is_err());
+        assert!(merkle_tree.update(leaves[1], [0xff; 32]).is_err());
+        assert!(!merkle_tree.verify_proof(&leaves[1], &[[0xff; 32]], &merkle_tree.root));
+        assert!(matches!(
+            PoseidonHasher::hash_pair(leaves[0], [0xff; 32]),
+            Err(MerkleTreeError::InvalidNode(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_poseidon_merkle_tree_matches_state_tree() -> Result<(), Box<dyn std::error::Error>> {
+        for count in [1, 2, 3, 5] {
+            let leaves: Vec<Bytes32> = (1..=count).map(|i| [i; 32]).collect();
+            let mut merkle_tree = PoseidonMerkleTree::new();
+            for leaf in &leaves {
+                merkle_tree.insert(*leaf)?;
+            }
+            assert_eq!(merkle_tree.root, state_tree_root(&leaves)?);
+
+            // Proofs are the state tree's authentication paths, padding included
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_tree.get_proof(leaf).unwrap();
+                assert_eq!(proof, StateTreePath::new(&leaves, index)?.siblings);
+            }
+
+            // Updating the last leaf pairs it with the padding again
+            let last = *leaves.last().unwrap();
+            let mut updated = leaves.clone();
+            *updated.last_mut().unwrap() = [9u8; 32];
+            merkle_tree.update(last, [9u8; 32])?;
+            assert_eq!(merkle_tree.root, state_tree_root(&updated)?);
+
+            merkle_tree.delete(updated[0])?;
+            assert_eq!(merkle_tree.root, state_tree_root(&updated[1..])?);
+        }
+        Ok(())
+    }
 }
@@ -4,26 +4,40 @@ use std::collections::HashMap;
 use std::fmt;
 
 use anyhow::Result;
+use secp256k1::Keypair;
 use serde_json;
 
-use crate::channel::ChannelState;
+use crate::channel::{ChannelLifecycle, ChannelState};
+use crate::error::ChannelError;
 use crate::global_root_contract::{GlobalRootContract, GlobalRootContractError};
-use crate::merkle::{compute_global_root, compute_global_root_from_sorted};
+use crate::merkle::compute_global_root;
+use crate::merkle_circuit::{
+    state_tree_root, ChannelUpdateCircuit, ChannelUpdateProof, StateTreePath,
+};
 use crate::pedersen_parameters::PedersenParameters;
+use crate::proof_system::{Plonky2ProofSystem, ProofSystem, ProvenChannelUpdate};
 use crate::state::hash_state;
+use crate::state_proof::StateProof;
+use crate::tree::MerkleTree;
 use crate::types::Bytes32;
 
 /// WalletId type alias
 pub type WalletId = Bytes32;
 
 /// Local Verification Layer (Level 2)
-/// Manages channels and generates network proofs.
-pub struct WalletContract {
+/// Manages channels and generates network proofs with the proof system `P`.
+pub struct WalletContract<P = Plonky2ProofSystem> {
     pub wallet_id: Bytes32,
     pub params: PedersenParameters,
     pub channels: HashMap<Bytes32, ChannelState>,
     pub merkle_root: Bytes32,
-    pub global_contract: GlobalRootContract,
+    /// Poseidon state tree root over the same channel hashes, which channel
+    /// updates can prove in-circuit; see `merkle_circuit`.
+    pub state_root: Bytes32,
+    pub global_contract: GlobalRootContract<P>,
+    pub proof_system: P,
+    /// SHA-256 tree behind `merkle_root`, kept for incremental updates
+    merkle_tree: MerkleTree,
 }
 
 /// Represents errors in WalletContract operations.
@@ -39,6 +53,12 @@ pub enum WalletContractError {
     GlobalRootError(#[from] GlobalRootContractError),
     #[error("State proof generation failed: {0}")]
     ProofGenerationError(String),
+    #[error("Channel not found: {0:?}")]
+    ChannelNotFound(Bytes32),
+    #[error("Invalid channel update proof: {0}")]
+    InvalidUpdateProof(String),
+    #[error("Channel error: {0}")]
+    ChannelError(#[from] ChannelError),
 }
 
 impl From<serde_json::Error> for WalletContractError {
@@ -46,11 +66,23 @@ impl From<serde_json::Error> for WalletContractError {
 }
 
 impl WalletContract {
-    /// Creates a new WalletContract.
+    /// Creates a new WalletContract proving with plonky2.
     pub fn new(
         wallet_id: Bytes32,
         params: PedersenParameters,
         global_contract: GlobalRootContract,
+    ) -> Self {
+        Self::with_proof_system(wallet_id, params, global_contract, Plonky2ProofSystem::default())
+    }
+}
+
+impl<P: ProofSystem> WalletContract<P> {
+    /// Creates a new WalletContract proving with `proof_system`.
+    pub fn with_proof_system(
+        wallet_id: Bytes32,
+        params: PedersenParameters,
+        global_contract: GlobalRootContract<P>,
+        proof_system: P,
     ) -> Self {
         // Initialize Merkle root based on initial channels (empty at creation)
         let merkle_root = compute_global_root(&HashMap::new()).unwrap_or_default();
@@ -60,7 +92,10 @@ impl WalletContract {
             params,
             channels: HashMap::new(),
             merkle_root,
+            state_root: [0u8; 32],
             global_contract,
+            proof_system,
+            merkle_tree: MerkleTree::new(),
         }
     }
 
@@ -82,28 +117,159 @@ impl WalletContract {
         Ok(true)
     }
     
-    /// Updates the Merkle root for the wallet, based on channel states.
+    /// Updates the Merkle and state roots for the wallet, based on channel states.
     fn update_merkle_root(&mut self) -> Result<(), WalletContractError> {
-        // Compute channel hashes and collect them into a vector.
-        let mut channel_hashes: Vec<(Bytes32, Bytes32)> = self
+        let sorted_hashes = sorted_channel_hashes(&self.channels)?;
+
+        self.state_root = state_tree_root(&sorted_hashes)
+            .map_err(|e| WalletContractError::MerkleRootError(e.to_string()))?;
+        // Rebuild the global Merkle tree over the sorted channel hashes.
+        self.merkle_tree = MerkleTree::from_leaves(sorted_hashes)
+            .map_err(|e| WalletContractError::MerkleRootError(e.to_string()))?;
+        self.merkle_root = self.merkle_tree.root;
+        Ok(())
+    }
+
+    /// Replaces a channel's state with a verified successor state, returning
+    /// the updated Merkle root.
+    pub fn update_channel(
+        &mut self,
+        channel_id: &Bytes32,
+        next: ChannelState,
+    ) -> Result<Bytes32, WalletContractError> {
+        let current = self
             .channels
-            .iter()
-            .map(|(channel_id, channel_state)| {
-                let channel_hash = hash_state(channel_state)
-                    .map_err(|e| WalletContractError::HashError(e.to_string()))?;
-                Ok::<(Bytes32, Bytes32), WalletContractError>((*channel_id, channel_hash))
-            })
-            .collect::<Result<_, _>>()?;
+            .get(channel_id)
+            .ok_or(WalletContractError::ChannelNotFound(*channel_id))?;
+        next.verify_transition(current, self.proof_system.clock())?;
 
-        // Sort the channel hashes by channel ID to ensure canonical ordering.
-        channel_hashes.sort_by_key(|(channel_id, _)| *channel_id);
+        self.channels.insert(*channel_id, next);
+        self.update_merkle_root()?;
 
-        // Extract the sorted list of hashes.
-        let sorted_hashes: Vec<Bytes32> = channel_hashes.iter().map(|(_, hash)| *hash).collect();
+        Ok(self.merkle_root)
+    }
 
-        // Compute the new global Merkle root using the sorted channel hashes.
-        self.merkle_root = compute_global_root_from_sorted(&sorted_hashes);
-        Ok(())
+    /// Proves that replacing a channel's state with `next` turns the current
+    /// state root into a new one, without checking the transition itself.
+    pub fn prove_channel_update(
+        &self,
+        channel_id: &Bytes32,
+        next: &ChannelState,
+    ) -> Result<ChannelUpdateProof, WalletContractError> {
+        let current = self
+            .channels
+            .get(channel_id)
+            .ok_or(WalletContractError::ChannelNotFound(*channel_id))?;
+        let old_leaf =
+            hash_state(current).map_err(|e| WalletContractError::HashError(e.to_string()))?;
+        let new_leaf =
+            hash_state(next).map_err(|e| WalletContractError::HashError(e.to_string()))?;
+
+        let proof_error =
+            |e: anyhow::Error| WalletContractError::ProofGenerationError(e.to_string());
+        let path = state_tree_path(&self.channels, channel_id)?;
+        ChannelUpdateCircuit::for_depth(path.siblings.len())
+            .and_then(|circuit| circuit.prove(&old_leaf, &new_leaf, &path))
+            .map_err(proof_error)
+    }
+
+    /// Replaces a channel's state with a verified successor state, taking the
+    /// new state root from `proof` instead of recomputing it. Returns the
+    /// updated state root.
+    pub fn update_channel_with_proof(
+        &mut self,
+        channel_id: &Bytes32,
+        next: ChannelState,
+        proof: &ChannelUpdateProof,
+    ) -> Result<Bytes32, WalletContractError> {
+        let current = self
+            .channels
+            .get(channel_id)
+            .ok_or(WalletContractError::ChannelNotFound(*channel_id))?;
+        next.verify_transition(current, self.proof_system.clock())?;
+
+        let hash_error = |e: anyhow::Error| WalletContractError::HashError(e.to_string());
+        if proof.old_root() != self.state_root {
+            return Err(WalletContractError::InvalidUpdateProof("stale state root".to_string()));
+        }
+        let new_leaf = hash_state(&next).map_err(hash_error)?;
+        if proof.old_leaf() != hash_state(current).map_err(hash_error)?
+            || proof.new_leaf() != new_leaf
+        {
+            return Err(WalletContractError::InvalidUpdateProof(
+                "proof is for a different channel update".to_string(),
+            ));
+        }
+        // Channels with equal states share leaves, so the position must match too
+        let index = state_tree_index(&self.channels, channel_id)?;
+        if proof.index() != index {
+            return Err(WalletContractError::InvalidUpdateProof(
+                "proof is for another channel".to_string(),
+            ));
+        }
+        ChannelUpdateCircuit::for_depth(proof.depth)
+            .and_then(|circuit| circuit.verify(proof))
+            .map_err(|e| WalletContractError::InvalidUpdateProof(e.to_string()))?;
+
+        self.merkle_tree
+            .update_at(index, new_leaf)
+            .map_err(|e| WalletContractError::MerkleRootError(e.to_string()))?;
+        self.channels.insert(*channel_id, next);
+        self.merkle_root = self.merkle_tree.root;
+        self.state_root = proof.new_root();
+
+        Ok(self.state_root)
+    }
+
+    /// Applies proven channel updates in order and proves the resulting
+    /// state root transition. Each new state must carry the serialized proof
+    /// of its transition in `ChannelState::proof`, as left by
+    /// `transfer_with_proof` with the wallet's proof system.
+    ///
+    /// Nothing is changed if any update is invalid. The returned proof is what
+    /// `GlobalRootContract::update_wallet` accepts for the new state root.
+    pub fn apply_proven_updates(
+        &mut self,
+        updates: Vec<(Bytes32, ChannelState)>,
+    ) -> Result<StateProof, WalletContractError> {
+        let mut channels = self.channels.clone();
+        let mut proven = Vec::with_capacity(updates.len());
+        for (channel_id, next) in updates {
+            let current = channels
+                .get(&channel_id)
+                .ok_or(WalletContractError::ChannelNotFound(channel_id))?;
+            next.verify_transition(current, self.proof_system.clock())?;
+
+            let proof_bytes = next.proof.as_ref().ok_or_else(|| {
+                WalletContractError::InvalidUpdateProof("missing transition proof".to_string())
+            })?;
+            let transition_proof = self
+                .proof_system
+                .deserialize(proof_bytes)
+                .and_then(|proof| {
+                    self.proof_system.verify_transition(
+                        &proof,
+                        &channel_id,
+                        Some(current),
+                        &next,
+                    )?;
+                    Ok(proof)
+                })
+                .map_err(|e| WalletContractError::InvalidUpdateProof(e.to_string()))?;
+
+            let path = state_tree_path(&channels, &channel_id)?;
+            proven.push(ProvenChannelUpdate { transition_proof, path });
+            channels.insert(channel_id, next);
+        }
+
+        let proof = self
+            .proof_system
+            .prove_wallet_update(&self.state_root, &proven)
+            .map_err(|e| WalletContractError::ProofGenerationError(e.to_string()))?;
+
+        self.channels = channels;
+        self.update_merkle_root()?;
+        Ok(proof)
     }
 
     /// Gets the current merkle root.
@@ -121,9 +287,85 @@ impl WalletContract {
     pub fn has_channel(&self, channel_id: &Bytes32) -> bool {
         self.channels.contains_key(channel_id)
     }
+
+    /// Gets the lifecycle stage of a channel.
+    pub fn get_channel_lifecycle(&self, channel_id: &Bytes32) -> Option<ChannelLifecycle> {
+        self.channels.get(channel_id).map(|channel| channel.lifecycle)
+    }
+
+    /// Lists the IDs of all channels in the given lifecycle stage.
+    pub fn channels_in_lifecycle(&self, lifecycle: ChannelLifecycle) -> Vec<Bytes32> {
+        self.channels
+            .iter()
+            .filter(|(_, channel)| channel.lifecycle == lifecycle)
+            .map(|(channel_id, _)| *channel_id)
+            .collect()
+    }
+
+    /// Moves a channel to the `next` lifecycle stage with a state signed by
+    /// `signer`, returning the updated Merkle root.
+    pub fn advance_channel_lifecycle(
+        &mut self,
+        channel_id: &Bytes32,
+        next: ChannelLifecycle,
+        signer: &Keypair,
+    ) -> Result<Bytes32, WalletContractError> {
+        let channel = self
+            .channels
+            .get_mut(channel_id)
+            .ok_or(WalletContractError::ChannelNotFound(*channel_id))?;
+        channel.advance_lifecycle(next, signer)?;
+
+        self.update_merkle_root()?;
+        Ok(self.merkle_root)
+    }
+}
+
+/// Hashes of all channel states, ordered by channel ID.
+fn sorted_channel_hashes(
+    channels: &HashMap<Bytes32, ChannelState>,
+) -> Result<Vec<Bytes32>, WalletContractError> {
+    // Compute channel hashes and collect them into a vector.
+    let mut channel_hashes: Vec<(Bytes32, Bytes32)> = channels
+        .iter()
+        .map(|(channel_id, channel_state)| {
+            let channel_hash = hash_state(channel_state)
+                .map_err(|e| WalletContractError::HashError(e.to_string()))?;
+            Ok::<(Bytes32, Bytes32), WalletContractError>((*channel_id, channel_hash))
+        })
+        .collect::<Result<_, _>>()?;
+
+    // Sort the channel hashes by channel ID to ensure canonical ordering.
+    channel_hashes.sort_by_key(|(channel_id, _)| *channel_id);
+
+    // Extract the sorted list of hashes.
+    Ok(channel_hashes.iter().map(|(_, hash)| *hash).collect())
+}
+
+/// Position of a channel's leaf in the trees over `channels`.
+fn state_tree_index(
+    channels: &HashMap<Bytes32, ChannelState>,
+    channel_id: &Bytes32,
+) -> Result<usize, WalletContractError> {
+    // Leaves are ordered by channel ID, as in `sorted_channel_hashes`.
+    let mut channel_ids: Vec<Bytes32> = channels.keys().copied().collect();
+    channel_ids.sort();
+    channel_ids
+        .binary_search(channel_id)
+        .map_err(|_| WalletContractError::ChannelNotFound(*channel_id))
+}
+
+/// Path of a channel's leaf in the state tree over `channels`.
+fn state_tree_path(
+    channels: &HashMap<Bytes32, ChannelState>,
+    channel_id: &Bytes32,
+) -> Result<StateTreePath, WalletContractError> {
+    let index = state_tree_index(channels, channel_id)?;
+    StateTreePath::new(&sorted_channel_hashes(channels)?, index)
+        .map_err(|e| WalletContractError::ProofGenerationError(e.to_string()))
 }
 
-impl fmt::Display for WalletContract {
+impl<P: ProofSystem> fmt::Display for WalletContract<P> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Wallet Contract:")?;
         writeln!(f, "  ID: 0x{}", hex::encode(self.wallet_id))?;
@@ -136,6 +378,7 @@ impl fmt::Display for WalletContract {
             writeln!(f, "\n  Channel 0x{}:", hex::encode(channel_id))?;
             writeln!(f, "    Balance: {} units", state.sender_balance)?;
             writeln!(f, "    Nonce: {}", state.nonce)?;
+            writeln!(f, "    Lifecycle: {:?}", state.lifecycle)?;
             if !state.metadata.is_empty() {
                 writeln!(f, "    Metadata: {} bytes", state.metadata.len())?;
             }
@@ -148,6 +391,14 @@ impl fmt::Display for WalletContract {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::channel::ChannelParticipants;
+    use crate::merkle::compute_global_root_from_sorted;
+    use crate::signing::x_only_public_key;
+    use secp256k1::SECP256K1;
+
+    fn participants() -> ChannelParticipants {
+        ChannelParticipants { sender: [8u8; 32], receiver: [9u8; 32] }
+    }
 
     fn setup_test_wallet() -> WalletContract {
         let wallet_id = [1u8; 32];
@@ -198,12 +449,14 @@ mod tests {
         let channel_id = [2u8; 32];
 
         // Register new channel
-        let result = wallet.register_channel(channel_id, ChannelState::new(100, Vec::new()).unwrap())?;
+        let channel = ChannelState::new(100, Vec::new(), participants()).unwrap();
+        let result = wallet.register_channel(channel_id, channel)?;
         assert!(result);
         assert!(wallet.has_channel(&channel_id));
 
         // Try registering same channel again
-        let result = wallet.register_channel(channel_id, ChannelState::new(200, Vec::new()).unwrap())?;
+        let channel = ChannelState::new(200, Vec::new(), participants()).unwrap();
+        let result = wallet.register_channel(channel_id, channel)?;
         assert!(!result);
 
         Ok(())
@@ -216,7 +469,7 @@ mod tests {
 
         // Register multiple channels
         for &id in &channel_ids {
-            wallet.register_channel(id, ChannelState::new(100, Vec::new()).unwrap())?;
+            wallet.register_channel(id, ChannelState::new(100, Vec::new(), participants()).unwrap())?;
         }
 
         let listed_channels = wallet.list_channels();
@@ -228,6 +481,176 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_channel_lifecycle() -> Result<(), WalletContractError> {
+        let mut wallet = setup_test_wallet();
+        let open_id = [1u8; 32];
+        let funding_id = [2u8; 32];
+        let signer = Keypair::from_seckey_slice(SECP256K1, &[1u8; 32]).unwrap();
+        let participants =
+            ChannelParticipants { sender: x_only_public_key(&signer), receiver: [9u8; 32] };
+
+        wallet.register_channel(open_id, ChannelState::new(100, Vec::new(), participants)?)?;
+        let funding = ChannelState::new_funding(100, Vec::new(), participants)?;
+        wallet.register_channel(funding_id, funding)?;
+
+        assert_eq!(wallet.get_channel_lifecycle(&open_id), Some(ChannelLifecycle::Open));
+        assert_eq!(wallet.get_channel_lifecycle(&funding_id), Some(ChannelLifecycle::Funding));
+        assert_eq!(wallet.channels_in_lifecycle(ChannelLifecycle::Funding), vec![funding_id]);
+
+        // The stage is part of the channel hash, so moving it updates the roots
+        let root_before = wallet.get_merkle_root();
+        let root_after =
+            wallet.advance_channel_lifecycle(&funding_id, ChannelLifecycle::Open, &signer)?;
+        assert_ne!(root_after, root_before);
+        assert_eq!(wallet.channels_in_lifecycle(ChannelLifecycle::Open).len(), 2);
+        assert_eq!(wallet.get_channel(&funding_id).unwrap().nonce, 1);
+
+        // Illegal moves and unknown channels are rejected
+        assert!(matches!(
+            wallet.advance_channel_lifecycle(&open_id, ChannelLifecycle::Funding, &signer),
+            Err(WalletContractError::ChannelError(ChannelError::InvalidLifecycleTransition { .. }))
+        ));
+        assert!(matches!(
+            wallet.advance_channel_lifecycle(&[9u8; 32], ChannelLifecycle::Closing, &signer),
+            Err(WalletContractError::ChannelNotFound(_))
+        ));
+        assert_eq!(wallet.get_channel_lifecycle(&[9u8; 32]), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_channel_with_deposit() -> Result<(), WalletContractError> {
+        let mut wallet = setup_test_wallet();
+        let channel_id = [1u8; 32];
+        let signer = Keypair::from_seckey_slice(SECP256K1, &[1u8; 32]).unwrap();
+        let participants = ChannelParticipants {
+            sender: x_only_public_key(&signer),
+            receiver: [9u8; 32],
+        };
+        let channel = ChannelState::new(100, Vec::new(), participants)?;
+        wallet.register_channel(channel_id, channel.clone())?;
+        let root_before = wallet.get_merkle_root();
+
+        let mut topped_up = channel.deposit(50, [5u8; 32], wallet.proof_system.clock())?;
+        topped_up.sign(&signer)?;
+        let root_after = wallet.update_channel(&channel_id, topped_up)?;
+
+        assert_ne!(root_after, root_before);
+        assert_eq!(wallet.get_channel(&channel_id).unwrap().sender_balance, 150);
+
+        // Unsigned or unknown updates are rejected
+        let current = wallet.get_channel(&channel_id).unwrap();
+        let unsigned = current.deposit(10, [5u8; 32], wallet.proof_system.clock())?;
+        assert!(matches!(
+            wallet.update_channel(&channel_id, unsigned.clone()),
+            Err(WalletContractError::ChannelError(ChannelError::MissingSignature))
+        ));
+        assert!(matches!(
+            wallet.update_channel(&[7u8; 32], unsigned),
+            Err(WalletContractError::ChannelNotFound(_))
+        ));
+        assert_eq!(wallet.get_merkle_root(), root_after);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_channel_with_proof() -> Result<(), WalletContractError> {
+        let mut wallet = setup_test_wallet();
+        let channel_id = [2u8; 32];
+        let signer = Keypair::from_seckey_slice(SECP256K1, &[1u8; 32]).unwrap();
+        let participants = ChannelParticipants {
+            sender: x_only_public_key(&signer),
+            receiver: [9u8; 32],
+        };
+        let channel = ChannelState::new(100, Vec::new(), participants)?;
+        let small = ChannelState::new(10, Vec::new(), participants)?;
+        for id in [[1u8; 32], [3u8; 32]] {
+            wallet.register_channel(id, small.clone())?;
+        }
+        wallet.register_channel(channel_id, channel.clone())?;
+
+        let mut topped_up = channel.deposit(50, [5u8; 32], wallet.proof_system.clock())?;
+        topped_up.sign(&signer)?;
+        let proof = wallet.prove_channel_update(&channel_id, &topped_up)?;
+
+        // The proven root matches recomputing the tree
+        let mut recomputed = setup_test_wallet();
+        recomputed.channels = wallet.channels.clone();
+        recomputed.update_merkle_root()?;
+        let state_root = wallet.update_channel_with_proof(&channel_id, topped_up.clone(), &proof)?;
+        recomputed.update_channel(&channel_id, topped_up)?;
+        assert_eq!(state_root, recomputed.state_root);
+        assert_eq!(wallet.get_merkle_root(), recomputed.get_merkle_root());
+
+        // A proof against an outdated root is rejected
+        let current = wallet.get_channel(&channel_id).unwrap();
+        let mut next = current.deposit(5, [5u8; 32], wallet.proof_system.clock())?;
+        next.sign(&signer)?;
+        assert!(matches!(
+            wallet.update_channel_with_proof(&channel_id, next, &proof),
+            Err(WalletContractError::InvalidUpdateProof(_))
+        ));
+        assert_eq!(wallet.state_root, state_root);
+
+        // Equal states share a leaf, but a proof only updates its own position
+        let mut small_next = small.deposit(5, [5u8; 32], wallet.proof_system.clock())?;
+        small_next.sign(&signer)?;
+        let proof = wallet.prove_channel_update(&[1u8; 32], &small_next)?;
+        assert_eq!(proof.index(), 0);
+        assert!(matches!(
+            wallet.update_channel_with_proof(&[3u8; 32], small_next.clone(), &proof),
+            Err(WalletContractError::InvalidUpdateProof(_))
+        ));
+        wallet.update_channel_with_proof(&[1u8; 32], small_next, &proof)?;
+        recomputed.channels = wallet.channels.clone();
+        recomputed.update_merkle_root()?;
+        assert_eq!(wallet.get_merkle_root(), recomputed.get_merkle_root());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_proven_updates() -> Result<(), Box<dyn std::error::Error>> {
+        let mut wallet = setup_test_wallet();
+        let system = Plonky2ProofSystem::default();
+        let signer = Keypair::from_seckey_slice(SECP256K1, &[1u8; 32])?;
+        let participants =
+            ChannelParticipants { sender: x_only_public_key(&signer), receiver: [9u8; 32] };
+        for id in [[1u8; 32], [2u8; 32], [3u8; 32]] {
+            wallet.register_channel(id, ChannelState::new(100, Vec::new(), participants)?)?;
+        }
+        let old_root = wallet.state_root;
+
+        // Two transfers on one channel and one on another
+        let mut first = wallet.get_channel(&[2u8; 32]).unwrap().clone();
+        first.transfer_with_proof(&system, [2u8; 32], 10, &signer)?;
+        let mut second = first.clone();
+        second.transfer_with_proof(&system, [2u8; 32], 15, &signer)?;
+        let mut other = wallet.get_channel(&[3u8; 32]).unwrap().clone();
+        other.transfer_with_proof(&system, [3u8; 32], 40, &signer)?;
+
+        let updates = vec![([2u8; 32], first), ([3u8; 32], other), ([2u8; 32], second)];
+        let proof = wallet.apply_proven_updates(updates)?;
+        system.verify_wallet_update(&proof, &old_root, &wallet.state_root)?;
+        assert_eq!(system.wallet_proof(&proof)?.updates(), 3);
+        assert_eq!(wallet.get_channel(&[2u8; 32]).unwrap().sender_balance, 75);
+
+        // States without a transition proof are rejected and change nothing
+        let current = wallet.get_channel(&[1u8; 32]).unwrap();
+        let mut unproven = current.transfer(5, wallet.proof_system.clock())?;
+        unproven.sign(&signer)?;
+        assert!(matches!(
+            wallet.apply_proven_updates(vec![([1u8; 32], unproven)]),
+            Err(WalletContractError::InvalidUpdateProof(_))
+        ));
+        assert_eq!(wallet.state_root, proof.public_inputs[1]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_update_merkle_root() -> Result<(), WalletContractError> {
         let mut wallet = setup_test_wallet();
@@ -240,7 +663,7 @@ mod tests {
 
         // Register multiple channels in the wallet.
         for &id in &channel_ids {
-            wallet.register_channel(id, ChannelState::new(100, Vec::new()).unwrap())?;
+            wallet.register_channel(id, ChannelState::new(100, Vec::new(), participants()).unwrap())?;
         }
 
         // Update the Merkle root for the wallet.
@@ -0,0 +1,326 @@
+//! Wallet-level transition proofs
+//!
+//! A wallet commits to its channels with the Poseidon state tree of
+//! `merkle_circuit`. `WalletTransitionCircuit` proves that the wallet's state
+//! root moved from an old to a new value by applying proven channel
+//! transitions: each update slot recursively verifies a
+//! `StateTransitionCircuit` proof and replaces the channel's old state hash by
+//! its new one in the tree, starting from the root left by the previous slot.
+//!
+//! The circuit has a fixed number of update slots and supports state trees up
+//! to `WALLET_TREE_MAX_DEPTH`. Unused slots are flagged inactive; they verify
+//! a padding transition proof but leave the root unchanged.
+//!
+//! Public inputs are `[old wallet root (4), new wallet root (4), updates,
+//! stamp (5)]`, where the stamp is the `stamp_elements` encoding of the time
+//! and block height the proof was generated at. The circuit does not
+//! constrain the stamp; exposing it binds the `StateProof` envelope's stamp,
+//! which freshness is checked against, to the proof.
+//!
+//! Slots only bind the old and new state hashes of a transition proof
+//! (its public inputs 0..8) to the replaced leaf. The channel id limbs
+//! (public inputs 12..20) are not bound, because leaves are bare
+//! `hash_state` values with no channel key. A wallet proof therefore shows
+//! that each leaf moved by a proven transition, but not in which channel.
+//! Provers must check that themselves, as
+//! `WalletContract::apply_proven_updates` does with
+//! `ProofSystem::verify_transition` before proving.
+
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use anyhow::{anyhow, Context, Result};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::hash::hash_types::HashOutTarget;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2_field::types::Field;
+
+use crate::channel::{ChannelParticipants, ChannelState};
+use crate::codec::{bytes_to_hash_out, elements_to_bytes, field_to_u64, u64_to_limbs};
+use crate::merkle_circuit::{MerkleUpdateTargets, StateTreePath};
+use crate::state_transition::StateTransitionCircuit;
+use crate::types::Bytes32;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Deepest wallet state tree supported by `WalletTransitionCircuit`.
+pub const WALLET_TREE_MAX_DEPTH: usize = 16;
+
+/// Maximum number of channel updates proven by one wallet proof.
+pub const WALLET_UPDATE_SLOTS: usize = 4;
+
+/// Index of the update count in a wallet proof's public inputs.
+const UPDATES_INDEX: usize = 8;
+
+/// Index of the first stamp element in a wallet proof's public inputs.
+const STAMP_INDEX: usize = 9;
+
+/// Number of field elements in the encoding of a proof's stamp.
+pub const STAMP_ELEMENTS: usize = 5;
+
+/// Encodes a generation time and optional block height as
+/// `[timestamp limbs (2), has block height, block height limbs (2)]`.
+pub fn stamp_elements(timestamp: u64, block_height: Option<u64>) -> [F; STAMP_ELEMENTS] {
+    let [time_low, time_high] = u64_to_limbs(timestamp);
+    let [height_low, height_high] = u64_to_limbs(block_height.unwrap_or(0));
+    let has_height = F::from_bool(block_height.is_some());
+    [time_low, time_high, has_height, height_low, height_high]
+}
+
+/// A proven channel transition and the path of the channel's leaf in the
+/// wallet state tree it is applied to.
+#[derive(Debug, Clone)]
+pub struct WalletUpdateStep {
+    /// `StateTransitionCircuit` proof of the channel transition
+    pub transition_proof: ProofWithPublicInputs<F, C, D>,
+    /// Path of the channel's leaf in the tree before the update
+    pub path: StateTreePath,
+}
+
+/// Proof that a wallet's state root moved from `old_root` to `new_root` by
+/// applying `updates` proven channel transitions.
+#[derive(Debug, Clone)]
+pub struct WalletTransitionProof {
+    /// The wallet proof
+    pub proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl WalletTransitionProof {
+    /// Wallet state root before the updates.
+    pub fn old_root(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[0..4]) }
+
+    /// Wallet state root after the updates.
+    pub fn new_root(&self) -> Bytes32 { elements_to_bytes(&self.proof.public_inputs[4..8]) }
+
+    /// Number of channel updates covered by the proof.
+    pub fn updates(&self) -> u64 { field_to_u64(self.proof.public_inputs[UPDATES_INDEX]) }
+
+    /// Checks that the proof was stamped with `timestamp` and `block_height`.
+    pub fn is_stamped(&self, timestamp: u64, block_height: Option<u64>) -> bool {
+        self.proof.public_inputs.get(STAMP_INDEX..STAMP_INDEX + STAMP_ELEMENTS)
+            == Some(&stamp_elements(timestamp, block_height)[..])
+    }
+}
+
+/// Targets of one update slot.
+struct UpdateSlotTargets {
+    active: BoolTarget,
+    transition_proof: ProofWithPublicInputsTarget<D>,
+    update: MerkleUpdateTargets,
+}
+
+/// Circuit proving a batch of channel updates to a wallet state tree.
+pub struct WalletTransitionCircuit {
+    circuit_data: CircuitData<F, C, D>,
+    old_root_target: HashOutTarget,
+    stamp_targets: Vec<Target>,
+    slots: Vec<UpdateSlotTargets>,
+    /// Valid transition proof verified by inactive slots
+    padding_proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl WalletTransitionCircuit {
+    /// Builds the circuit for proofs of `transition_circuit`.
+    pub fn new(transition_circuit: &StateTransitionCircuit) -> Result<Self> {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+
+        let inner = transition_circuit.circuit_data();
+        let inner_verifier_target = builder.constant_verifier_data(&inner.verifier_only);
+
+        let old_root_target = builder.add_virtual_hash();
+        let mut root = old_root_target;
+        let mut updates = builder.zero();
+        let mut slots = Vec::with_capacity(WALLET_UPDATE_SLOTS);
+        for _ in 0..WALLET_UPDATE_SLOTS {
+            let active = builder.add_virtual_bool_target_safe();
+            let transition_proof = builder.add_virtual_proof_with_pis(&inner.common);
+            builder.verify_proof::<C>(&transition_proof, &inner_verifier_target, &inner.common);
+
+            // The replaced leaves are the state hashes the transition proves.
+            let update = MerkleUpdateTargets::add_up_to(&mut builder, WALLET_TREE_MAX_DEPTH);
+            for i in 0..4 {
+                builder.connect(update.old_leaf.elements[i], transition_proof.public_inputs[i]);
+                builder.connect(update.new_leaf.elements[i], transition_proof.public_inputs[4 + i]);
+            }
+
+            // An active slot must start from the current root and moves it on.
+            let mut next_root = root;
+            for i in 0..4 {
+                let gap = builder.sub(update.old_root.elements[i], root.elements[i]);
+                let checked_gap = builder.mul(active.target, gap);
+                builder.assert_zero(checked_gap);
+                next_root.elements[i] =
+                    builder.select(active, update.new_root.elements[i], root.elements[i]);
+            }
+            root = next_root;
+            updates = builder.add(updates, active.target);
+
+            slots.push(UpdateSlotTargets { active, transition_proof, update });
+        }
+
+        builder.register_public_inputs(&old_root_target.elements);
+        builder.register_public_inputs(&root.elements);
+        builder.register_public_input(updates);
+        let stamp_targets = builder.add_virtual_targets(STAMP_ELEMENTS);
+        builder.register_public_inputs(&stamp_targets);
+
+        let circuit_data = builder.build::<C>();
+
+        // Any valid transfer proof can pad inactive slots.
+        let participants = ChannelParticipants { sender: [1u8; 32], receiver: [2u8; 32] };
+        let padding_state = ChannelState::new(1, Vec::new(), participants)?;
+        let padding_proof = transition_circuit
+            .prove_transfer(&[0u8; 32], &padding_state, 1)
+            .context("Failed to generate padding proof")?;
+
+        Ok(Self { circuit_data, old_root_target, stamp_targets, slots, padding_proof })
+    }
+
+    /// Gets a circuit for `StateTransitionCircuit::shared` proofs, built on
+    /// first use.
+    pub fn shared() -> Result<&'static Self> {
+        static CIRCUIT: OnceLock<WalletTransitionCircuit> = OnceLock::new();
+        static BUILD: Mutex<()> = Mutex::new(());
+        if let Some(circuit) = CIRCUIT.get() {
+            return Ok(circuit);
+        }
+        // Concurrent first callers wait for one build instead of each
+        // building their own. A failed build is retried by the next caller.
+        let _build = BUILD.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(circuit) = CIRCUIT.get() {
+            return Ok(circuit);
+        }
+        let circuit = Self::new(StateTransitionCircuit::shared())?;
+        Ok(CIRCUIT.get_or_init(|| circuit))
+    }
+
+    /// Gets the underlying circuit data, e.g. to verify its proofs recursively.
+    pub fn circuit_data(&self) -> &CircuitData<F, C, D> { &self.circuit_data }
+
+    /// Proves applying `steps`, in order, to the wallet state tree with root
+    /// `old_root`, stamping the proof with `timestamp` and `block_height`.
+    pub fn prove(
+        &self,
+        old_root: &Bytes32,
+        steps: &[WalletUpdateStep],
+        timestamp: u64,
+        block_height: Option<u64>,
+    ) -> Result<WalletTransitionProof> {
+        if steps.len() > WALLET_UPDATE_SLOTS {
+            return Err(anyhow!(
+                "{} updates exceed the {WALLET_UPDATE_SLOTS} slots of a wallet proof",
+                steps.len()
+            ));
+        }
+
+        // Check the chain natively rather than failing inside the prover.
+        let mut root = *old_root;
+        for (i, step) in steps.iter().enumerate() {
+            let (old_leaf, new_leaf) =
+                StateTransitionCircuit::public_state_hashes(&step.transition_proof)?;
+            if step.path.compute_root(&old_leaf)? != root {
+                return Err(anyhow!("Update {i} does not apply to the current wallet root"));
+            }
+            root = step.path.compute_root(&new_leaf)?;
+        }
+
+        let mut pw = PartialWitness::new();
+        pw.set_hash_target(self.old_root_target, bytes_to_hash_out(old_root)?)
+            .context("Failed to set old wallet root")?;
+        let stamp = stamp_elements(timestamp, block_height);
+        for (&target, &value) in self.stamp_targets.iter().zip(&stamp) {
+            pw.set_target(target, value).context("Failed to set stamp")?;
+        }
+        let padding_path = StateTreePath { index: 0, siblings: Vec::new() };
+        for (i, slot) in self.slots.iter().enumerate() {
+            let (proof, path) = match steps.get(i) {
+                Some(step) => (&step.transition_proof, &step.path),
+                None => (&self.padding_proof, &padding_path),
+            };
+            let (old_leaf, new_leaf) = StateTransitionCircuit::public_state_hashes(proof)?;
+            pw.set_bool_target(slot.active, i < steps.len())
+                .context("Failed to set slot flag")?;
+            pw.set_proof_with_pis_target(&slot.transition_proof, proof)
+                .context("Failed to set transition proof")?;
+            slot.update.set_witness(&mut pw, &old_leaf, &new_leaf, path)?;
+        }
+
+        let proof = self.circuit_data.prove(pw).context("Wallet proof generation failed")?;
+        Ok(WalletTransitionProof { proof })
+    }
+
+    /// Verifies a wallet proof.
+    pub fn verify(&self, proof: &WalletTransitionProof) -> Result<()> {
+        self.circuit_data
+            .verify(proof.proof.clone())
+            .context("Wallet proof verification failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_circuit::state_tree_root;
+    use crate::state::hash_state;
+    use crate::state_transition::{apply_transition, TransitionData, TransitionKind};
+
+    #[test]
+    fn test_wallet_transition_proof() -> Result<()> {
+        let circuit = WalletTransitionCircuit::shared()?;
+        let transition_circuit = StateTransitionCircuit::shared();
+        let participants = ChannelParticipants { sender: [2u8; 32], receiver: [3u8; 32] };
+        let channels = [
+            ChannelState::new(100, Vec::new(), participants)?,
+            ChannelState::new(50, b"second".to_vec(), participants)?,
+            ChannelState::new(70, b"third".to_vec(), participants)?,
+        ];
+        let mut leaves = channels.iter().map(hash_state).collect::<Result<Vec<_>>>()?;
+        let old_root = state_tree_root(&leaves)?;
+
+        // Any kind of transition can be applied, here a transfer and a claim
+        // on the same channel, a deposit on another and a batch on a third
+        let mut states = channels.to_vec();
+        let mut steps = Vec::new();
+        for (index, data) in [
+            (0, TransitionData::transfer(10)),
+            (2, TransitionData::deposit(5, [5u8; 32])),
+            (0, TransitionData::new(TransitionKind::Claim, 4)),
+            (1, TransitionData::transfer_batch(3, 9)),
+        ] {
+            let path = StateTreePath::new(&leaves, index)?;
+            let channel_id = [index as u8; 32];
+            let transition_proof =
+                transition_circuit.generate_zkp(&channel_id, &states[index], &data)?;
+            states[index] = apply_transition(&states[index], &data)?;
+            leaves[index] = hash_state(&states[index])?;
+            steps.push(WalletUpdateStep { transition_proof, path });
+        }
+
+        let proof = circuit.prove(&old_root, &steps, 1_000, Some(800_000))?;
+        circuit.verify(&proof)?;
+        assert_eq!(proof.old_root(), old_root);
+        assert_eq!(proof.new_root(), state_tree_root(&leaves)?);
+        assert_eq!(proof.updates(), 4);
+
+        // The proof commits to its stamp
+        assert!(proof.is_stamped(1_000, Some(800_000)));
+        assert!(!proof.is_stamped(1_001, Some(800_000)));
+        assert!(!proof.is_stamped(1_000, None));
+        assert!(!proof.is_stamped(1_000, Some(0)));
+        let mut restamped = proof.clone();
+        restamped.proof.public_inputs[STAMP_INDEX] = F::from_canonical_u32(1_001);
+        assert!(circuit.verify(&restamped).is_err());
+
+        // Updates must apply to the root they are proven against
+        steps.swap(0, 2);
+        assert!(circuit.prove(&old_root, &steps, 1_000, None).is_err());
+        Ok(())
+    }
+}
@@ -1,8 +1,10 @@
 use anyhow::{anyhow, Ok, Result};
 use midas::*;
-use overpass_poc::channel::ChannelState;
+use overpass_poc::channel::{
+    ChannelLifecycle, ChannelParticipants, ChannelState, ChannelTransition,
+};
 use overpass_poc::state::hash_state;
-use overpass_poc::state_transition::apply_transition;
+use overpass_poc::state_transition::{apply_transition, TransitionData};
 use overpass_poc::tree::MerkleTree;
 use serde_json::json;
 
@@ -18,6 +20,14 @@ async fn test_midas() -> Result<()> {
         nonce: 0,
         metadata: vec![],
         proof: None,
+        commitment: None,
+        range_proof: None,
+        participants: ChannelParticipants { sender: [2u8; 32], receiver: [3u8; 32] },
+        signature: None,
+        lifecycle: ChannelLifecycle::Open,
+        expiry: None,
+        transition: ChannelTransition::Genesis,
+        memo: None,
     };
     let channel_id = [1u8; 32];
 
@@ -27,14 +37,10 @@ async fn test_midas() -> Result<()> {
     println!("Initial state created: {:?}", initial_state);
 
     println!("\n=== Generating Transition Data ===");
-    let mut transition_data = [0u8; 32];
-    transition_data[0..4].copy_from_slice(&(3u32).to_le_bytes());
+    let transition_data = TransitionData::transfer(3);
 
     println!("Transition data: {:?}", transition_data);
-    println!(
-        "Transfer amount: {}",
-        u32::from_le_bytes(transition_data[0..4].try_into().unwrap())
-    );
+    println!("Transfer amount: {}", transition_data.amount);
 
     println!("\n=== Applying Transition ===");
     let next_state = apply_transition(&initial_state, &transition_data)?;
@@ -48,7 +54,7 @@ async fn test_midas() -> Result<()> {
     println!("Next state hash bytes: {:?}", next_state_bytes);
 
     println!("\n=== Updating Merkle Tree ===");
-    let mut smt = MerkleTree::new();
+    let mut smt: MerkleTree = MerkleTree::new();
 
     smt.insert(initial_state_bytes)?;
     println!("Initial state added to Merkle tree");